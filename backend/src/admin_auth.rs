@@ -0,0 +1,217 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::jobs::now_millis;
+
+pub const KEY_HEADER: &str = "x-admin-key";
+pub const TIMESTAMP_HEADER: &str = "x-admin-timestamp";
+pub const NONCE_HEADER: &str = "x-admin-nonce";
+pub const SIGNATURE_HEADER: &str = "x-admin-signature";
+
+/// How far a signed request's timestamp may be from the server clock.
+pub const MAX_SKEW_SECS: i64 = 300;
+
+/// The admin headers of a request.
+#[derive(Debug, Clone, Default)]
+pub struct AdminCredentials {
+    pub key: Option<String>,
+    pub timestamp: Option<String>,
+    pub nonce: Option<String>,
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthError {
+    NotConfigured,
+    Missing,
+    InvalidKey,
+    InvalidSignature,
+    MalformedTimestamp,
+    Expired,
+    Replayed,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::NotConfigured => write!(f, "Admin authentication is not configured"),
+            AuthError::Missing => write!(f, "Missing admin credentials"),
+            AuthError::InvalidKey => write!(f, "Invalid admin key"),
+            AuthError::InvalidSignature => write!(f, "Invalid admin signature"),
+            AuthError::MalformedTimestamp => write!(f, "Admin request timestamp must be unix seconds"),
+            AuthError::Expired => write!(f, "Admin request timestamp is outside the allowed window"),
+            AuthError::Replayed => write!(f, "Admin request nonce was already used"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Checks admin requests, either by a shared API key or by an HMAC-SHA256
+/// signature over the request with a timestamp and single-use nonce.
+/// Admin routes refuse every request when neither is configured.
+pub struct AdminAuth {
+    api_key: Option<String>,
+    hmac_secret: Option<Vec<u8>>,
+    /// Nonces of accepted signed requests and their timestamps
+    seen_nonces: Mutex<HashMap<String, i64>>,
+}
+
+impl AdminAuth {
+    pub fn new(api_key: Option<String>, hmac_secret: Option<String>) -> Self {
+        AdminAuth {
+            api_key: api_key.filter(|key| !key.is_empty()),
+            hmac_secret: hmac_secret.filter(|secret| !secret.is_empty()).map(String::into_bytes),
+            seen_nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reads `ADMIN_API_KEY` and `ADMIN_HMAC_SECRET`.
+    pub fn from_env() -> Self {
+        AdminAuth::new(env::var("ADMIN_API_KEY").ok(), env::var("ADMIN_HMAC_SECRET").ok())
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.api_key.is_some() || self.hmac_secret.is_some()
+    }
+
+    /// Accepts a request to `method` `path_and_query` with `body`, at unix
+    /// time `now`.
+    pub fn verify(&self, credentials: &AdminCredentials, method: &str, path_and_query: &str, body: &[u8], now: i64) -> Result<(), AuthError> {
+        if !self.is_configured() {
+            return Err(AuthError::NotConfigured);
+        }
+
+        if let (Some(expected), Some(key)) = (&self.api_key, &credentials.key) {
+            return if constant_time_eq(expected.as_bytes(), key.as_bytes()) { Ok(()) } else { Err(AuthError::InvalidKey) };
+        }
+
+        let (Some(secret), Some(timestamp), Some(nonce), Some(signature)) =
+            (&self.hmac_secret, &credentials.timestamp, &credentials.nonce, &credentials.signature)
+        else {
+            return Err(AuthError::Missing);
+        };
+        let timestamp: i64 = timestamp.parse().map_err(|_| AuthError::MalformedTimestamp)?;
+        if (now - timestamp).abs() > MAX_SKEW_SECS {
+            return Err(AuthError::Expired);
+        }
+        let signature = hex::decode(signature).map_err(|_| AuthError::InvalidSignature)?;
+        signing_mac(secret, method, path_and_query, timestamp, nonce, body)
+            .verify_slice(&signature)
+            .map_err(|_| AuthError::InvalidSignature)?;
+
+        let mut seen = self.seen_nonces.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Older nonces would fail the timestamp check anyway
+        seen.retain(|_, seen_at| (now - *seen_at).abs() <= MAX_SKEW_SECS);
+        if seen.insert(nonce.clone(), timestamp).is_some() {
+            return Err(AuthError::Replayed);
+        }
+        Ok(())
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn signing_mac(secret: &[u8], method: &str, path_and_query: &str, timestamp: i64, nonce: &str, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    let message = format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method.to_uppercase(),
+        path_and_query,
+        timestamp,
+        nonce,
+        hex::encode(Sha256::digest(body)),
+    );
+    mac.update(message.as_bytes());
+    mac
+}
+
+/// Hex HMAC-SHA256 signature of a request.
+pub fn sign(secret: &str, method: &str, path_and_query: &str, timestamp: i64, nonce: &str, body: &[u8]) -> String {
+    hex::encode(signing_mac(secret.as_bytes(), method, path_and_query, timestamp, nonce, body).finalize().into_bytes())
+}
+
+/// Credentials the worker calls admin routes with.
+pub struct AdminClient {
+    api_key: Option<String>,
+    hmac_secret: Option<String>,
+}
+
+impl AdminClient {
+    pub fn new(api_key: Option<String>, hmac_secret: Option<String>) -> Self {
+        AdminClient {
+            api_key: api_key.filter(|key| !key.is_empty()),
+            hmac_secret: hmac_secret.filter(|secret| !secret.is_empty()),
+        }
+    }
+
+    /// Reads `ADMIN_API_KEY` and `ADMIN_HMAC_SECRET`.
+    pub fn from_env() -> Self {
+        AdminClient::new(env::var("ADMIN_API_KEY").ok(), env::var("ADMIN_HMAC_SECRET").ok())
+    }
+
+    /// Admin headers for a request, signed if there is an HMAC secret and
+    /// carrying the API key otherwise.
+    pub fn headers(&self, method: &str, path_and_query: &str, body: &[u8]) -> Result<Vec<(&'static str, String)>, AuthError> {
+        if let Some(secret) = &self.hmac_secret {
+            let timestamp = now_millis() / 1000;
+            let nonce = mongodb::bson::oid::ObjectId::new().to_hex();
+            let signature = sign(secret, method, path_and_query, timestamp, &nonce, body);
+            return Ok(vec![
+                (TIMESTAMP_HEADER, timestamp.to_string()),
+                (NONCE_HEADER, nonce),
+                (SIGNATURE_HEADER, signature),
+            ]);
+        }
+        match &self.api_key {
+            Some(key) => Ok(vec![(KEY_HEADER, key.clone())]),
+            None => Err(AuthError::NotConfigured),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(secret: &str, path: &str, timestamp: i64, nonce: &str) -> AdminCredentials {
+        AdminCredentials {
+            key: None,
+            timestamp: Some(timestamp.to_string()),
+            nonce: Some(nonce.to_string()),
+            signature: Some(sign(secret, "POST", path, timestamp, nonce, b"")),
+        }
+    }
+
+    #[test]
+    fn test_admin_auth() {
+        let now = 1_700_000_000;
+        let path = "/clear-cache-and-fetch?target=all";
+        let auth = AdminAuth::new(Some("key".to_string()), Some("secret".to_string()));
+
+        let key = AdminCredentials { key: Some("key".to_string()), ..Default::default() };
+        assert_eq!(auth.verify(&key, "POST", path, b"", now), Ok(()));
+        let wrong_key = AdminCredentials { key: Some("nope".to_string()), ..Default::default() };
+        assert_eq!(auth.verify(&wrong_key, "POST", path, b"", now), Err(AuthError::InvalidKey));
+
+        let request = signed("secret", path, now - 10, "nonce-1");
+        assert_eq!(auth.verify(&request, "POST", path, b"", now), Ok(()));
+        assert_eq!(auth.verify(&request, "POST", path, b"", now), Err(AuthError::Replayed));
+        assert_eq!(auth.verify(&signed("secret", path, now, "nonce-2"), "POST", "/refresh-results", b"", now), Err(AuthError::InvalidSignature));
+        assert_eq!(auth.verify(&signed("other", path, now, "nonce-3"), "POST", path, b"", now), Err(AuthError::InvalidSignature));
+        assert_eq!(auth.verify(&signed("secret", path, now - 600, "nonce-4"), "POST", path, b"", now), Err(AuthError::Expired));
+        let mut malformed = signed("secret", path, now, "nonce-5");
+        malformed.timestamp = Some("yesterday".to_string());
+        assert_eq!(auth.verify(&malformed, "POST", path, b"", now), Err(AuthError::MalformedTimestamp));
+        assert_eq!(auth.verify(&AdminCredentials::default(), "POST", path, b"", now), Err(AuthError::Missing));
+
+        let unconfigured = AdminAuth::new(None, Some(String::new()));
+        assert_eq!(unconfigured.verify(&key, "POST", path, b"", now), Err(AuthError::NotConfigured));
+    }
+}
@@ -0,0 +1,221 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+use crate::admin_auth::AuthError;
+use crate::grid::BuffSet;
+use crate::share_code::ShareCodeError;
+use crate::weapons::Weapon;
+
+/// Stable machine readable error codes, part of the API contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    UnknownWeapon,
+    InvalidWeakPointChance,
+    ConflictingBuffs,
+    InvalidShareCode,
+    InvalidRequest,
+    NotFound,
+    Unauthorized,
+    Unavailable,
+    Internal,
+}
+
+/// JSON body of every error response.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Weapon names close to an unknown one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    UnknownWeapon { name: String, suggestions: Vec<String> },
+    InvalidWeakPointChance(f64),
+    ConflictingBuffs,
+    InvalidShareCode(ShareCodeError),
+    InvalidRequest(String),
+    NotFound(String),
+    Unauthorized(AuthError),
+    /// A backing service this route needs isn't configured
+    Unavailable(String),
+    /// Logged in full, only `what` failed is returned
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::UnknownWeapon { .. } => ErrorCode::UnknownWeapon,
+            ApiError::InvalidWeakPointChance(_) => ErrorCode::InvalidWeakPointChance,
+            ApiError::ConflictingBuffs => ErrorCode::ConflictingBuffs,
+            ApiError::InvalidShareCode(_) => ErrorCode::InvalidShareCode,
+            ApiError::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Unauthorized(_) => ErrorCode::Unauthorized,
+            ApiError::Unavailable(_) => ErrorCode::Unavailable,
+            ApiError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Logs `error` and hides it from the client.
+    pub fn internal(what: &str, error: impl fmt::Display) -> Self {
+        log::error!("{}: {}", what, error);
+        ApiError::Internal(what.to_string())
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            suggestions: match self {
+                ApiError::UnknownWeapon { suggestions, .. } => suggestions.clone(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::UnknownWeapon { name, .. } => write!(f, "Unknown weapon {}", name),
+            ApiError::InvalidWeakPointChance(chance) => write!(f, "Weak point hit chance {} must be between 0 and 1", chance),
+            ApiError::ConflictingBuffs => write!(f, "Valby and Enzo buffs can't be combined"),
+            ApiError::InvalidShareCode(e) => write!(f, "{}", e),
+            ApiError::InvalidRequest(message) => write!(f, "{}", message),
+            ApiError::NotFound(message) => write!(f, "{}", message),
+            ApiError::Unauthorized(e) => write!(f, "{}", e),
+            ApiError::Unavailable(message) => write!(f, "{}", message),
+            ApiError::Internal(what) => write!(f, "{}", what),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<ShareCodeError> for ApiError {
+    fn from(e: ShareCodeError) -> Self {
+        ApiError::InvalidShareCode(e)
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::MalformedTimestamp => ApiError::InvalidRequest(e.to_string()),
+            e => ApiError::Unauthorized(e),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::UnknownWeapon { .. } | ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::InvalidWeakPointChance(_)
+            | ApiError::ConflictingBuffs
+            | ApiError::InvalidShareCode(_)
+            | ApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.body())
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Up to three weapon names closest to `name`, ignoring case and punctuation.
+pub fn suggest_weapons(name: &str) -> Vec<String> {
+    let wanted = normalize(name);
+    let mut scored: Vec<(usize, String)> = Weapon::all()
+        .iter()
+        .map(|weapon| weapon.to_string())
+        .filter_map(|candidate| {
+            let normalized = normalize(&candidate);
+            let distance = if !wanted.is_empty() && normalized.contains(&wanted) { 0 } else { edit_distance(&wanted, &normalized) };
+            (distance <= (normalized.len() / 3).max(2)).then_some((distance, candidate))
+        })
+        .collect();
+    scored.sort();
+    scored.into_iter().take(3).map(|(_, candidate)| candidate).collect()
+}
+
+pub fn parse_weapon(name: &str) -> Result<Weapon, ApiError> {
+    name.parse().map_err(|_| ApiError::UnknownWeapon { name: name.to_string(), suggestions: suggest_weapons(name) })
+}
+
+pub fn validate_weak_point_chance(chance: f64) -> Result<f64, ApiError> {
+    if chance.is_finite() && (0.0..=1.0).contains(&chance) {
+        Ok(chance)
+    } else {
+        Err(ApiError::InvalidWeakPointChance(chance))
+    }
+}
+
+/// Valby and Enzo are both descendants, only one can be played at a time.
+pub fn validate_buffs(buffs: BuffSet) -> Result<BuffSet, ApiError> {
+    if buffs.valby && buffs.enzo {
+        Err(ApiError::ConflictingBuffs)
+    } else {
+        Ok(buffs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_errors() {
+        assert_eq!(parse_weapon("Python"), Ok(Weapon::Python));
+        assert_eq!(suggest_weapons("python"), vec!["Python"]);
+        assert_eq!(suggest_weapons("Thundercag"), vec!["Thundercage"]);
+        assert_eq!(suggest_weapons("Piercing"), vec!["PiercingLight"]);
+        assert!(suggest_weapons("xyzzy").is_empty());
+
+        let unknown = parse_weapon("Pyton").unwrap_err();
+        assert_eq!(unknown.status_code(), StatusCode::NOT_FOUND);
+        let body = serde_json::to_value(unknown.body()).unwrap();
+        assert_eq!(body["code"], "unknown_weapon");
+        assert_eq!(body["suggestions"][0], "Python");
+
+        for chance in [-0.1, 1.5, f64::NAN, f64::INFINITY] {
+            assert_eq!(validate_weak_point_chance(chance).unwrap_err().code(), ErrorCode::InvalidWeakPointChance);
+        }
+        assert_eq!(validate_weak_point_chance(0.0), Ok(0.0));
+        assert_eq!(validate_weak_point_chance(1.0), Ok(1.0));
+
+        assert_eq!(validate_buffs(BuffSet { valby: true, enzo: true }), Err(ApiError::ConflictingBuffs));
+        let body = serde_json::to_value(ApiError::ConflictingBuffs.body()).unwrap();
+        assert_eq!(body["code"], "conflicting_buffs");
+        assert!(body.get("suggestions").is_none());
+    }
+}
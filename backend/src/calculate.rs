@@ -1,24 +1,64 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use std::sync::atomic::Ordering;
 use std::{collections::HashMap, sync::atomic::AtomicU64};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use itertools::Itertools;
 use rayon::prelude::*;
+use strum_macros::{Display, EnumString};
+use log::debug;
 
-use crate::weapons::{Module, ModuleBonusType, ModuleType, Roll, RollType, WeaponBaseStats, WeaponType};
+use crate::components::{ComponentSubstat, ExternalComponent, REACTOR_SUBSTAT_SLOTS, Reactor};
+use crate::progression::WeaponProgress;
+use crate::weapons::{Module, ModuleBonusType, ModuleType, Roll, RollType, SlotCounts, Weapon, WeaponBaseStats, WeaponType};
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ModuleCombinations {
     pub combinations: Vec<Vec<usize>>,
 }
 
+/// What the weapon is shooting at. Colossus damage only applies to colossi.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString, Display, ToSchema)]
+pub enum EnemyProfile {
+    #[default]
+    Colossus,
+    Standard,
+}
+
+impl EnemyProfile {
+    pub fn is_default(&self) -> bool {
+        *self == EnemyProfile::default()
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct OptimizationConfig {
     pub valby: bool,
     pub enzo: bool,
     pub gley: bool,
     pub gley_duration: f64,
+    #[serde(default)]
+    pub constraints: BuildConstraints,
+    /// Caps the weapon's own slot counts, e.g. for partially unlocked weapons.
+    #[serde(default)]
+    pub module_slots: Option<usize>,
+    #[serde(default)]
+    pub roll_slots: Option<usize>,
+    /// Equipped reactor. Ignored when its weapon type doesn't match.
+    #[serde(default)]
+    pub reactor: Option<Reactor>,
+    #[serde(default)]
+    pub external_components: Vec<ExternalComponent>,
+    /// Search `REACTOR_SUBSTAT_SLOTS` of the reactor's substats together
+    /// with the weapon rolls instead of equipping all of them.
+    #[serde(default)]
+    pub optimize_reactor: bool,
+    /// Level and unlocks of the weapon, max level and fully unlocked if absent.
+    #[serde(default)]
+    pub progress: Option<WeaponProgress>,
+    #[serde(default, skip_serializing_if = "EnemyProfile::is_default")]
+    pub enemy: EnemyProfile,
 }
 
 impl Default for OptimizationConfig {
@@ -28,15 +68,171 @@ impl Default for OptimizationConfig {
             enzo: false,
             gley: false,
             gley_duration: 9.0,
+            constraints: BuildConstraints::default(),
+            module_slots: None,
+            roll_slots: None,
+            reactor: None,
+            external_components: Vec::new(),
+            optimize_reactor: false,
+            progress: None,
+            enemy: EnemyProfile::default(),
         }
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+impl OptimizationConfig {
+    pub fn slot_counts(&self, weapon: Weapon) -> SlotCounts {
+        self.slots_within(weapon.slot_counts())
+    }
+
+    /// Slots of a weapon whose full layout is `slots`, after the level and
+    /// the overrides.
+    pub fn slots_within(&self, slots: SlotCounts) -> SlotCounts {
+        let slots = match self.progress {
+            Some(progress) => progress.unlocked_slots(slots),
+            None => slots,
+        };
+        SlotCounts {
+            modules: self.module_slots.map_or(slots.modules, |modules| modules.min(slots.modules)),
+            rolls: self.roll_slots.map_or(slots.rolls, |rolls| rolls.min(slots.rolls)),
+        }
+    }
+
+    /// Base stats of the weapon at the configured level.
+    pub fn base_stats(&self, weapon: Weapon) -> WeaponBaseStats {
+        match self.progress {
+            Some(progress) => progress.base_stats(weapon),
+            None => WeaponBaseStats::get(weapon),
+        }
+    }
+
+    /// Reactor substats the search may pick from, and the index combinations
+    /// of them to evaluate.
+    fn reactor_space(&self, weapon_type: WeaponType) -> (Vec<ComponentSubstat>, Vec<Vec<usize>>) {
+        let options = self.reactor.as_ref().map_or(&[][..], |reactor| reactor.substats_for(weapon_type)).to_vec();
+        let combinations = if self.optimize_reactor {
+            (0..options.len()).combinations(REACTOR_SUBSTAT_SLOTS.min(options.len())).collect()
+        } else {
+            vec![(0..options.len()).collect()]
+        };
+        (options, combinations)
+    }
+
+    /// External component substats followed by the given reactor substats.
+    pub fn component_substats(&self, reactor_substats: &[ComponentSubstat]) -> Vec<ComponentSubstat> {
+        self.external_components
+            .iter()
+            .flat_map(|component| component.substats.iter().copied())
+            .chain(reactor_substats.iter().copied())
+            .collect()
+    }
+}
+
+/// Rules a build has to satisfy on top of the game's own slotting rules.
+/// Modules are referenced by name so a spec stays valid across catalogs.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+#[serde(default)]
+pub struct BuildConstraints {
+    pub required_modules: Vec<String>,
+    pub excluded_modules: Vec<String>,
+    pub required_rolls: Vec<RollType>,
+    pub min_magazine: Option<f64>,
+    pub max_reload_time: Option<f64>,
+    /// Overrides the per-`ModuleType` cap. Typed categories default to 1,
+    /// `ModuleType::None` is uncapped.
+    pub max_per_category: HashMap<ModuleType, usize>,
+}
+
+impl Default for BuildConstraints {
+    fn default() -> Self {
+        BuildConstraints {
+            // Action and Reaction is the first module of every catalog and
+            // has always been pinned, which keeps the search at C(n-1, slots-1).
+            required_modules: vec!["Action and Reaction".to_string()],
+            excluded_modules: Vec::new(),
+            required_rolls: Vec::new(),
+            min_magazine: None,
+            max_reload_time: None,
+            max_per_category: HashMap::new(),
+        }
+    }
+}
+
+impl BuildConstraints {
+    /// No constraints beyond the one-module-per-type rule.
+    #[allow(dead_code)]
+    pub fn unconstrained() -> Self {
+        BuildConstraints {
+            required_modules: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether every build these constraints allow is also allowed by the
+    /// defaults, so the cached default combinations can be filtered instead
+    /// of regenerated.
+    pub fn narrows_default(&self) -> bool {
+        let default = BuildConstraints::default();
+        default.required_modules.iter().all(|name| self.required_modules.contains(name) && !self.excluded_modules.contains(name))
+            && self.max_per_category.iter().all(|(&module_type, &cap)| cap <= default.category_cap(module_type))
+    }
+
+    fn category_cap(&self, module_type: ModuleType) -> usize {
+        match self.max_per_category.get(&module_type) {
+            Some(&cap) => cap,
+            None if module_type == ModuleType::None => usize::MAX,
+            None => 1,
+        }
+    }
+
+    fn is_excluded(&self, module: &Module) -> bool {
+        self.excluded_modules.contains(&module.name)
+    }
+
+    fn is_required(&self, module: &Module) -> bool {
+        self.required_modules.contains(&module.name)
+    }
+
+    /// Builds a predicate over module index combinations of `modules`.
+    /// Lookups are resolved once so the check stays cheap inside the search.
+    pub fn module_filter<'a>(&'a self, modules: &[Module]) -> impl Fn(&[usize]) -> bool + Sync + 'a {
+        let excluded: Vec<bool> = modules.iter().map(|m| self.is_excluded(m)).collect();
+        let required: Vec<usize> = (0..modules.len()).filter(|&i| self.is_required(&modules[i])).collect();
+        let module_types: Vec<ModuleType> = modules.iter().map(|m| m.module_type).collect();
+
+        move |combo: &[usize]| {
+            combo.iter().all(|&i| !excluded[i])
+                && required.iter().all(|r| combo.contains(r))
+                && is_valid_combination(combo, &module_types, self)
+        }
+    }
+
+    pub fn allows_rolls(&self, combo: &[usize], rolls: &[Roll]) -> bool {
+        self.required_rolls.iter().all(|required| combo.iter().any(|&i| rolls[i].roll_type == *required))
+    }
+
+    pub fn allows_stats(&self, stats: &WeaponBaseStats) -> bool {
+        if let Some(min_magazine) = self.min_magazine {
+            if stats.magazine_capacity < min_magazine {
+                return false;
+            }
+        }
+        if let Some(max_reload_time) = self.max_reload_time {
+            if stats.reload_time > max_reload_time {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
 pub struct OptimizationResult {
     pub max_dps: f64,
     pub best_rolls: Vec<Roll>,
     pub best_modules: Vec<(Module, f64)>,  // (Module, importance)
+    #[serde(default)]
+    pub best_reactor: Vec<ComponentSubstat>,
 }
 
 fn calculate_dps(stats: &WeaponBaseStats, weak_point_hit_chance: f64, gley: bool) -> f64 {
@@ -83,21 +279,34 @@ fn calculate_dpbullet(stats: &WeaponBaseStats, weak_point_hit_chance: f64) -> f6
     base_damage + ele_damage
 }
 
-pub fn generate_module_combinations(modules: &[Module]) -> Vec<Vec<usize>> {
-    let total_modules = modules.len();
-    if total_modules <= 10 {
-        return vec![modules.iter().enumerate().map(|(i, _)| i).collect()];
+pub fn generate_module_combinations(modules: &[Module], constraints: &BuildConstraints, module_slots: usize) -> Vec<Vec<usize>> {
+    let pinned: Vec<usize> = (0..modules.len())
+        .filter(|&i| constraints.is_required(&modules[i]) && !constraints.is_excluded(&modules[i]))
+        .collect();
+    let candidates: Vec<usize> = (0..modules.len())
+        .filter(|&i| !constraints.is_required(&modules[i]) && !constraints.is_excluded(&modules[i]))
+        .collect();
+    let module_types: Vec<ModuleType> = modules.iter().map(|m| m.module_type).collect();
+
+    if pinned.len() + candidates.len() <= module_slots {
+        let mut combination = pinned;
+        combination.extend(candidates);
+        combination.sort_unstable();
+        return vec![combination];
+    }
+    if pinned.len() > module_slots {
+        return Vec::new();
     }
 
     let mut valid_combinations = Vec::new();
-    let module_types: Vec<ModuleType> = modules.iter().map(|m| m.module_type).collect();
 
-    // Generate combinations of 9 modules (first module is always included)
-    for combination in (1..total_modules).combinations(9) {
-        let mut full_combination = vec![0]; // Always include the first module
+    // Pinned modules are always included, the remaining slots are searched
+    for combination in candidates.into_iter().combinations(module_slots - pinned.len()) {
+        let mut full_combination = pinned.clone();
         full_combination.extend(combination);
-        
-        if is_valid_combination(&full_combination, &module_types) {
+        full_combination.sort_unstable();
+
+        if is_valid_combination(&full_combination, &module_types, constraints) {
             valid_combinations.push(full_combination);
         }
     }
@@ -105,23 +314,44 @@ pub fn generate_module_combinations(modules: &[Module]) -> Vec<Vec<usize>> {
     valid_combinations
 }
 
-fn is_valid_combination(combo: &[usize], module_types: &[ModuleType]) -> bool {
+/// Module combinations to search for `constraints` and `module_slots`.
+/// `cached` holds the combinations of the default constraints and slot
+/// count; anything wider than those gets its own combinations.
+pub fn combinations_for(cached: &ModuleCombinations, modules: &[Module], constraints: &BuildConstraints, module_slots: usize) -> Vec<Vec<usize>> {
+    if module_slots == SlotCounts::default().modules && constraints.narrows_default() {
+        cached.combinations.clone()
+    } else {
+        generate_module_combinations(modules, constraints, module_slots)
+    }
+}
+
+pub fn is_valid_combination(combo: &[usize], module_types: &[ModuleType], constraints: &BuildConstraints) -> bool {
     let mut used_types = 0u64;
+    let mut type_counts: HashMap<ModuleType, usize> = HashMap::new();
 
     for &index in combo {
         let module_type = module_types[index];
-        if module_type != ModuleType::None {
-            let type_bit = 1u64 << (module_type as u64);
-            if used_types & type_bit != 0 {
-                return false; // Duplicate non-None type found
+        if constraints.max_per_category.is_empty() {
+            if module_type != ModuleType::None {
+                let type_bit = 1u64 << (module_type as u64);
+                if used_types & type_bit != 0 {
+                    return false; // Duplicate non-None type found
+                }
+                used_types |= type_bit;
+            }
+        } else {
+            let count = type_counts.entry(module_type).or_insert(0);
+            *count += 1;
+            if *count > constraints.category_cap(module_type) {
+                return false;
             }
-            used_types |= type_bit;
         }
     }
 
     true
 }
 
+#[allow(clippy::too_many_arguments)]
 fn apply_rolls_and_modules(
     base_stats: &WeaponBaseStats,
     roll_indices: &[usize],
@@ -130,6 +360,7 @@ fn apply_rolls_and_modules(
     available_modules: &[Module],
     valby: bool,
     enzo: bool,
+    components: &[ComponentSubstat],
 ) -> WeaponBaseStats {
     let mut new_stats = *base_stats;
     let mut bonus_multipliers: HashMap<ModuleBonusType, f64> = HashMap::new();
@@ -173,27 +404,32 @@ fn apply_rolls_and_modules(
         }
     }
     
-    // Accumulate module bonuses
-    for &i in module_indices {
-        let module = &available_modules[i];
-        for effect in &module.effects {
-            match effect.effect_type {
-                ModuleBonusType::EleEnhancement => {
-                    new_stats.ele_enhancement = effect.value;
-                },
-                ModuleBonusType::EleMult => {
-                    new_stats.ele_multiplier += effect.value;
-                },
-                ModuleBonusType::FiringFiesta => {
-                    new_stats.firing_fiesta = effect.value;
-                },
-                _ => {
-                    *bonus_multipliers.entry(effect.effect_type).or_insert(0.0) += effect.value;
-                }
+    // Accumulate module bonuses, then reactor and external components
+    let module_effects = module_indices
+        .iter()
+        .flat_map(|&i| &available_modules[i].effects)
+        .map(|effect| (effect.effect_type, effect.value));
+    let component_effects = components.iter().map(|substat| (substat.bonus_type, substat.value));
+    for (effect_type, value) in module_effects.chain(component_effects) {
+        match effect_type {
+            ModuleBonusType::EleEnhancement => {
+                new_stats.ele_enhancement = value;
+            },
+            ModuleBonusType::EleMult => {
+                new_stats.ele_multiplier += value;
+            },
+            ModuleBonusType::FiringFiesta => {
+                new_stats.firing_fiesta = value;
+            },
+            ModuleBonusType::ColossusDamage => {
+                new_stats.colossus_atk += value;
+            },
+            _ => {
+                *bonus_multipliers.entry(effect_type).or_insert(0.0) += value;
             }
         }
     }
-    
+
     // Apply accumulated bonuses
     if valby {
         new_stats.crit_chance += 0.2;
@@ -226,27 +462,79 @@ fn apply_rolls_and_modules(
     new_stats
 }
 
+/// Told how large an optimization's search space is, then how much of it
+/// has been evaluated as the search goes. Called from rayon threads.
+pub trait OptimizationProgress: Sync {
+    fn started(&self, total_combinations: u64);
+    fn evaluated(&self, combinations: u64);
+}
+
+pub struct NoProgress;
+
+impl OptimizationProgress for NoProgress {
+    fn started(&self, _total_combinations: u64) {}
+    fn evaluated(&self, _combinations: u64) {}
+}
+
 pub async fn optimize_weapon(
     base_stats: WeaponBaseStats,
+    slots: SlotCounts,
     available_rolls: Vec<Roll>,
     available_modules: Vec<Module>,
     module_combinations: Vec<Vec<usize>>,
     weak_point_hit_chance: f64,
     config: OptimizationConfig,
 ) -> OptimizationResult {
-    println!("Starting optimization for {:?}", base_stats.weapon_type);
+    optimize_weapon_with_progress(
+        base_stats,
+        slots,
+        available_rolls,
+        available_modules,
+        module_combinations,
+        weak_point_hit_chance,
+        config,
+        &NoProgress,
+    ).await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn optimize_weapon_with_progress(
+    base_stats: WeaponBaseStats,
+    slots: SlotCounts,
+    available_rolls: Vec<Roll>,
+    available_modules: Vec<Module>,
+    module_combinations: Vec<Vec<usize>>,
+    weak_point_hit_chance: f64,
+    config: OptimizationConfig,
+    progress: &dyn OptimizationProgress,
+) -> OptimizationResult {
+    debug!("Starting optimization for {:?}", base_stats.weapon_type);
     let start_time = std::time::Instant::now();
     let best_dps = Arc::new(AtomicU64::new(0));
-    let best_combo = Arc::new(Mutex::new((Vec::new(), Vec::new())));
-    let roll_combinations: Vec<Vec<usize>> = (0..available_rolls.len()).combinations(4).collect();
-    let total_combinations = roll_combinations.len() * module_combinations.len();
-    println!("Total combinations to evaluate: {}", total_combinations);
+    let best_combo = Arc::new(Mutex::new((Vec::new(), Vec::new(), Vec::new())));
+    let (roll_combinations, module_combinations) = search_space(
+        slots,
+        &available_rolls,
+        &available_modules,
+        module_combinations,
+        &config.constraints,
+    );
+    let (reactor_substats, reactor_combinations) = config.reactor_space(base_stats.weapon_type);
+    let outer_combinations: Vec<(&Vec<usize>, &Vec<usize>)> = roll_combinations
+        .iter()
+        .cartesian_product(reactor_combinations.iter())
+        .collect();
+    let total_combinations = outer_combinations.len() * module_combinations.len();
+    debug!("Total combinations to evaluate: {}", total_combinations);
+    progress.started(total_combinations as u64);
     let base_stats = Arc::new(base_stats);
     let available_rolls = Arc::new(available_rolls);
     let available_modules = Arc::new(available_modules);
     let config = Arc::new(config);
 
-    roll_combinations.par_iter().for_each(|roll_combo| {
+    outer_combinations.par_iter().for_each(|&(roll_combo, reactor_combo)| {
+        let reactor: Vec<ComponentSubstat> = reactor_combo.iter().map(|&i| reactor_substats[i]).collect();
+        let components = config.component_substats(&reactor);
         for module_indices in &module_combinations {
             let final_stats = apply_rolls_and_modules(
                 &base_stats,
@@ -256,56 +544,196 @@ pub async fn optimize_weapon(
                 &available_modules,
                 config.valby,
                 config.enzo,
+                &components,
             );
+            if !config.constraints.allows_stats(&final_stats) {
+                continue;
+            }
 
-            let final_dps = if base_stats.weapon_type == WeaponType::SniperRifle {
-                calculate_dpbullet(&final_stats, weak_point_hit_chance)
-            } else {
-                calculate_dps(&final_stats, weak_point_hit_chance, config.gley)
-            };
+            let final_dps = evaluate_dps(&base_stats, &final_stats, weak_point_hit_chance, config.gley, config.enemy);
 
             let current_best = best_dps.load(Ordering::Relaxed);
             if (final_dps * 1e6) as u64 > current_best {
                 best_dps.store((final_dps * 1e6) as u64, Ordering::Relaxed);
                 let mut best = best_combo.blocking_lock();
                 *best = (
-                    roll_combo.iter().map(|&i| available_rolls[i].clone()).collect(),
-                    module_indices.iter().map(|&i| available_modules[i].clone()).collect()
+                    roll_combo.iter().map(|&i| available_rolls[i]).collect(),
+                    module_indices.iter().map(|&i| available_modules[i].clone()).collect(),
+                    reactor.clone(),
                 );
             }
         }
+        progress.evaluated(module_combinations.len() as u64);
     });
 
-    let (best_rolls, best_modules) = best_combo.lock().await.clone();
+    let (best_rolls, best_modules, best_reactor) = best_combo.lock().await.clone();
     let final_dps = (best_dps.load(Ordering::Relaxed) as f64) / 1e6;
-    println!("Optimization complete. Best DPS: {}, Total time: {:?}", final_dps, start_time.elapsed());
+    debug!("Optimization complete. Best DPS: {}, Total time: {:?}", final_dps, start_time.elapsed());
+
+    build_result(&base_stats, final_dps, best_rolls, best_modules, best_reactor, weak_point_hit_chance, &config)
+}
+
+/// Roll and module index combinations left after applying the slot counts
+/// and the config's constraints. `module_combinations` has to cover the
+/// constraints, see `combinations_for`.
+fn search_space(
+    slots: SlotCounts,
+    available_rolls: &[Roll],
+    available_modules: &[Module],
+    module_combinations: Vec<Vec<usize>>,
+    constraints: &BuildConstraints,
+) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+    let module_combinations: Vec<Vec<usize>> = {
+        let allows_modules = constraints.module_filter(available_modules);
+        module_combinations
+            .into_par_iter()
+            .filter(|combo| combo.len() <= slots.modules && allows_modules(combo))
+            .collect()
+    };
+    let roll_combinations: Vec<Vec<usize>> = (0..available_rolls.len())
+        .combinations(slots.rolls.min(available_rolls.len()))
+        .filter(|combo| constraints.allows_rolls(combo, available_rolls))
+        .collect();
+    (roll_combinations, module_combinations)
+}
 
+fn build_result(
+    base_stats: &WeaponBaseStats,
+    max_dps: f64,
+    best_rolls: Vec<Roll>,
+    best_modules: Vec<Module>,
+    best_reactor: Vec<ComponentSubstat>,
+    weak_point_hit_chance: f64,
+    config: &OptimizationConfig,
+) -> OptimizationResult {
+    let components = config.component_substats(&best_reactor);
     let module_importance: Vec<f64> = best_modules.iter().map(|module| {
         let mut reduced_modules = best_modules.clone();
         reduced_modules.retain(|m| m.name != module.name);
-        let reduced_dps = calculate_dps_with_combination(&base_stats, &best_rolls, &reduced_modules, weak_point_hit_chance, config.valby, config.enzo);
-        final_dps - reduced_dps
+        let reduced_dps = calculate_dps_with_combination(base_stats, &best_rolls, &reduced_modules, weak_point_hit_chance, config, &components);
+        max_dps - reduced_dps
     }).collect();
 
     OptimizationResult {
-        max_dps: final_dps,
-        best_rolls: best_rolls,
-        best_modules: best_modules.into_iter().zip(module_importance.into_iter()).collect(),
+        max_dps,
+        best_rolls,
+        best_modules: best_modules.into_iter().zip(module_importance).collect(),
+        best_reactor,
     }
 }
 
-fn calculate_dps_with_combination(base_stats: &WeaponBaseStats, rolls: &[Roll], modules: &[Module], weak_point_hit_chance: f64, valby: bool, enzo: bool) -> f64 {
-    let roll_indices: Vec<usize> = (0..rolls.len()).collect();
-    let module_indices: Vec<usize> = (0..modules.len()).collect();
-    let final_stats = apply_rolls_and_modules(base_stats, &roll_indices, &module_indices, rolls, modules, valby, enzo);
-    
+/// Optimizes one weapon for every chance in `weak_point_hit_chances` in a
+/// single pass. A build's DPS is linear in the weak point hit chance, so each
+/// combination only needs to be evaluated at 0 and 1.
+pub fn optimize_weapon_sweep(
+    base_stats: WeaponBaseStats,
+    slots: SlotCounts,
+    available_rolls: Vec<Roll>,
+    available_modules: Vec<Module>,
+    module_combinations: Vec<Vec<usize>>,
+    weak_point_hit_chances: &[f64],
+    config: OptimizationConfig,
+) -> Vec<OptimizationResult> {
+    debug!("Starting weak point sweep for {:?}", base_stats.weapon_type);
+    let start_time = std::time::Instant::now();
+    let (roll_combinations, module_combinations) = search_space(
+        slots,
+        &available_rolls,
+        &available_modules,
+        module_combinations,
+        &config.constraints,
+    );
+    let (reactor_substats, reactor_combinations) = config.reactor_space(base_stats.weapon_type);
+    let outer_combinations: Vec<(&Vec<usize>, &Vec<usize>)> = roll_combinations
+        .iter()
+        .cartesian_product(reactor_combinations.iter())
+        .collect();
+    debug!("Total combinations to evaluate: {}", outer_combinations.len() * module_combinations.len());
+
+    // (dps, outer combination index, module combination index) per chance
+    let empty = vec![(f64::MIN, usize::MAX, usize::MAX); weak_point_hit_chances.len()];
+    let best = outer_combinations
+        .par_iter()
+        .enumerate()
+        .fold(|| empty.clone(), |mut best, (outer_index, &(roll_combo, reactor_combo))| {
+            let reactor: Vec<ComponentSubstat> = reactor_combo.iter().map(|&i| reactor_substats[i]).collect();
+            let components = config.component_substats(&reactor);
+            for (module_index, module_indices) in module_combinations.iter().enumerate() {
+                let final_stats = apply_rolls_and_modules(
+                    &base_stats,
+                    roll_combo,
+                    module_indices,
+                    &available_rolls,
+                    &available_modules,
+                    config.valby,
+                    config.enzo,
+                    &components,
+                );
+                if !config.constraints.allows_stats(&final_stats) {
+                    continue;
+                }
+
+                let dps_at_zero = evaluate_dps(&base_stats, &final_stats, 0.0, config.gley, config.enemy);
+                let dps_at_one = evaluate_dps(&base_stats, &final_stats, 1.0, config.gley, config.enemy);
+                for (slot, &chance) in weak_point_hit_chances.iter().enumerate() {
+                    let dps = dps_at_zero + chance * (dps_at_one - dps_at_zero);
+                    if dps > best[slot].0 {
+                        best[slot] = (dps, outer_index, module_index);
+                    }
+                }
+            }
+            best
+        })
+        .reduce(|| empty.clone(), |a, b| {
+            a.into_iter().zip(b).map(|(x, y)| if y.0 > x.0 { y } else { x }).collect()
+        });
+    debug!("Sweep complete. Total time: {:?}", start_time.elapsed());
+
+    best.into_iter()
+        .zip(weak_point_hit_chances)
+        .map(|((dps, outer_index, module_index), &chance)| {
+            if outer_index == usize::MAX {
+                return OptimizationResult { max_dps: 0.0, best_rolls: Vec::new(), best_modules: Vec::new(), best_reactor: Vec::new() };
+            }
+            let (roll_combo, reactor_combo) = outer_combinations[outer_index];
+            let rolls = roll_combo.iter().map(|&i| available_rolls[i]).collect();
+            let modules = module_combinations[module_index].iter().map(|&i| available_modules[i].clone()).collect();
+            let reactor = reactor_combo.iter().map(|&i| reactor_substats[i]).collect();
+            build_result(&base_stats, dps, rolls, modules, reactor, chance, &config)
+        })
+        .collect()
+}
+
+fn evaluate_dps(base_stats: &WeaponBaseStats, final_stats: &WeaponBaseStats, weak_point_hit_chance: f64, gley: bool, enemy: EnemyProfile) -> f64 {
+    let mut stats = *final_stats;
+    if enemy != EnemyProfile::Colossus {
+        stats.colossus_atk = 0.0;
+    }
     if base_stats.weapon_type == WeaponType::SniperRifle {
-        calculate_dpbullet(&final_stats, weak_point_hit_chance)
+        calculate_dpbullet(&stats, weak_point_hit_chance)
     } else {
-        calculate_dps(&final_stats, weak_point_hit_chance, false)
+        calculate_dps(&stats, weak_point_hit_chance, gley)
     }
 }
 
+/// DPS of a fixed build at the given weak point hit chance.
+pub fn evaluate_build(base_stats: &WeaponBaseStats, rolls: &[Roll], modules: &[Module], reactor: &[ComponentSubstat], weak_point_hit_chance: f64, config: &OptimizationConfig) -> f64 {
+    let roll_indices: Vec<usize> = (0..rolls.len()).collect();
+    let module_indices: Vec<usize> = (0..modules.len()).collect();
+    let components = config.component_substats(reactor);
+    let final_stats = apply_rolls_and_modules(base_stats, &roll_indices, &module_indices, rolls, modules, config.valby, config.enzo, &components);
+
+    evaluate_dps(base_stats, &final_stats, weak_point_hit_chance, config.gley, config.enemy)
+}
+
+fn calculate_dps_with_combination(base_stats: &WeaponBaseStats, rolls: &[Roll], modules: &[Module], weak_point_hit_chance: f64, config: &OptimizationConfig, components: &[ComponentSubstat]) -> f64 {
+    let roll_indices: Vec<usize> = (0..rolls.len()).collect();
+    let module_indices: Vec<usize> = (0..modules.len()).collect();
+    let final_stats = apply_rolls_and_modules(base_stats, &roll_indices, &module_indices, rolls, modules, config.valby, config.enzo, components);
+    
+    evaluate_dps(base_stats, &final_stats, weak_point_hit_chance, config.gley, config.enemy)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -317,6 +745,7 @@ mod tests {
             max_dps: 1000.0,
             best_rolls: vec![/* ... */],
             best_modules: vec![/* ... */],
+            best_reactor: vec![],
         };
 
         let bson = bson::to_bson(&result).unwrap();
@@ -329,4 +758,158 @@ mod tests {
         let deserialized: OptimizationResult = bson::from_bson(bson).unwrap();
         assert_eq!(result.max_dps, deserialized.max_dps);
     }
-}
\ No newline at end of file
+
+    fn test_modules() -> Vec<Module> {
+        let modules = crate::modules::general_rounds_modules::get_modules();
+        [0, 1, 2, 3, 4, 5, 6, 7, 12, 13, 16, 17, 20].iter().map(|&i| modules[i].clone()).collect()
+    }
+
+    #[test]
+    fn test_default_constraints_pin_first_module() {
+        let modules = test_modules();
+        let combinations = generate_module_combinations(&modules, &BuildConstraints::default(), 10);
+
+        assert!(!combinations.is_empty());
+        assert!(combinations.iter().all(|combo| combo.len() == 10 && combo.contains(&0)));
+    }
+
+    #[test]
+    fn test_reduced_module_slots() {
+        let modules = test_modules();
+        let combinations = generate_module_combinations(&modules, &BuildConstraints::default(), 6);
+
+        assert!(!combinations.is_empty());
+        assert!(combinations.iter().all(|combo| combo.len() == 6 && combo.contains(&0)));
+    }
+
+    #[test]
+    fn test_excluded_and_required_modules() {
+        let modules = test_modules();
+        let constraints = BuildConstraints {
+            required_modules: vec![modules[3].name.clone()],
+            excluded_modules: vec![modules[2].name.clone()],
+            ..BuildConstraints::unconstrained()
+        };
+        let combinations = generate_module_combinations(&modules, &constraints, 10);
+        let allows_modules = constraints.module_filter(&modules);
+
+        assert!(!combinations.is_empty());
+        assert!(combinations.iter().all(|combo| combo.contains(&3) && !combo.contains(&2)));
+        assert!(combinations.iter().all(|combo| allows_modules(combo)));
+    }
+
+    #[tokio::test]
+    async fn test_widened_constraints_regenerate_combinations() {
+        let base_stats = WeaponBaseStats::get(Weapon::Thundercage);
+        let slots = SlotCounts::default();
+        let rolls = crate::weapons::get_available_rolls(base_stats.weapon_type);
+        let modules = test_modules();
+        let cached = ModuleCombinations {
+            combinations: generate_module_combinations(&modules, &BuildConstraints::default(), slots.modules),
+        };
+        let optimize = |constraints: BuildConstraints| {
+            let combinations = combinations_for(&cached, &modules, &constraints, slots.modules);
+            let config = OptimizationConfig { constraints, ..Default::default() };
+            optimize_weapon(base_stats, slots, rolls.clone(), modules.clone(), combinations, 0.5, config)
+        };
+
+        assert!(BuildConstraints::default().narrows_default());
+        let excluded = BuildConstraints { excluded_modules: vec![modules[1].name.clone()], ..Default::default() };
+        assert!(excluded.narrows_default());
+
+        let pinned = optimize(BuildConstraints::default()).await;
+        assert!(pinned.best_modules.iter().any(|(module, _)| module.name == modules[0].name));
+
+        let without_first = BuildConstraints { excluded_modules: vec![modules[0].name.clone()], ..BuildConstraints::unconstrained() };
+        assert!(!without_first.narrows_default());
+        let unpinned = optimize(without_first).await;
+        assert!(unpinned.max_dps > 0.0);
+        assert!(unpinned.best_modules.iter().all(|(module, _)| module.name != modules[0].name));
+
+        let doubled = BuildConstraints { max_per_category: HashMap::from([(modules[1].module_type, 2)]), ..Default::default() };
+        assert_eq!(doubled.narrows_default(), modules[1].module_type == ModuleType::None);
+        assert!(optimize(doubled).await.max_dps >= pinned.max_dps);
+    }
+
+    #[test]
+    fn test_required_rolls() {
+        let rolls = crate::weapons::get_available_rolls(WeaponType::AssaultRifle);
+        let constraints = BuildConstraints {
+            required_rolls: vec![RollType::ColossusDamage],
+            ..Default::default()
+        };
+
+        assert!(constraints.allows_rolls(&[0, 1, 2, 6], &rolls));
+        assert!(!constraints.allows_rolls(&[0, 1, 2, 3], &rolls));
+    }
+
+    #[test]
+    fn test_colossus_damage_only_against_colossi() {
+        let base_stats = WeaponBaseStats::get(Weapon::Thundercage);
+        let stats = WeaponBaseStats { colossus_atk: 1000.0, ..base_stats };
+
+        let colossus = evaluate_dps(&base_stats, &stats, 0.5, false, EnemyProfile::Colossus);
+        let standard = evaluate_dps(&base_stats, &stats, 0.5, false, EnemyProfile::Standard);
+
+        assert!(colossus > standard);
+        assert_eq!(standard, evaluate_dps(&base_stats, &base_stats, 0.5, false, EnemyProfile::Standard));
+    }
+
+    #[tokio::test]
+    async fn test_sweep_matches_single_point_optimization() {
+        let base_stats = WeaponBaseStats::get(Weapon::Thundercage);
+        let slots = SlotCounts::default();
+        let rolls = crate::weapons::get_available_rolls(base_stats.weapon_type);
+        let modules = test_modules();
+        let combinations = generate_module_combinations(&modules, &BuildConstraints::default(), slots.modules);
+
+        let sweep = optimize_weapon_sweep(
+            base_stats, slots, rolls.clone(), modules.clone(), combinations.clone(), &[0.0, 0.5, 1.0], OptimizationConfig::default(),
+        );
+        let single = optimize_weapon(
+            base_stats, slots, rolls, modules, combinations, 0.5, OptimizationConfig::default(),
+        ).await;
+
+        assert_eq!(sweep.len(), 3);
+        assert!((sweep[1].max_dps - single.max_dps).abs() < 1e-3);
+        assert!(sweep[0].max_dps <= sweep[2].max_dps);
+    }
+
+    #[tokio::test]
+    async fn test_optimized_reactor_adds_dps() {
+        let base_stats = WeaponBaseStats::get(Weapon::Thundercage);
+        let slots = SlotCounts::default();
+        let rolls = crate::weapons::get_available_rolls(base_stats.weapon_type);
+        let modules = test_modules();
+        let combinations = generate_module_combinations(&modules, &BuildConstraints::default(), slots.modules);
+
+        let without = optimize_weapon(
+            base_stats, slots, rolls.clone(), modules.clone(), combinations.clone(), 0.5, OptimizationConfig::default(),
+        ).await;
+        // Synthetic candidates, not in-game roll ranges
+        let substat = |bonus_type, value| ComponentSubstat { bonus_type, value };
+        let reactor = |weapon_type| Reactor {
+            weapon_type,
+            substats: vec![
+                substat(ModuleBonusType::Atk, 0.05),
+                substat(ModuleBonusType::Crit, 0.05),
+                substat(ModuleBonusType::ReloadTime, 0.1),
+            ],
+        };
+        let optimized = |reactor| OptimizationConfig { reactor: Some(reactor), optimize_reactor: true, ..Default::default() };
+        let with = optimize_weapon(
+            base_stats, slots, rolls.clone(), modules.clone(), combinations.clone(), 0.5, optimized(reactor(base_stats.weapon_type)),
+        ).await;
+        let mismatched_type = if base_stats.weapon_type == WeaponType::Handgun { WeaponType::Shotgun } else { WeaponType::Handgun };
+        let mismatched = optimize_weapon(
+            base_stats, slots, rolls, modules, combinations, 0.5, optimized(reactor(mismatched_type)),
+        ).await;
+
+        assert!(without.best_reactor.is_empty());
+        assert_eq!(with.best_reactor.len(), crate::components::REACTOR_SUBSTAT_SLOTS);
+        assert!(with.best_reactor.iter().all(|substat| substat.bonus_type != ModuleBonusType::ReloadTime));
+        assert!(with.max_dps > without.max_dps);
+        assert!(mismatched.best_reactor.is_empty());
+        assert!((mismatched.max_dps - without.max_dps).abs() < 1e-9);
+    }
+}
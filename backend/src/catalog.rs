@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::weapons::{
+    get_available_modules, get_available_rolls, BulletType, Module, ModuleType, Roll, Weapon, WeaponBaseStats,
+    WeaponType, MODULE_SETS,
+};
+
+/// Everything the optimizer knows about a weapon.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WeaponDetails {
+    pub name: String,
+    pub weapon_type: WeaponType,
+    pub bullet_type: BulletType,
+    pub module_set: &'static str,
+    pub base_stats: WeaponBaseStats,
+    pub rolls: Vec<Roll>,
+    pub modules: Vec<Module>,
+}
+
+impl WeaponDetails {
+    pub fn get(weapon: Weapon) -> Self {
+        let base_stats = WeaponBaseStats::get(weapon);
+        WeaponDetails {
+            name: weapon.to_string(),
+            weapon_type: base_stats.weapon_type,
+            bullet_type: base_stats.bullet_type,
+            module_set: base_stats.module_key(),
+            base_stats,
+            rolls: get_available_rolls(base_stats.weapon_type),
+            modules: get_available_modules(base_stats.bullet_type, base_stats.weapon_type),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ModuleFilter {
+    pub bullet_type: Option<BulletType>,
+    pub weapon_type: Option<WeaponType>,
+    pub module_type: Option<ModuleType>,
+}
+
+/// One entry of `MODULE_SETS` with the weapon types that use it.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ModuleSet {
+    pub name: &'static str,
+    pub bullet_type: BulletType,
+    pub weapon_types: Vec<WeaponType>,
+    pub modules: Vec<Module>,
+}
+
+/// The module sets used by weapons of the filter's bullet and weapon type,
+/// holding only modules of its module type. Sets left empty are dropped.
+pub fn module_catalog(filter: &ModuleFilter) -> Vec<ModuleSet> {
+    MODULE_SETS
+        .iter()
+        .filter_map(|&(name, bullet_type, weapon_type)| {
+            let mut weapon_types: Vec<WeaponType> = Vec::new();
+            for stats in Weapon::all().iter().map(|&weapon| WeaponBaseStats::get(weapon)) {
+                let matches = stats.module_key() == name
+                    && filter.bullet_type.is_none_or(|wanted| stats.bullet_type == wanted)
+                    && filter.weapon_type.is_none_or(|wanted| stats.weapon_type == wanted);
+                if matches && !weapon_types.contains(&stats.weapon_type) {
+                    weapon_types.push(stats.weapon_type);
+                }
+            }
+            if weapon_types.is_empty() {
+                return None;
+            }
+
+            let modules: Vec<Module> = get_available_modules(bullet_type, weapon_type)
+                .into_iter()
+                .filter(|module| filter.module_type.is_none_or(|wanted| module.module_type == wanted))
+                .collect();
+            (!modules.is_empty()).then_some(ModuleSet { name, bullet_type, weapon_types, modules })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_catalog() {
+        let details = WeaponDetails::get(Weapon::Thundercage);
+        assert_eq!(details.weapon_type, WeaponType::SubmachineGun);
+        assert_eq!(details.modules.len(), get_available_modules(details.bullet_type, details.weapon_type).len());
+        assert!(!details.rolls.is_empty());
+
+        assert_eq!(module_catalog(&ModuleFilter::default()).len(), MODULE_SETS.len());
+
+        let snipers = module_catalog(&ModuleFilter { weapon_type: Some(WeaponType::SniperRifle), ..Default::default() });
+        assert_eq!(snipers.len(), 1);
+        assert_eq!(snipers[0].name, "SniperRifle");
+        assert_eq!(snipers[0].weapon_types, vec![WeaponType::SniperRifle]);
+
+        let crit = module_catalog(&ModuleFilter { module_type: Some(ModuleType::Crit), ..Default::default() });
+        assert!(crit.iter().flat_map(|set| &set.modules).all(|module| module.module_type == ModuleType::Crit));
+
+        let high_power = module_catalog(&ModuleFilter { bullet_type: Some(BulletType::HighPowerRounds), ..Default::default() });
+        assert!(high_power.iter().all(|set| set.bullet_type == BulletType::HighPowerRounds));
+        assert!(module_catalog(&ModuleFilter {
+            bullet_type: Some(BulletType::HighPowerRounds),
+            weapon_type: Some(WeaponType::Handgun),
+            module_type: None,
+        })
+        .is_empty());
+    }
+}
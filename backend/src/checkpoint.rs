@@ -0,0 +1,172 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::calculate::EnemyProfile;
+use crate::grid::{BuffSet, GridConfig, Objective};
+use crate::jobs::now_millis;
+use crate::result_key::ResultKey;
+use crate::weapons::Weapon;
+
+pub const RUNS_COLLECTION: &str = "worker_runs";
+
+/// One (weapon, grid point, weak point hit chance) result of a worker run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkUnit {
+    pub weapon: Weapon,
+    pub valby: bool,
+    pub enzo: bool,
+    pub objective: Objective,
+    pub enemy: EnemyProfile,
+    pub weak_point_hit_chance: f64,
+}
+
+impl WorkUnit {
+    /// The key of the result this unit computes. Units are told apart by it.
+    pub fn key(&self) -> ResultKey {
+        let buffs = BuffSet { valby: self.valby, enzo: self.enzo };
+        ResultKey::new(self.weapon, self.weak_point_hit_chance, buffs, self.objective, self.enemy)
+    }
+
+    pub fn id(&self) -> String {
+        self.key().to_string()
+    }
+
+    pub fn grid_config(&self) -> GridConfig {
+        GridConfig {
+            buffs: BuffSet { valby: self.valby, enzo: self.enzo },
+            objective: self.objective,
+            enemy: self.enemy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedUnit {
+    pub unit: ResultKey,
+    pub error: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum UnitOutcome {
+    Finished,
+    /// Stored result already matches the inputs
+    Skipped,
+    Failed(String),
+}
+
+/// Progress of a worker run, saved after every unit so a crashed run can be
+/// resumed without redoing finished units.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunCheckpoint {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub command: String,
+    pub started_at: i64,
+    pub updated_at: i64,
+    pub finished: HashSet<ResultKey>,
+    pub skipped: HashSet<ResultKey>,
+    pub failed: Vec<FailedUnit>,
+    pub completed: bool,
+}
+
+impl RunCheckpoint {
+    pub fn new(id: Option<String>, command: String) -> Self {
+        let now = now_millis();
+        RunCheckpoint {
+            id: id.unwrap_or_else(|| ObjectId::new().to_hex()),
+            command,
+            started_at: now,
+            updated_at: now,
+            finished: HashSet::new(),
+            skipped: HashSet::new(),
+            failed: Vec::new(),
+            completed: false,
+        }
+    }
+
+    /// Continues from the `existing` checkpoint of run `id`, if there is
+    /// one. Units that failed or were skipped last time are tried again.
+    pub fn resume(existing: Option<RunCheckpoint>, id: String, command: String) -> Self {
+        match existing {
+            Some(checkpoint) => RunCheckpoint {
+                skipped: HashSet::new(),
+                failed: Vec::new(),
+                completed: false,
+                ..checkpoint
+            },
+            None => RunCheckpoint::new(Some(id), command),
+        }
+    }
+
+    pub fn touch(&mut self) {
+        self.updated_at = now_millis();
+    }
+
+    pub fn is_finished(&self, unit: &WorkUnit) -> bool {
+        self.finished.contains(&unit.key())
+    }
+
+    pub fn record(&mut self, unit: &WorkUnit, outcome: UnitOutcome) {
+        let key = unit.key();
+        match outcome {
+            UnitOutcome::Finished => {
+                self.finished.insert(key);
+            },
+            UnitOutcome::Skipped => {
+                self.skipped.insert(key);
+            },
+            UnitOutcome::Failed(error) => self.failed.push(FailedUnit { unit: key, error }),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Run {}: {} finished, {} skipped, {} failed",
+            self.id,
+            self.finished.len(),
+            self.skipped.len(),
+            self.failed.len(),
+        );
+        for failed in &self.failed {
+            summary.push_str(&format!("\n  {} failed: {}", failed.unit, failed.error));
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_units() {
+        let mut checkpoint = RunCheckpoint::new(Some("run".to_string()), "UpdateAll".to_string());
+        let unit = |weak_point_hit_chance| WorkUnit {
+            weapon: Weapon::Thundercage,
+            valby: true,
+            enzo: false,
+            objective: Objective::Sustained,
+            enemy: EnemyProfile::Colossus,
+            weak_point_hit_chance,
+        };
+
+        checkpoint.record(&unit(0.25), UnitOutcome::Finished);
+        checkpoint.record(&unit(0.5), UnitOutcome::Skipped);
+        checkpoint.record(&unit(1.0), UnitOutcome::Failed("timeout".to_string()));
+
+        assert!(checkpoint.is_finished(&unit(0.25)));
+        assert!(!checkpoint.is_finished(&unit(1.0)));
+        assert_eq!(checkpoint.finished, HashSet::from([unit(0.25).key()]));
+        assert_eq!(checkpoint.summary(), "Run run: 1 finished, 1 skipped, 1 failed\n  Thundercage_1.0000_valby_Sustained_Colossus failed: timeout");
+
+        // Chances closer than the old two-decimal ids are still told apart
+        checkpoint.record(&unit(0.125), UnitOutcome::Finished);
+        assert!(checkpoint.is_finished(&unit(0.125)));
+        assert!(!checkpoint.is_finished(&unit(0.13)));
+
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        let decoded: RunCheckpoint = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.finished, checkpoint.finished);
+    }
+}
@@ -0,0 +1,211 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, create_dir_all};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::calculate::{BuildConstraints, ModuleCombinations, generate_module_combinations};
+use crate::input_hash::hash_json;
+use crate::weapons::{Module, SlotCounts, MODULE_SETS, get_available_modules};
+
+pub const CACHE_DIR: &str = "module_combinations";
+
+/// Bump when the file layout or the combination generation changes.
+pub const CACHE_FORMAT_VERSION: u32 = 2;
+
+const MAGIC: &[u8; 4] = b"WOMC";
+
+/// Written in front of the combinations so a file can be checked against the
+/// module catalog it is indexing into before it is used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheHeader {
+    pub format_version: u32,
+    pub catalog_hash: String,
+    pub module_names: Vec<String>,
+    pub module_slots: usize,
+}
+
+impl CacheHeader {
+    pub fn for_catalog(modules: &[Module]) -> Self {
+        let module_slots = SlotCounts::default().modules;
+        CacheHeader {
+            format_version: CACHE_FORMAT_VERSION,
+            catalog_hash: hash_json(&(modules, BuildConstraints::default(), module_slots)),
+            module_names: modules.iter().map(|module| module.name.clone()).collect(),
+            module_slots,
+        }
+    }
+
+    /// Why a file with this header can't be used for `expected`, if it can't.
+    fn mismatch(&self, expected: &CacheHeader) -> Option<String> {
+        if self.format_version != expected.format_version {
+            return Some(format!("format version {} (expected {})", self.format_version, expected.format_version));
+        }
+        if self.module_names != expected.module_names {
+            let moved = expected.module_names
+                .iter()
+                .enumerate()
+                .find(|(index, name)| self.module_names.get(*index) != Some(name))
+                .map_or_else(|| "fewer modules".to_string(), |(index, name)| format!("index {} is now {}", index, name));
+            return Some(format!("module list changed, {}", moved));
+        }
+        if self.module_slots != expected.module_slots {
+            return Some(format!("{} module slots (expected {})", self.module_slots, expected.module_slots));
+        }
+        if self.catalog_hash != expected.catalog_hash {
+            return Some("module values or default constraints changed".to_string());
+        }
+        None
+    }
+}
+
+#[derive(Debug)]
+pub enum CacheError {
+    Missing(PathBuf),
+    Stale { path: PathBuf, reason: String },
+    Io(std::io::Error),
+    Encoding(bincode::Error),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheError::Missing(path) => write!(f, "Module combination cache {} does not exist", path.display()),
+            CacheError::Stale { path, reason } => write!(f, "Module combination cache {} is stale: {}", path.display(), reason),
+            CacheError::Io(e) => write!(f, "Module combination cache I/O error: {}", e),
+            CacheError::Encoding(e) => write!(f, "Module combination cache encoding error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<std::io::Error> for CacheError {
+    fn from(e: std::io::Error) -> Self {
+        CacheError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for CacheError {
+    fn from(e: bincode::Error) -> Self {
+        CacheError::Encoding(e)
+    }
+}
+
+fn cache_path(dir: &str, key: &str) -> PathBuf {
+    Path::new(dir).join(format!("{}.bin", key))
+}
+
+/// Writes to a temporary file next to the cache and renames it over, so a
+/// worker killed mid-write never leaves a truncated cache behind.
+pub fn save(dir: &str, key: &str, modules: &[Module], combinations: &ModuleCombinations) -> Result<(), CacheError> {
+    create_dir_all(dir)?;
+    let path = cache_path(dir, key);
+    let temp_path = path.with_extension(format!("bin.{}.tmp", std::process::id()));
+    let written = (|| -> Result<(), CacheError> {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writer.write_all(MAGIC)?;
+        bincode::serialize_into(&mut writer, &CacheHeader::for_catalog(modules))?;
+        bincode::serialize_into(&mut writer, combinations)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&temp_path, &path)?;
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    written
+}
+
+/// Reads the combinations for `key`, refusing files written for a different
+/// module catalog since their indices would point at the wrong modules.
+pub fn load(dir: &str, key: &str, modules: &[Module]) -> Result<ModuleCombinations, CacheError> {
+    let path = cache_path(dir, key);
+    if !path.exists() {
+        return Err(CacheError::Missing(path));
+    }
+    let mut reader = BufReader::new(File::open(&path)?);
+
+    let mut magic = [0u8; 4];
+    if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+        return Err(CacheError::Stale { path, reason: "no header, written by an older worker".to_string() });
+    }
+    let header: CacheHeader = bincode::deserialize_from(&mut reader)
+        .map_err(|e| CacheError::Stale { path: path.clone(), reason: format!("unreadable header: {}", e) })?;
+    if let Some(reason) = header.mismatch(&CacheHeader::for_catalog(modules)) {
+        return Err(CacheError::Stale { path, reason });
+    }
+
+    Ok(bincode::deserialize_from(&mut reader)?)
+}
+
+pub fn generate(modules: &[Module]) -> ModuleCombinations {
+    ModuleCombinations {
+        combinations: generate_module_combinations(modules, &BuildConstraints::default(), SlotCounts::default().modules),
+    }
+}
+
+/// Loads every module set's combinations. Missing, stale or corrupt files
+/// are regenerated and rewritten, unless `regenerate` is off in which case
+/// they are an error.
+pub fn load_all(dir: &str, regenerate: bool) -> Result<HashMap<String, ModuleCombinations>, CacheError> {
+    let mut combinations = HashMap::new();
+    for (key, bullet_type, weapon_type) in MODULE_SETS.iter() {
+        let modules = get_available_modules(*bullet_type, *weapon_type);
+        let loaded = match load(dir, key, &modules) {
+            Ok(loaded) => loaded,
+            Err(e @ (CacheError::Missing(_) | CacheError::Stale { .. } | CacheError::Encoding(_))) if regenerate => {
+                warn!("{}, regenerating", e);
+                let generated = generate(&modules);
+                save(dir, key, &modules, &generated)?;
+                generated
+            },
+            Err(e) => return Err(e),
+        };
+        combinations.insert(key.to_string(), loaded);
+    }
+    Ok(combinations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapons::{BulletType, WeaponType};
+
+    #[test]
+    fn test_cache_validation() {
+        let dir = std::env::temp_dir().join(format!("combination_cache_test_{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let modules = get_available_modules(BulletType::GeneralRounds, WeaponType::HandCannon);
+        let combinations = ModuleCombinations { combinations: vec![vec![0, 1, 2], vec![0, 3, 4]] };
+
+        save(dir, "Test", &modules, &combinations).unwrap();
+        assert_eq!(load(dir, "Test", &modules).unwrap().combinations, combinations.combinations);
+
+        let mut reordered = modules.clone();
+        reordered.swap(1, 2);
+        assert!(matches!(load(dir, "Test", &reordered), Err(CacheError::Stale { .. })));
+
+        let mut rebalanced = modules.clone();
+        rebalanced[3].effects[0].value += 0.01;
+        assert!(matches!(load(dir, "Test", &rebalanced), Err(CacheError::Stale { .. })));
+
+        // A truncated body is an encoding error, which load_all regenerates
+        let bytes = std::fs::read(cache_path(dir, "Test")).unwrap();
+        std::fs::write(cache_path(dir, "Test"), &bytes[..bytes.len() - 4]).unwrap();
+        assert!(matches!(load(dir, "Test", &modules), Err(CacheError::Encoding(_))));
+
+        // Saving replaces the file without leaving the temporary one behind
+        save(dir, "Test", &modules, &combinations).unwrap();
+        assert!(load(dir, "Test", &modules).is_ok());
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+
+        std::fs::write(cache_path(dir, "Test"), bincode::serialize(&combinations).unwrap()).unwrap();
+        assert!(matches!(load(dir, "Test", &modules), Err(CacheError::Stale { .. })));
+        assert!(matches!(load(dir, "Missing", &modules), Err(CacheError::Missing(_))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
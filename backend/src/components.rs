@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::weapons::{ModuleBonusType, WeaponType};
+
+/// Number of substats a reactor rolls.
+pub const REACTOR_SUBSTAT_SLOTS: usize = 2;
+
+/// A bonus rolled on a reactor or external component. Applied like the
+/// module effect of the same type.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ComponentSubstat {
+    pub bonus_type: ModuleBonusType,
+    pub value: f64,
+}
+
+/// A reactor only boosts weapons of the type it is optimized for. When the
+/// reactor is optimized, `substats` are the candidates the search picks
+/// `REACTOR_SUBSTAT_SLOTS` from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Reactor {
+    pub weapon_type: WeaponType,
+    pub substats: Vec<ComponentSubstat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ExternalComponent {
+    pub name: String,
+    pub substats: Vec<ComponentSubstat>,
+}
+
+impl Reactor {
+    /// Substats that apply to a weapon of `weapon_type`, none on a mismatch.
+    pub fn substats_for(&self, weapon_type: WeaponType) -> &[ComponentSubstat] {
+        if self.weapon_type == weapon_type {
+            &self.substats
+        } else {
+            &[]
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::calculate::OptimizationResult;
+use crate::tiers::{Tier, TierGrouping, TierThresholds, tier_list};
+use crate::weapons::Weapon;
+
+/// How one weapon's result changed between two result sets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeaponDelta {
+    pub weapon: String,
+    pub before_dps: Option<f64>,
+    pub after_dps: Option<f64>,
+    pub dps_delta: f64,
+    pub dps_delta_percent: f64,
+    pub before_rank: Option<usize>,
+    pub after_rank: Option<usize>,
+    pub before_tier: Option<Tier>,
+    pub after_tier: Option<Tier>,
+    pub modules_added: Vec<String>,
+    pub modules_removed: Vec<String>,
+    pub rolls_before: Vec<String>,
+    pub rolls_after: Vec<String>,
+}
+
+impl WeaponDelta {
+    pub fn build_changed(&self) -> bool {
+        !self.modules_added.is_empty() || !self.modules_removed.is_empty() || self.rolls_before != self.rolls_after
+    }
+
+    pub fn tier_changed(&self) -> bool {
+        self.before_tier != self.after_tier
+    }
+}
+
+/// How much a recomputed result may change before `worker diff` fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffThresholds {
+    pub max_dps_change_percent: f64,
+    /// Fail on any tier change
+    pub tier: bool,
+    /// Fail on any change to the optimal build
+    pub build: bool,
+}
+
+impl DiffThresholds {
+    /// Why `delta` goes over the thresholds, if it does. Weapons without a
+    /// stored result have nothing to regress from.
+    pub fn exceeded_by(&self, delta: &WeaponDelta) -> Option<String> {
+        delta.before_dps?;
+        if delta.dps_delta_percent.abs() > self.max_dps_change_percent {
+            return Some(format!("max DPS changed {:+.2}%", delta.dps_delta_percent));
+        }
+        if self.tier && delta.tier_changed() {
+            return Some(format!("tier changed from {:?} to {:?}", delta.before_tier, delta.after_tier));
+        }
+        if self.build && delta.build_changed() {
+            return Some("optimal build changed".to_string());
+        }
+        None
+    }
+}
+
+/// Rank (1-based) and tier of every weapon within its group of the default
+/// tier list, so a diff reports the same ranks and tiers `/tier-list` shows.
+pub fn rank_weapons(results: &HashMap<Weapon, OptimizationResult>) -> HashMap<Weapon, (usize, Tier)> {
+    let dps: HashMap<Weapon, f64> = results.iter().map(|(weapon, result)| (*weapon, result.max_dps)).collect();
+    tier_list(&dps, TierGrouping::default(), &TierThresholds::default())
+        .into_iter()
+        .flat_map(|group| group.weapons)
+        .map(|entry| (entry.weapon, (entry.rank, entry.tier)))
+        .collect()
+}
+
+fn module_names(result: &OptimizationResult) -> Vec<String> {
+    result.best_modules.iter().map(|(module, _)| module.name.clone()).collect()
+}
+
+fn roll_names(result: &OptimizationResult) -> Vec<String> {
+    let mut names: Vec<String> = result.best_rolls.iter().map(|roll| format!("{:?}", roll.roll_type)).collect();
+    names.sort();
+    names
+}
+
+/// Compares two result sets weapon by weapon, in `Weapon::all()` order.
+pub fn compare_results(
+    before: &HashMap<Weapon, OptimizationResult>,
+    after: &HashMap<Weapon, OptimizationResult>,
+) -> Vec<WeaponDelta> {
+    let before_ranks = rank_weapons(before);
+    let after_ranks = rank_weapons(after);
+
+    Weapon::all()
+        .iter()
+        .filter(|weapon| before.contains_key(weapon) || after.contains_key(weapon))
+        .map(|weapon| {
+            let old = before.get(weapon);
+            let new = after.get(weapon);
+            let before_dps = old.map(|r| r.max_dps);
+            let after_dps = new.map(|r| r.max_dps);
+            let dps_delta = after_dps.unwrap_or(0.0) - before_dps.unwrap_or(0.0);
+            let dps_delta_percent = match before_dps {
+                Some(dps) if dps > 0.0 => dps_delta / dps * 100.0,
+                _ => 0.0,
+            };
+            let old_modules = old.map(module_names).unwrap_or_default();
+            let new_modules = new.map(module_names).unwrap_or_default();
+
+            WeaponDelta {
+                weapon: weapon.to_string(),
+                before_dps,
+                after_dps,
+                dps_delta,
+                dps_delta_percent,
+                before_rank: before_ranks.get(weapon).map(|(rank, _)| *rank),
+                after_rank: after_ranks.get(weapon).map(|(rank, _)| *rank),
+                before_tier: before_ranks.get(weapon).map(|(_, tier)| *tier),
+                after_tier: after_ranks.get(weapon).map(|(_, tier)| *tier),
+                modules_added: new_modules.iter().filter(|m| !old_modules.contains(m)).cloned().collect(),
+                modules_removed: old_modules.iter().filter(|m| !new_modules.contains(m)).cloned().collect(),
+                rolls_before: old.map(roll_names).unwrap_or_default(),
+                rolls_after: new.map(roll_names).unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+/// How the `fresh` results would change things if they replaced the stored
+/// ones. Ranks and tiers are taken over all stored weapons, not just the
+/// recomputed ones.
+pub fn compare_to_stored(
+    stored: &HashMap<Weapon, OptimizationResult>,
+    fresh: &HashMap<Weapon, OptimizationResult>,
+) -> Vec<WeaponDelta> {
+    let mut after = stored.clone();
+    after.extend(fresh.iter().map(|(weapon, result)| (*weapon, result.clone())));
+    compare_results(stored, &after)
+        .into_iter()
+        .filter(|delta| fresh.keys().any(|weapon| weapon.to_string() == delta.weapon))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(max_dps: f64) -> OptimizationResult {
+        OptimizationResult { max_dps, best_rolls: Vec::new(), best_modules: Vec::new(), best_reactor: Vec::new() }
+    }
+
+    #[test]
+    fn test_compare_to_stored() {
+        // Ranked against the other submachine guns only, like the tier list
+        let stored = HashMap::from([
+            (Weapon::Thundercage, result(1000.0)),
+            (Weapon::Python, result(900.0)),
+            (Weapon::PiercingLight, result(90000.0)),
+        ]);
+        let fresh = HashMap::from([(Weapon::Python, result(1100.0))]);
+
+        let deltas = compare_to_stored(&stored, &fresh);
+        assert_eq!(deltas.len(), 1);
+        let delta = &deltas[0];
+        assert_eq!(delta.weapon, Weapon::Python.to_string());
+        assert_eq!((delta.before_rank, delta.after_rank), (Some(2), Some(1)));
+        assert!((delta.dps_delta_percent - 22.22).abs() < 0.01);
+
+        let thresholds = DiffThresholds { max_dps_change_percent: 25.0, tier: false, build: false };
+        assert!(thresholds.exceeded_by(delta).is_none());
+        let thresholds = DiffThresholds { max_dps_change_percent: 5.0, ..thresholds };
+        assert!(thresholds.exceeded_by(delta).is_some());
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use strum_macros::{Display, EnumString};
+use utoipa::ToSchema;
+
+use crate::calculate::OptimizationResult;
+use crate::result_key::ResultKey;
+use crate::weapons::{Roll, RollType};
+
+/// How a build is rendered for sharing outside the frontend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ExportFormat {
+    /// Tables for wikis, forums and Discord
+    #[default]
+    Markdown,
+    /// Short lines suited to in-game chat
+    Text,
+    /// One row per module and roll, with a header
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Text => "text/plain; charset=utf-8",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    /// Renders the result stored under `key`. Modules are ordered by
+    /// importance, most important first.
+    pub fn render(&self, key: &ResultKey, result: &OptimizationResult) -> String {
+        match self {
+            ExportFormat::Markdown => render_markdown(key, result),
+            ExportFormat::Text => render_text(key, result),
+            ExportFormat::Csv => render_csv(key, result),
+        }
+    }
+
+    /// Renders several results into one document. CSV keeps a single header.
+    pub fn render_all<'a>(&self, results: impl IntoIterator<Item = (&'a ResultKey, &'a OptimizationResult)>) -> String {
+        let mut out = String::new();
+        for (i, (key, result)) in results.into_iter().enumerate() {
+            let rendered = self.render(key, result);
+            match (self, i) {
+                (_, 0) => out.push_str(&rendered),
+                (ExportFormat::Csv, _) => out.push_str(rendered.split_once('\n').map_or("", |(_, rows)| rows)),
+                _ => {
+                    out.push('\n');
+                    out.push_str(&rendered);
+                },
+            }
+        }
+        out
+    }
+}
+
+fn modules_by_importance(result: &OptimizationResult) -> Vec<(&str, f64)> {
+    let mut modules: Vec<(&str, f64)> = result.best_modules.iter().map(|(module, importance)| (module.name.as_str(), *importance)).collect();
+    modules.sort_by(|a, b| b.1.total_cmp(&a.1));
+    modules
+}
+
+/// Flat rolls as whole numbers, multipliers as percentages.
+fn roll_value(roll: &Roll) -> String {
+    match roll.roll_type {
+        RollType::ElementAtk | RollType::ColossusDamage => format!("{:.0}", roll.value),
+        _ => format!("{:.1}%", roll.value * 100.0),
+    }
+}
+
+fn config_summary(key: &ResultKey) -> String {
+    format!(
+        "{:.0}% weak point hits, buffs: {}, {}, {}",
+        key.weak_point_hit_chance() * 100.0,
+        key.buffs,
+        key.objective,
+        key.enemy,
+    )
+}
+
+fn render_markdown(key: &ResultKey, result: &OptimizationResult) -> String {
+    let mut out = String::new();
+    writeln!(out, "## {}", key.weapon).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "**DPS:** {:.0}  ", result.max_dps).unwrap();
+    writeln!(out, "**Config:** {}", config_summary(key)).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "| # | Module | Importance |").unwrap();
+    writeln!(out, "|---|--------|-----------:|").unwrap();
+    for (rank, (name, importance)) in modules_by_importance(result).into_iter().enumerate() {
+        writeln!(out, "| {} | {} | {:.1} |", rank + 1, name.replace('|', "\\|"), importance).unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "| Roll | Value |").unwrap();
+    writeln!(out, "|------|------:|").unwrap();
+    for roll in &result.best_rolls {
+        writeln!(out, "| {:?} | {} |", roll.roll_type, roll_value(roll)).unwrap();
+    }
+    out
+}
+
+fn render_text(key: &ResultKey, result: &OptimizationResult) -> String {
+    let mut out = String::new();
+    writeln!(out, "{} - {:.0} DPS ({})", key.weapon, result.max_dps, config_summary(key)).unwrap();
+    let modules: Vec<String> = modules_by_importance(result)
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (name, importance))| format!("{}. {} (+{:.0})", rank + 1, name, importance))
+        .collect();
+    writeln!(out, "Modules: {}", modules.join(", ")).unwrap();
+    let rolls: Vec<String> = result.best_rolls.iter().map(|roll| format!("{:?} {}", roll.roll_type, roll_value(roll))).collect();
+    writeln!(out, "Rolls: {}", rolls.join(", ")).unwrap();
+    out
+}
+
+/// Quotes a field containing separators, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn render_csv(key: &ResultKey, result: &OptimizationResult) -> String {
+    let prefix = [
+        key.weapon.to_string(),
+        key.weak_point_hit_chance().to_string(),
+        key.buffs.to_string(),
+        key.objective.to_string(),
+        key.enemy.to_string(),
+        result.max_dps.to_string(),
+    ]
+    .join(",");
+
+    let mut out = String::from("weapon,weak_point_hit_chance,buffs,objective,enemy,max_dps,kind,rank,name,value\n");
+    for (rank, (name, importance)) in modules_by_importance(result).into_iter().enumerate() {
+        writeln!(out, "{},module,{},{},{}", prefix, rank + 1, csv_field(name), importance).unwrap();
+    }
+    for (rank, roll) in result.best_rolls.iter().enumerate() {
+        writeln!(out, "{},roll,{},{:?},{}", prefix, rank + 1, roll.roll_type, roll.value).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::BuffSet;
+    use crate::weapons::{Module, ModuleType, Weapon};
+
+    fn module(name: &str) -> Module {
+        Module { name: name.to_string(), module_type: ModuleType::None, effects: Vec::new() }
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let key = ResultKey::served(Weapon::Thundercage, 0.5, BuffSet { valby: true, enzo: false });
+        let result = OptimizationResult {
+            max_dps: 12345.6,
+            best_rolls: vec![Roll { roll_type: RollType::Atk, value: 0.122 }, Roll { roll_type: RollType::ElementAtk, value: 1232.0 }],
+            best_modules: vec![(module("Rifling Reinforcement"), 150.0), (module("Weak Point Sight, Mk 2"), 900.0)],
+            best_reactor: Vec::new(),
+        };
+
+        let markdown = ExportFormat::Markdown.render(&key, &result);
+        assert!(markdown.starts_with("## Thundercage\n"));
+        assert!(markdown.contains("**DPS:** 12346"));
+        assert!(markdown.contains("50% weak point hits, buffs: valby, Sustained, Colossus"));
+        assert!(markdown.contains("| 1 | Weak Point Sight, Mk 2 | 900.0 |\n| 2 | Rifling Reinforcement | 150.0 |"));
+        assert!(markdown.contains("| Atk | 12.2% |\n| ElementAtk | 1232 |"));
+
+        let text = ExportFormat::Text.render(&key, &result);
+        assert_eq!(
+            text,
+            "Thundercage - 12346 DPS (50% weak point hits, buffs: valby, Sustained, Colossus)\n\
+             Modules: 1. Weak Point Sight, Mk 2 (+900), 2. Rifling Reinforcement (+150)\n\
+             Rolls: Atk 12.2%, ElementAtk 1232\n"
+        );
+
+        let csv = ExportFormat::Csv.render(&key, &result);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1], "Thundercage,0.5,valby,Sustained,Colossus,12345.6,module,1,\"Weak Point Sight, Mk 2\",900");
+        assert_eq!(lines[4], "Thundercage,0.5,valby,Sustained,Colossus,12345.6,roll,2,ElementAtk,1232");
+
+        let both = ExportFormat::Csv.render_all([(&key, &result), (&key, &result)]);
+        assert_eq!(both.lines().filter(|line| line.starts_with("weapon,")).count(), 1);
+        assert_eq!(both.lines().count(), 9);
+
+        assert_eq!("csv".parse::<ExportFormat>(), Ok(ExportFormat::Csv));
+        assert!("pdf".parse::<ExportFormat>().is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, create_dir_all};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::weapons::{Module, Roll, Weapon, WeaponBaseStats, WeaponType, MODULE_SETS, get_available_modules, get_available_rolls};
+
+/// Version of the compiled-in weapon, roll and module tables. It's a hash
+/// of their contents, so any balance change in `weapons.rs` or `modules/`
+/// gets a new version and stored results of the old one go stale.
+pub fn game_data_version() -> &'static str {
+    static VERSION: OnceLock<String> = OnceLock::new();
+    VERSION.get_or_init(|| GameData::current().content_version())
+}
+
+pub const GAME_DATA_DIR: &str = "game_data";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeaponEntry {
+    pub weapon: Weapon,
+    pub stats: WeaponBaseStats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollSet {
+    pub weapon_type: WeaponType,
+    pub rolls: Vec<Roll>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleSet {
+    pub key: String,
+    pub modules: Vec<Module>,
+}
+
+/// A full snapshot of the game numbers for one patch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameData {
+    pub version: String,
+    pub weapons: Vec<WeaponEntry>,
+    pub rolls: Vec<RollSet>,
+    pub modules: Vec<ModuleSet>,
+}
+
+impl GameData {
+    /// The compiled-in tables.
+    pub fn current() -> Self {
+        let weapons: Vec<WeaponEntry> = Weapon::all()
+            .iter()
+            .map(|&weapon| WeaponEntry { weapon, stats: WeaponBaseStats::get(weapon) })
+            .collect();
+
+        let mut rolls: Vec<RollSet> = Vec::new();
+        for entry in &weapons {
+            if !rolls.iter().any(|set| set.weapon_type == entry.stats.weapon_type) {
+                rolls.push(RollSet {
+                    weapon_type: entry.stats.weapon_type,
+                    rolls: get_available_rolls(entry.stats.weapon_type),
+                });
+            }
+        }
+
+        let modules = MODULE_SETS
+            .iter()
+            .map(|(key, bullet_type, weapon_type)| ModuleSet {
+                key: key.to_string(),
+                modules: get_available_modules(*bullet_type, *weapon_type),
+            })
+            .collect();
+
+        let mut data = GameData { version: String::new(), weapons, rolls, modules };
+        data.version = data.content_version();
+        data
+    }
+
+    /// First 12 hex digits of the SHA-256 of the tables, ignoring `version`.
+    pub fn content_version(&self) -> String {
+        let tables = serde_json::to_vec(&(&self.weapons, &self.rolls, &self.modules)).expect("Game data serializes");
+        Sha256::digest(tables).iter().take(6).map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn path(dir: &str, version: &str) -> std::path::PathBuf {
+        Path::new(dir).join(format!("{}.json", version))
+    }
+
+    pub fn save(&self, dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+        create_dir_all(dir)?;
+        let file = File::create(Self::path(dir, &self.version))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Loads a snapshot from `dir`. The current version falls back to the
+    /// compiled-in tables when it hasn't been exported yet. Errors if the
+    /// file's tables don't hash to its version.
+    pub fn load(dir: &str, version: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = Self::path(dir, version);
+        if !path.exists() && version == game_data_version() {
+            return Ok(Self::current());
+        }
+        let file = File::open(&path).map_err(|e| format!("Game data {} not found at {}: {}", version, path.display(), e))?;
+        let data: GameData = serde_json::from_reader(file)?;
+        let content_version = data.content_version();
+        if data.version != version || content_version != version {
+            return Err(format!("Game data at {} was edited, its tables are version {}", path.display(), content_version).into());
+        }
+        Ok(data)
+    }
+
+    pub fn base_stats(&self, weapon: Weapon) -> Option<WeaponBaseStats> {
+        self.weapons.iter().find(|entry| entry.weapon == weapon).map(|entry| entry.stats)
+    }
+
+    pub fn rolls(&self, weapon_type: WeaponType) -> Vec<Roll> {
+        self.rolls
+            .iter()
+            .find(|set| set.weapon_type == weapon_type)
+            .map_or_else(|| get_available_rolls(weapon_type), |set| set.rolls.clone())
+    }
+
+    pub fn modules(&self, key: &str) -> Vec<Module> {
+        self.modules
+            .iter()
+            .find(|set| set.key == key)
+            .map(|set| set.modules.clone())
+            .unwrap_or_default()
+    }
+}
+
+fn format_effects(module: &Module) -> String {
+    module.effects
+        .iter()
+        .map(|effect| format!("{:?} {}", effect.effect_type, effect.value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Human-readable list of every number that changed between two snapshots,
+/// in the style of the frontend changelog.
+pub fn data_changes(from: &GameData, to: &GameData) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    for entry in &to.weapons {
+        let Some(old_stats) = from.base_stats(entry.weapon) else {
+            changes.push(format!("Added {}", entry.weapon));
+            continue;
+        };
+        let old = serde_json::to_value(old_stats).unwrap_or_default();
+        let new = serde_json::to_value(entry.stats).unwrap_or_default();
+        if let (Some(old), Some(new)) = (old.as_object(), new.as_object()) {
+            for (field, new_value) in new {
+                if old.get(field) != Some(new_value) {
+                    let old_value = old.get(field).map_or("-".to_string(), |v| v.to_string());
+                    changes.push(format!("{} {} {} -> {}", entry.weapon, field, old_value, new_value));
+                }
+            }
+        }
+    }
+    for entry in &from.weapons {
+        if to.base_stats(entry.weapon).is_none() {
+            changes.push(format!("Removed {}", entry.weapon));
+        }
+    }
+
+    for set in &to.rolls {
+        let old_rolls = from.rolls(set.weapon_type);
+        for roll in &set.rolls {
+            match old_rolls.iter().find(|old| old.roll_type == roll.roll_type) {
+                Some(old) if old.value != roll.value => {
+                    changes.push(format!("{} {:?} roll {} -> {}", set.weapon_type, roll.roll_type, old.value, roll.value));
+                },
+                None => changes.push(format!("{} added {:?} roll {}", set.weapon_type, roll.roll_type, roll.value)),
+                _ => {},
+            }
+        }
+    }
+
+    for set in &to.modules {
+        let old_modules = from.modules(&set.key);
+        for module in &set.modules {
+            match old_modules.iter().find(|old| old.name == module.name) {
+                Some(old) => {
+                    let (old_effects, new_effects) = (format_effects(old), format_effects(module));
+                    if old_effects != new_effects {
+                        changes.push(format!("{} {}: {} -> {}", set.key, module.name, old_effects, new_effects));
+                    }
+                },
+                None => changes.push(format!("Added {} module {}", set.key, module.name)),
+            }
+        }
+        for old in &old_modules {
+            if !set.modules.iter().any(|module| module.name == old.name) {
+                changes.push(format!("Removed {} module {}", set.key, old.name));
+            }
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::OptimizationConfig;
+    use crate::weapons::SlotCounts;
+
+    #[test]
+    fn test_game_data_version() {
+        let current = GameData::current();
+        assert_eq!(current.version, game_data_version());
+        assert_eq!(game_data_version().len(), 12);
+
+        let mut changed = current.clone();
+        changed.rolls[0].rolls[0].value += 0.01;
+        assert_ne!(changed.content_version(), current.content_version());
+
+        let dir = std::env::temp_dir().join(format!("game_data_test_{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        current.save(dir).unwrap();
+        assert!(GameData::load(dir, game_data_version()).is_ok());
+        changed.version = current.version.clone();
+        changed.save(dir).unwrap();
+        assert!(GameData::load(dir, game_data_version()).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_weapon_slot_layouts() {
+        let current = GameData::current();
+        let weapon = current.weapons[1].weapon;
+        assert_eq!(weapon.slot_counts(), SlotCounts::default());
+        assert!(serde_json::to_value(WeaponBaseStats::get(weapon)).unwrap().get("slots").is_none());
+
+        let mut patched = current.clone();
+        patched.weapons[1].stats.slots = SlotCounts { modules: 8, rolls: 3 };
+        patched.version = patched.content_version();
+        assert_ne!(patched.version, current.version);
+
+        let dir = std::env::temp_dir().join(format!("game_data_slots_test_{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        patched.save(dir).unwrap();
+        let loaded = GameData::load(dir, &patched.version).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let stats = loaded.base_stats(weapon).unwrap();
+        assert_eq!(stats.slots, SlotCounts { modules: 8, rolls: 3 });
+        assert_eq!(loaded.base_stats(current.weapons[0].weapon).unwrap().slots, SlotCounts::default());
+        assert_eq!(OptimizationConfig::default().slots_within(stats.slots), SlotCounts { modules: 8, rolls: 3 });
+        let partial = OptimizationConfig { module_slots: Some(6), ..Default::default() };
+        assert_eq!(partial.slots_within(stats.slots), SlotCounts { modules: 6, rolls: 3 });
+        assert!(data_changes(&current, &loaded)[0].starts_with(&format!("{} slots", weapon)));
+    }
+
+    #[test]
+    fn test_data_changes() {
+        let before = GameData::current();
+        let mut after = GameData::current();
+        after.version = "next".to_string();
+        after.weapons[0].stats.crit_damage += 0.6;
+        after.modules[0].modules.remove(1);
+
+        let changes = data_changes(&before, &after);
+
+        assert!(data_changes(&before, &before).is_empty());
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].starts_with(&format!("{} crit_damage", after.weapons[0].weapon)));
+        assert!(changes[1].starts_with("Removed GeneralRounds module"));
+    }
+}
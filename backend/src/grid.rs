@@ -0,0 +1,321 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use std::path::Path;
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+use crate::calculate::{BuildConstraints, EnemyProfile, OptimizationConfig};
+use crate::components::{ExternalComponent, Reactor};
+use crate::weapons::SlotCounts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub struct BuffSet {
+    pub valby: bool,
+    pub enzo: bool,
+}
+
+/// Parses `none`, `valby`, `enzo` or `valby+enzo`.
+impl FromStr for BuffSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut buffs = BuffSet { valby: false, enzo: false };
+        for buff in s.split('+').map(str::trim) {
+            match buff.to_lowercase().as_str() {
+                "none" => {},
+                "valby" => buffs.valby = true,
+                "enzo" => buffs.enzo = true,
+                _ => return Err(format!("Unknown buff {}, expected none, valby or enzo", buff)),
+            }
+        }
+        Ok(buffs)
+    }
+}
+
+impl std::fmt::Display for BuffSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.valby, self.enzo) {
+            (false, false) => write!(f, "none"),
+            (true, false) => write!(f, "valby"),
+            (false, true) => write!(f, "enzo"),
+            (true, true) => write!(f, "valby+enzo"),
+        }
+    }
+}
+
+/// What the DPS is measured over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString, Display, ToSchema)]
+pub enum Objective {
+    /// Full magazine plus reload cycles
+    #[default]
+    Sustained,
+    /// Gley's infinite ammo window
+    InfiniteAmmo,
+}
+
+impl Objective {
+    pub fn is_default(&self) -> bool {
+        *self == Objective::default()
+    }
+}
+
+/// One buff set, objective and enemy combination of a grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GridConfig {
+    pub buffs: BuffSet,
+    pub objective: Objective,
+    pub enemy: EnemyProfile,
+}
+
+impl GridConfig {
+    pub fn optimization_config(&self) -> OptimizationConfig {
+        OptimizationConfig {
+            valby: self.buffs.valby,
+            enzo: self.buffs.enzo,
+            gley: self.objective == Objective::InfiniteAmmo,
+            enemy: self.enemy,
+            ..Default::default()
+        }
+    }
+}
+
+/// Every configuration and weak point hit chance a weapon is optimized for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct ComputationGrid {
+    pub weak_point_hit_chances: Vec<f64>,
+    pub buffs: Vec<BuffSet>,
+    pub objectives: Vec<Objective>,
+    pub enemies: Vec<EnemyProfile>,
+    /// Applied at every point
+    pub constraints: BuildConstraints,
+    /// Caps every weapon's module slot count
+    pub module_slots: Option<usize>,
+    /// Caps every weapon's roll slot count
+    pub roll_slots: Option<usize>,
+    /// Applied at every point, see `ComponentLoadout`
+    pub reactor: Option<Reactor>,
+    pub external_components: Vec<ExternalComponent>,
+    /// Pick the best reactor substats out of `reactor`'s
+    pub optimize_reactor: bool,
+}
+
+/// Reactor and external components equipped alongside the weapon, as read
+/// by the worker's `--components` flag.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComponentLoadout {
+    pub reactor: Option<Reactor>,
+    pub external_components: Vec<ExternalComponent>,
+}
+
+impl Default for ComputationGrid {
+    fn default() -> Self {
+        ComputationGrid {
+            weak_point_hit_chances: vec![0.25, 0.5, 1.0],
+            buffs: vec![
+                BuffSet { valby: false, enzo: false },
+                BuffSet { valby: true, enzo: false },
+                BuffSet { valby: false, enzo: true },
+            ],
+            objectives: vec![Objective::default()],
+            enemies: vec![EnemyProfile::default()],
+            constraints: BuildConstraints::default(),
+            module_slots: None,
+            roll_slots: None,
+            reactor: None,
+            external_components: Vec::new(),
+            optimize_reactor: false,
+        }
+    }
+}
+
+impl ComputationGrid {
+    /// Reads a grid from a `.toml` or `.json` file. Missing fields keep
+    /// their defaults.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        load_file(path)
+    }
+
+    /// Every buff set, objective and enemy combination.
+    pub fn points(&self) -> Vec<GridConfig> {
+        let mut points = Vec::new();
+        for &enemy in &self.enemies {
+            for &objective in &self.objectives {
+                for &buffs in &self.buffs {
+                    points.push(GridConfig { buffs, objective, enemy });
+                }
+            }
+        }
+        points
+    }
+
+    /// Config of one point, with the grid's constraints, slot overrides and
+    /// components.
+    pub fn optimization_config(&self, point: &GridConfig) -> OptimizationConfig {
+        OptimizationConfig {
+            constraints: self.constraints.clone(),
+            module_slots: self.module_slots,
+            roll_slots: self.roll_slots,
+            reactor: self.reactor.clone(),
+            external_components: self.external_components.clone(),
+            optimize_reactor: self.optimize_reactor,
+            ..point.optimization_config()
+        }
+    }
+
+    /// The point of a command that computes a single build, at `buffs` and
+    /// the grid's only objective and enemy.
+    pub fn single_point(&self, buffs: BuffSet) -> Result<GridConfig, String> {
+        match (&self.objectives[..], &self.enemies[..]) {
+            ([objective], [enemy]) => Ok(GridConfig { buffs, objective: *objective, enemy: *enemy }),
+            _ => Err("A single build needs exactly one objective and one enemy".to_string()),
+        }
+    }
+
+    /// One config per buff set, at the default objective and enemy.
+    pub fn configs(&self) -> Vec<OptimizationConfig> {
+        self.buffs
+            .iter()
+            .map(|&buffs| self.optimization_config(&GridConfig { buffs, objective: Objective::default(), enemy: EnemyProfile::default() }))
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(chance) = self.weak_point_hit_chances.iter().find(|chance| !(0.0..=1.0).contains(*chance)) {
+            return Err(format!("Weak point hit chance {} is outside 0 to 1", chance));
+        }
+        if self.points().is_empty() || self.weak_point_hit_chances.is_empty() {
+            return Err("Grid has no points".to_string());
+        }
+        let full = SlotCounts::default();
+        if self.module_slots.is_some_and(|slots| slots == 0 || slots > full.modules) {
+            return Err(format!("Module slots must be between 1 and {}", full.modules));
+        }
+        if self.roll_slots.is_some_and(|slots| slots > full.rolls) {
+            return Err(format!("Roll slots must be at most {}", full.rolls));
+        }
+        if self.optimize_reactor && self.reactor.is_none() {
+            return Err("Optimizing the reactor needs a reactor with candidate substats".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the grid changes builds in ways a `ResultKey` doesn't record:
+    /// constraints other than the defaults, slot overrides or components.
+    pub fn overrides_builds(&self) -> bool {
+        self.constraints != BuildConstraints::default()
+            || self.module_slots.is_some()
+            || self.roll_slots.is_some()
+            || self.reactor.is_some()
+            || !self.external_components.is_empty()
+            || self.optimize_reactor
+    }
+
+    /// Stored results are keyed by weapon, weak point hit chance, buffs,
+    /// objective and enemy, so results of a grid that overrides builds would
+    /// replace the canonical ones.
+    pub fn validate_storable(&self) -> Result<(), String> {
+        if self.overrides_builds() {
+            return Err("Constraints, slot overrides and components aren't part of the stored results, use them with diff or the optimize commands".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Reads a `.toml` or `.json` file.
+pub fn load_file<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    let value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&contents)?,
+        Some("json") => serde_json::from_str(&contents)?,
+        _ => return Err(format!("{} must be .toml or .json", path.display()).into()),
+    };
+    Ok(value)
+}
+
+/// Splits a comma separated CLI list.
+pub fn parse_list<T: FromStr>(list: &str) -> Result<Vec<T>, String>
+where
+    T::Err: std::fmt::Display,
+{
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse::<T>().map_err(|e| format!("Invalid value {}: {}", item, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapons::Weapon;
+
+    #[test]
+    fn test_grid_spec() {
+        let grid: ComputationGrid = toml::from_str(r#"
+            weak_point_hit_chances = [0.25, 0.5, 0.75, 1.0]
+            objectives = ["Sustained", "InfiniteAmmo"]
+
+            [constraints]
+            excluded_modules = ["Action and Reaction"]
+        "#).unwrap();
+
+        assert_eq!(grid.buffs, ComputationGrid::default().buffs);
+        assert_eq!(grid.points().len(), 6);
+        assert!(grid.points()[3].optimization_config().gley);
+        let config = grid.optimization_config(&grid.points()[0]);
+        assert_eq!(config.constraints.excluded_modules, vec!["Action and Reaction"]);
+        assert_eq!(config.constraints.required_modules, BuildConstraints::default().required_modules);
+        assert!(grid.validate().is_ok());
+        assert_eq!(parse_list::<BuffSet>("none, valby+enzo").unwrap(), vec![
+            BuffSet { valby: false, enzo: false },
+            BuffSet { valby: true, enzo: true },
+        ]);
+        assert!(parse_list::<BuffSet>("gley").is_err());
+        assert!(ComputationGrid { weak_point_hit_chances: vec![1.5], ..Default::default() }.validate().is_err());
+
+        let partial = ComputationGrid { module_slots: Some(6), roll_slots: Some(2), ..Default::default() };
+        assert!(partial.validate().is_ok());
+        let config = partial.optimization_config(&partial.points()[0]);
+        assert_eq!(config.slot_counts(Weapon::Thundercage), SlotCounts { modules: 6, rolls: 2 });
+        assert!(ComputationGrid { module_slots: Some(11), ..Default::default() }.validate().is_err());
+        assert!(ComputationGrid { roll_slots: Some(5), ..Default::default() }.validate().is_err());
+
+        let loadout: ComponentLoadout = toml::from_str(r#"
+            [reactor]
+            weapon_type = "Launcher"
+            substats = [{ bonus_type = "Atk", value = 0.05 }, { bonus_type = "ColossusDamage", value = 100.0 }]
+
+            [[external_components]]
+            name = "Auxiliary Power"
+            substats = [{ bonus_type = "Crit", value = 0.03 }]
+        "#).unwrap();
+        let grid = ComputationGrid { reactor: loadout.reactor, external_components: loadout.external_components, optimize_reactor: true, ..Default::default() };
+        assert!(grid.validate().is_ok());
+        let config = grid.optimization_config(&grid.points()[0]);
+        assert!(config.optimize_reactor);
+        assert_eq!(config.reactor.unwrap().substats.len(), 2);
+        assert_eq!(config.external_components[0].name, "Auxiliary Power");
+        assert!(ComputationGrid { optimize_reactor: true, ..Default::default() }.validate().is_err());
+
+        let valby = BuffSet { valby: true, enzo: false };
+        let infinite_ammo = ComputationGrid { objectives: vec![Objective::InfiniteAmmo], ..Default::default() };
+        assert_eq!(infinite_ammo.single_point(valby).unwrap(), GridConfig { buffs: valby, objective: Objective::InfiniteAmmo, enemy: EnemyProfile::default() });
+        assert!(ComputationGrid { objectives: vec![Objective::Sustained, Objective::InfiniteAmmo], ..Default::default() }.single_point(valby).is_err());
+    }
+
+    #[test]
+    fn test_overrides_are_not_storable() {
+        let grid = ComputationGrid { weak_point_hit_chances: vec![0.1], objectives: vec![Objective::InfiniteAmmo], ..Default::default() };
+        assert!(grid.validate_storable().is_ok());
+
+        let constraints = BuildConstraints { min_magazine: Some(20.0), ..Default::default() };
+        assert!(ComputationGrid { constraints, ..Default::default() }.validate_storable().is_err());
+        assert!(ComputationGrid { module_slots: Some(6), ..Default::default() }.validate_storable().is_err());
+        assert!(ComputationGrid { roll_slots: Some(2), ..Default::default() }.validate_storable().is_err());
+        let external_components = vec![ExternalComponent { name: "Auxiliary Power".to_string(), substats: Vec::new() }];
+        assert!(ComputationGrid { external_components, ..Default::default() }.validate_storable().is_err());
+    }
+}
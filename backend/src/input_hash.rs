@@ -0,0 +1,95 @@
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::calculate::{BuildConstraints, OptimizationConfig};
+use crate::weapons::{Module, Roll, SlotCounts, WeaponBaseStats};
+
+/// Bump whenever a change to the DPS formulas or the search can change a
+/// result for unchanged inputs, so stored results get recomputed.
+pub const ENGINE_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct HashInputs<'a> {
+    engine_version: u32,
+    base_stats: &'a WeaponBaseStats,
+    /// Resolved from the weapon, its progress and the config's overrides
+    slots: SlotCounts,
+    rolls: &'a [Roll],
+    modules: &'a [Module],
+    constraints: &'a BuildConstraints,
+    config: &'a OptimizationConfig,
+    weak_point_hit_chance: f64,
+}
+
+/// Object keys sorted, so maps hash the same regardless of iteration order.
+fn canonical(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(entries.into_iter().map(|(key, value)| (key, canonical(value))).collect())
+        },
+        Value::Array(values) => Value::Array(values.into_iter().map(canonical).collect()),
+        value => value,
+    }
+}
+
+/// Hex SHA-256 of the canonical JSON form of `value`.
+pub fn hash_json<T: Serialize>(value: &T) -> String {
+    let value = canonical(serde_json::to_value(value).expect("Hash inputs serialize to JSON"));
+    hex::encode(Sha256::digest(value.to_string().as_bytes()))
+}
+
+/// Hex SHA-256 of everything a stored result depends on. Results whose
+/// stored hash matches don't need to be recomputed.
+pub fn input_hash(
+    base_stats: &WeaponBaseStats,
+    slots: SlotCounts,
+    rolls: &[Roll],
+    modules: &[Module],
+    config: &OptimizationConfig,
+    weak_point_hit_chance: f64,
+) -> String {
+    let inputs = HashInputs {
+        engine_version: ENGINE_VERSION,
+        base_stats,
+        slots,
+        rolls,
+        modules,
+        constraints: &config.constraints,
+        config,
+        weak_point_hit_chance,
+    };
+    hash_json(&inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapons::{Weapon, ModuleType, get_available_modules, get_available_rolls};
+
+    #[test]
+    fn test_input_hash() {
+        let base_stats = WeaponBaseStats::get(Weapon::Thundercage);
+        let rolls = get_available_rolls(base_stats.weapon_type);
+        let mut modules = get_available_modules(base_stats.bullet_type, base_stats.weapon_type);
+        let mut config = OptimizationConfig::default();
+        config.constraints.max_per_category.insert(ModuleType::Crit, 1);
+        config.constraints.max_per_category.insert(ModuleType::Atk, 2);
+        config.constraints.max_per_category.insert(ModuleType::FireRate, 1);
+
+        let slots = SlotCounts::default();
+        let hash = input_hash(&base_stats, slots, &rolls, &modules, &config, 0.5);
+        assert_eq!(hash, input_hash(&base_stats, slots, &rolls, &modules, &config.clone(), 0.5));
+        assert_ne!(hash, input_hash(&base_stats, slots, &rolls, &modules, &config, 1.0));
+        assert_ne!(hash, input_hash(&base_stats, SlotCounts { modules: 8, ..slots }, &rolls, &modules, &config, 0.5));
+
+        let mut constrained = config.clone();
+        constrained.constraints.excluded_modules.push(modules[1].name.clone());
+        assert_ne!(hash, input_hash(&base_stats, slots, &rolls, &modules, &constrained, 0.5));
+
+        modules[0].effects[0].value += 0.01;
+        assert_ne!(hash, input_hash(&base_stats, slots, &rolls, &modules, &config, 0.5));
+    }
+}
@@ -0,0 +1,336 @@
+use futures::TryStreamExt;
+use mongodb::bson::{self, doc, oid::ObjectId, Document};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+use utoipa::ToSchema;
+
+use crate::grid::ComputationGrid;
+
+pub const JOBS_COLLECTION: &str = "worker_jobs";
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Most jobs `list_jobs` returns at once.
+pub const MAX_LIST_LIMIT: i64 = 500;
+
+/// Wait before the first retry of a failed job, doubled on every retry
+/// after that up to `MAX_RETRY_BACKOFF_MILLIS`.
+pub const RETRY_BACKOFF_MILLIS: i64 = 30_000;
+pub const MAX_RETRY_BACKOFF_MILLIS: i64 = 30 * 60_000;
+
+/// What a job recomputes, mirrors the worker subcommands.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type")]
+pub enum JobKind {
+    UpdateWeapons { names: Vec<String> },
+    UpdateWeaponType { weapon_type: String },
+    UpdateBulletType { bullet_type: String },
+    UpdateAll,
+    SweepWeakPoint { names: Vec<String>, step: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, ToSchema)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A queued unit of worker work. Timestamps are unix milliseconds.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Job {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub kind: JobKind,
+    pub grid: ComputationGrid,
+    /// Recompute results even if their inputs haven't changed
+    #[serde(default)]
+    pub force: bool,
+    /// Higher runs first
+    pub priority: i32,
+    pub status: JobStatus,
+    pub attempts: u32,
+    pub max_attempts: u32,
+    pub lease_owner: Option<String>,
+    pub lease_expires_at: Option<i64>,
+    pub heartbeat_at: Option<i64>,
+    /// A failed job isn't retried before this
+    #[serde(default)]
+    pub retry_at: Option<i64>,
+    pub created_at: i64,
+    /// First time the job was claimed
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl Job {
+    pub fn new(kind: JobKind, grid: ComputationGrid, priority: i32, max_attempts: u32) -> Self {
+        Job {
+            id: ObjectId::new().to_hex(),
+            kind,
+            grid,
+            force: false,
+            priority,
+            status: JobStatus::Queued,
+            attempts: 0,
+            max_attempts: max_attempts.max(1),
+            lease_owner: None,
+            lease_expires_at: None,
+            heartbeat_at: None,
+            retry_at: None,
+            created_at: now_millis(),
+            started_at: None,
+            finished_at: None,
+            error: None,
+        }
+    }
+}
+
+pub fn now_millis() -> i64 {
+    bson::DateTime::now().timestamp_millis()
+}
+
+/// How long to wait before retrying a job that failed on attempt `attempts`.
+pub fn retry_backoff_millis(attempts: u32) -> i64 {
+    let doublings = attempts.saturating_sub(1).min(16);
+    (RETRY_BACKOFF_MILLIS << doublings).min(MAX_RETRY_BACKOFF_MILLIS)
+}
+
+fn jobs(db: &mongodb::Database) -> mongodb::Collection<Job> {
+    db.collection::<Job>(JOBS_COLLECTION)
+}
+
+pub async fn enqueue(db: &mongodb::Database, job: &Job) -> Result<(), mongodb::error::Error> {
+    jobs(db).insert_one(job, None).await?;
+    Ok(())
+}
+
+pub async fn get_job(db: &mongodb::Database, id: &str) -> Result<Option<Job>, mongodb::error::Error> {
+    jobs(db).find_one(doc! { "_id": id }, None).await
+}
+
+/// Most recent jobs first, optionally only those with the given status.
+pub async fn list_jobs(db: &mongodb::Database, status: Option<JobStatus>, limit: i64) -> Result<Vec<Job>, mongodb::error::Error> {
+    let filter = status.map(|status| doc! { "status": status.to_string() });
+    let options = FindOptions::builder().sort(doc! { "created_at": -1 }).limit(limit).build();
+    jobs(db).find(filter, options).await?.try_collect().await
+}
+
+/// Jobs a worker may take: queued ones past their retry backoff, and
+/// running ones whose worker stopped heartbeating, as long as they have
+/// attempts left.
+fn claimable_filter(now: i64) -> Document {
+    doc! {
+        "$or": [
+            { "status": JobStatus::Queued.to_string(), "$or": [{ "retry_at": null }, { "retry_at": { "$lte": now } }] },
+            { "status": JobStatus::Running.to_string(), "lease_expires_at": { "$lt": now } },
+        ],
+        "$expr": { "$lt": ["$attempts", "$max_attempts"] },
+    }
+}
+
+/// Atomically leases the highest priority claimable job to `worker_id`.
+/// `started_at` keeps the time of the first claim.
+pub async fn claim_next(db: &mongodb::Database, worker_id: &str, lease_millis: i64) -> Result<Option<Job>, mongodb::error::Error> {
+    let now = now_millis();
+    // A pipeline update, so `started_at` can fall back to its current value
+    let update = vec![doc! {
+        "$set": {
+            "status": JobStatus::Running.to_string(),
+            "lease_owner": { "$literal": worker_id },
+            "lease_expires_at": now + lease_millis,
+            "heartbeat_at": now,
+            "started_at": { "$ifNull": ["$started_at", now] },
+            "attempts": { "$add": ["$attempts", 1] },
+        },
+    }];
+    let options = FindOneAndUpdateOptions::builder()
+        .sort(doc! { "priority": -1, "created_at": 1 })
+        .return_document(ReturnDocument::After)
+        .build();
+    jobs(db).find_one_and_update(claimable_filter(now), update, options).await
+}
+
+/// Extends the lease. Returns false if another worker has taken the job over.
+pub async fn heartbeat(db: &mongodb::Database, id: &str, worker_id: &str, lease_millis: i64) -> Result<bool, mongodb::error::Error> {
+    let now = now_millis();
+    let result = jobs(db)
+        .update_one(
+            doc! { "_id": id, "lease_owner": worker_id, "status": JobStatus::Running.to_string() },
+            doc! { "$set": { "lease_expires_at": now + lease_millis, "heartbeat_at": now } },
+            None,
+        )
+        .await?;
+    Ok(result.matched_count > 0)
+}
+
+/// Records the outcome of a run. Failed runs go back in the queue, after
+/// `retry_backoff_millis`, until the job is out of attempts.
+pub async fn finish(db: &mongodb::Database, job: &Job, worker_id: &str, outcome: Result<(), String>) -> Result<(), mongodb::error::Error> {
+    let now = now_millis();
+    let (status, error) = match outcome {
+        Ok(()) => (JobStatus::Succeeded, None),
+        Err(e) if job.attempts < job.max_attempts => (JobStatus::Queued, Some(e)),
+        Err(e) => (JobStatus::Failed, Some(e)),
+    };
+    let finished_at = (status != JobStatus::Queued).then_some(now);
+    let retry_at = (status == JobStatus::Queued).then(|| now + retry_backoff_millis(job.attempts));
+    jobs(db)
+        .update_one(
+            doc! { "_id": &job.id, "lease_owner": worker_id },
+            doc! { "$set": {
+                "status": status.to_string(),
+                "error": error,
+                "finished_at": finished_at,
+                "retry_at": retry_at,
+                "lease_owner": bson::Bson::Null,
+                "lease_expires_at": bson::Bson::Null,
+            } },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Marks running jobs that lost their worker on their last attempt as failed,
+/// since `claim_next` will never pick them up again.
+pub async fn fail_abandoned(db: &mongodb::Database) -> Result<u64, mongodb::error::Error> {
+    let now = now_millis();
+    let result = jobs(db)
+        .update_many(
+            doc! {
+                "status": JobStatus::Running.to_string(),
+                "lease_expires_at": { "$lt": now },
+                "$expr": { "$gte": ["$attempts", "$max_attempts"] },
+            },
+            doc! { "$set": {
+                "status": JobStatus::Failed.to_string(),
+                "error": "Worker stopped heartbeating",
+                "finished_at": now,
+                "lease_owner": bson::Bson::Null,
+                "lease_expires_at": bson::Bson::Null,
+            } },
+            None,
+        )
+        .await?;
+    Ok(result.modified_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_round_trip() {
+        let mut grid = ComputationGrid::default();
+        grid.constraints.max_per_category.insert(crate::weapons::ModuleType::Crit, 2);
+        let job = Job::new(
+            JobKind::UpdateWeapons { names: vec!["Thundercage".to_string()] },
+            grid,
+            5,
+            0,
+        );
+
+        let document = bson::to_document(&job).unwrap();
+        let decoded: Job = bson::from_document(document.clone()).unwrap();
+
+        assert_eq!(document.get_str("status").unwrap(), "Queued");
+        assert_eq!(document.get_document("kind").unwrap().get_str("type").unwrap(), "UpdateWeapons");
+        assert_eq!(decoded.kind, job.kind);
+        assert_eq!(decoded.grid, job.grid);
+        assert_eq!(decoded.max_attempts, 1);
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        assert_eq!(retry_backoff_millis(1), RETRY_BACKOFF_MILLIS);
+        assert_eq!(retry_backoff_millis(2), 2 * RETRY_BACKOFF_MILLIS);
+        assert_eq!(retry_backoff_millis(3), 4 * RETRY_BACKOFF_MILLIS);
+        assert_eq!(retry_backoff_millis(40), MAX_RETRY_BACKOFF_MILLIS);
+    }
+
+    /// A scratch database on the server at `MONGODB_TEST_URI`. The queue
+    /// tests are ignored by default, run them with
+    /// `cargo test jobs:: -- --ignored`.
+    async fn test_db(name: &str) -> mongodb::Database {
+        let uri = std::env::var("MONGODB_TEST_URI").expect("MONGODB_TEST_URI must be set");
+        let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+        let db = client.database(&format!("weapon_optimizer_test_{}_{}", name, std::process::id()));
+        db.drop(None).await.unwrap();
+        db
+    }
+
+    fn job(priority: i32, max_attempts: u32) -> Job {
+        Job::new(JobKind::UpdateAll, ComputationGrid::default(), priority, max_attempts)
+    }
+
+    async fn expire_lease(db: &mongodb::Database, id: &str) {
+        jobs(db).update_one(doc! { "_id": id }, doc! { "$set": { "lease_expires_at": now_millis() - 1 } }, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs MONGODB_TEST_URI"]
+    async fn test_claim_and_lease_expiry() {
+        let db = test_db("claim").await;
+        let (low, high) = (job(0, 3), job(5, 3));
+        enqueue(&db, &low).await.unwrap();
+        enqueue(&db, &high).await.unwrap();
+
+        let claimed = claim_next(&db, "a", 60_000).await.unwrap().unwrap();
+        assert_eq!(claimed.id, high.id);
+        assert_eq!((claimed.status, claimed.attempts, claimed.lease_owner.as_deref()), (JobStatus::Running, 1, Some("a")));
+        let started_at = claimed.started_at.unwrap();
+        assert_eq!(claim_next(&db, "b", 60_000).await.unwrap().unwrap().id, low.id);
+        assert!(claim_next(&db, "c", 60_000).await.unwrap().is_none());
+
+        // A live lease can't be taken over, an expired one can
+        assert!(heartbeat(&db, &high.id, "a", 60_000).await.unwrap());
+        expire_lease(&db, &high.id).await;
+        let reclaimed = claim_next(&db, "c", 60_000).await.unwrap().unwrap();
+        assert_eq!((reclaimed.id.as_str(), reclaimed.attempts), (high.id.as_str(), 2));
+        assert_eq!(reclaimed.started_at, Some(started_at));
+        assert!(!heartbeat(&db, &high.id, "a", 60_000).await.unwrap());
+
+        // A failed run is retried only after its backoff
+        finish(&db, &reclaimed, "c", Err("boom".to_string())).await.unwrap();
+        let retried = get_job(&db, &high.id).await.unwrap().unwrap();
+        assert_eq!(retried.status, JobStatus::Queued);
+        assert!(retried.retry_at.unwrap() >= now_millis() + retry_backoff_millis(2) - 1_000);
+        assert!(claim_next(&db, "c", 60_000).await.unwrap().is_none());
+        jobs(&db).update_one(doc! { "_id": &high.id }, doc! { "$set": { "retry_at": now_millis() - 1 } }, None).await.unwrap();
+        let last = claim_next(&db, "c", 60_000).await.unwrap().unwrap();
+        assert_eq!((last.attempts, last.started_at), (3, Some(started_at)));
+        finish(&db, &last, "c", Ok(())).await.unwrap();
+        assert_eq!(get_job(&db, &high.id).await.unwrap().unwrap().status, JobStatus::Succeeded);
+
+        db.drop(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs MONGODB_TEST_URI"]
+    async fn test_fail_abandoned() {
+        let db = test_db("abandoned").await;
+        let (last_attempt, retryable) = (job(0, 1), job(0, 2));
+        enqueue(&db, &last_attempt).await.unwrap();
+        enqueue(&db, &retryable).await.unwrap();
+        claim_next(&db, "a", 60_000).await.unwrap().unwrap();
+        claim_next(&db, "a", 60_000).await.unwrap().unwrap();
+
+        // Live leases aren't touched
+        assert_eq!(fail_abandoned(&db).await.unwrap(), 0);
+        expire_lease(&db, &last_attempt.id).await;
+        expire_lease(&db, &retryable.id).await;
+        assert_eq!(fail_abandoned(&db).await.unwrap(), 1);
+
+        let failed = get_job(&db, &last_attempt.id).await.unwrap().unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert!(failed.lease_owner.is_none() && failed.finished_at.is_some());
+        // The other job still has an attempt left, so it's reclaimed instead
+        assert_eq!(claim_next(&db, "b", 60_000).await.unwrap().unwrap().id, retryable.id);
+
+        db.drop(None).await.unwrap();
+    }
+}
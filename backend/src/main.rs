@@ -1,25 +1,58 @@
-use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, ResponseError};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Next};
 use actix_cors::Cors;
 use mongodb::bson::doc;
 use mongodb::{Client, options::ClientOptions, options::ServerApi, options::ServerApiVersion};
 use serde::{Deserialize, Serialize};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
 use dotenv::dotenv;
 use std::env;
-use log::{error, info};
+use log::{error, info, warn};
 use futures::TryStreamExt;
-use serde_json::json;
 
 pub mod weapons;
 pub mod calculate;
 pub mod modules;
+pub mod components;
+pub mod progression;
+pub mod game_data;
+pub mod grid;
+pub mod jobs;
+pub mod input_hash;
+pub mod sweep;
+pub mod store;
+pub mod admin_auth;
+pub mod result_key;
+pub mod catalog;
+pub mod tiers;
+pub mod api_error;
+pub mod share_code;
+pub mod export;
 
-use crate::weapons::Weapon;
+use crate::weapons::{BulletType, Weapon, WeaponBaseStats, WeaponType};
+use crate::game_data::game_data_version;
 use crate::calculate::OptimizationResult;
+use crate::sweep::{WeaponCrossover, WeaponSweep, weapon_crossovers};
+use crate::grid::{BuffSet, ComputationGrid, Objective};
+use crate::result_key::ResultKey;
+use crate::catalog::{ModuleFilter, ModuleSet, WeaponDetails, module_catalog};
+use crate::tiers::{TierGrouping, TierList, TierThresholds, tier_list};
+use crate::share_code::{ExpandedBuild, SharedBuild};
+use crate::export::ExportFormat;
+use crate::calculate::EnemyProfile;
+use crate::store::{ResultRecord, ResultStore, StoreError, StoreTarget};
+use crate::jobs::{Job, JobKind, JobStatus, DEFAULT_MAX_ATTEMPTS, MAX_LIST_LIMIT};
+use crate::api_error::{ApiError, ErrorBody, parse_weapon, validate_buffs, validate_weak_point_chance};
+use crate::admin_auth::{AdminAuth, AdminCredentials, AuthError, KEY_HEADER, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 struct OptimizationRequest {
     weapon: String,
     weak_point_hit_chance: f64,
@@ -27,61 +60,120 @@ struct OptimizationRequest {
     enzo: bool,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-struct WeaponResultDocument {
-    weapon: String,
-    weak_point_hit_chance: f64,
-    valby: bool,
-    enzo: bool,
-    #[serde(flatten)]
-    result: OptimizationResult,
+/// Results of the current game data version win over unversioned ones;
+/// results of other versions are only kept for history.
+fn insert_result(results: &mut HashMap<ResultKey, ResultRecord>, doc: ResultRecord) {
+    let Some(key) = doc.key() else {
+        return;
+    };
+    match doc.data_version.as_str() {
+        version if version == game_data_version() => {
+            results.insert(key, doc);
+        },
+        "" => {
+            results.entry(key).or_insert(doc);
+        },
+        _ => {},
+    }
 }
 
 struct AppState {
-    db: mongodb::Database,
-    weapon_results: Arc<RwLock<HashMap<String, WeaponResultDocument>>>,
+    /// `None` when MongoDB isn't configured, jobs and sweeps are unavailable.
+    db: Option<mongodb::Database>,
+    store: Arc<dyn ResultStore>,
+    weapon_results: Arc<RwLock<HashMap<ResultKey, ResultRecord>>>,
+    /// Shared by every server worker so a nonce can't be replayed against another
+    admin_auth: Arc<AdminAuth>,
+}
+
+impl AppState {
+    fn mongo(&self) -> Result<&mongodb::Database, ApiError> {
+        self.db.as_ref().ok_or_else(|| ApiError::Unavailable("MongoDB isn't configured on this server".to_string()))
+    }
 }
 
 async fn index() -> HttpResponse {
     HttpResponse::Ok().body("Weapon Optimizer API is running")
 }
 
+/// Best build of a weapon, from the precomputed results.
+#[utoipa::path(
+    post,
+    path = "/optimize",
+    request_body = OptimizationRequest,
+    responses(
+        (status = 200, description = "Optimal build", body = OptimizationResult),
+        (status = 400, description = "Invalid weak point hit chance or conflicting buffs", body = ErrorBody),
+        (status = 404, description = "Unknown weapon or no result for this configuration", body = ErrorBody),
+    ),
+)]
 async fn optimize_weapon_handler(
     req: web::Json<OptimizationRequest>,
     data: web::Data<AppState>,
-) -> HttpResponse {
-    let cache_key = format!("{}_{}_{}_{}",
-        req.weapon,
-        req.weak_point_hit_chance,
-        req.valby,
-        req.enzo
-    );
+) -> Result<HttpResponse, ApiError> {
+    let weapon = parse_weapon(&req.weapon)?;
+    let weak_point_hit_chance = validate_weak_point_chance(req.weak_point_hit_chance)?;
+    let buffs = validate_buffs(BuffSet { valby: req.valby, enzo: req.enzo })?;
+    let cache_key = ResultKey::served(weapon, weak_point_hit_chance, buffs);
 
     let weapon_results = data.weapon_results.read().await;
-    if let Some(doc) = weapon_results.get(&cache_key) {
-        return HttpResponse::Ok().json(&doc.result);
+    match weapon_results.get(&cache_key) {
+        Some(doc) => Ok(HttpResponse::Ok().json(&doc.result)),
+        None => Err(ApiError::NotFound(format!("No result for {}", cache_key))),
     }
-
-    HttpResponse::NotFound().finish()
 }
 
+/// Names of every weapon.
+#[utoipa::path(get, path = "/weapons", responses((status = 200, description = "Weapon names", body = Vec<String>)))]
 async fn get_weapons() -> HttpResponse {
     let weapons: Vec<String> = Weapon::all().iter().map(|w| w.to_string()).collect();
     info!("Returning {} weapons", weapons.len());
     HttpResponse::Ok().json(weapons)
 }
 
+/// Base stats, rolls and modules of a weapon.
+#[utoipa::path(
+    get,
+    path = "/weapons/{name}",
+    params(("name" = String, Path, description = "Weapon name as listed by /weapons")),
+    responses(
+        (status = 200, description = "Weapon details", body = WeaponDetails),
+        (status = 404, description = "Unknown weapon", body = ErrorBody),
+    ),
+)]
+async fn get_weapon_details(name: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let weapon = parse_weapon(&name)?;
+    Ok(HttpResponse::Ok().json(WeaponDetails::get(weapon)))
+}
+
+/// Module sets, optionally filtered by bullet, weapon and module type.
+#[utoipa::path(
+    get,
+    path = "/modules",
+    params(ModuleFilter),
+    responses(
+        (status = 200, description = "Matching module sets", body = Vec<ModuleSet>),
+        (status = 400, description = "Unknown bullet, weapon or module type", body = ErrorBody),
+    ),
+)]
+async fn get_modules(query: web::Query<ModuleFilter>) -> HttpResponse {
+    HttpResponse::Ok().json(module_catalog(&query))
+}
+
+/// Every served result, by weapon and then by `{weak point hit chance}_{none|valby|enzo}`.
+#[utoipa::path(
+    get,
+    path = "/weapon-data",
+    responses((status = 200, description = "Results of every weapon", body = HashMap<String, HashMap<String, OptimizationResult>>)),
+)]
 async fn get_weapon_data(data: web::Data<AppState>) -> HttpResponse {
     let weapon_results = data.weapon_results.read().await;
     let weapon_data: HashMap<String, HashMap<String, OptimizationResult>> = weapon_results
         .iter()
+        .filter(|(key, _)| key.objective.is_default() && key.enemy.is_default())
         .map(|(key, doc)| {
-            let parts: Vec<&str> = key.split('_').collect();
-            let weapon = parts[0].to_string();
-            let hit_chance = parts[1].to_string();
-            let valby = parts[2] == "true";
-            let enzo = parts[3] == "true";
-            (weapon, hit_chance, valby, enzo, doc.result.clone())
+            let hit_chance = key.weak_point_hit_chance().to_string();
+            (key.weapon.to_string(), hit_chance, key.buffs.valby, key.buffs.enzo, doc.result.clone())
         })
         .fold(HashMap::new(), |mut acc, (weapon, hit_chance, valby, enzo, result)| {
             let mode = if valby {
@@ -94,7 +186,7 @@ async fn get_weapon_data(data: web::Data<AppState>) -> HttpResponse {
             let key = format!("{}_{}", hit_chance, mode);
             
             acc.entry(weapon)
-                .or_insert_with(|| HashMap::new())
+                .or_default()
                 .insert(key, result);
             acc
         });
@@ -102,44 +194,217 @@ async fn get_weapon_data(data: web::Data<AppState>) -> HttpResponse {
     HttpResponse::Ok().json(weapon_data)
 }
 
-async fn refresh_weapon_results(data: web::Data<AppState>) -> HttpResponse {
-    match load_all_weapon_results(&data.db).await {
-        Ok(new_results) => {
-            let mut weapon_results = data.weapon_results.write().await;
-            *weapon_results = new_results;
-            HttpResponse::Ok().json(json!({"status": "Weapon results refreshed successfully"}))
-        },
-        Err(e) => {
-            error!("Failed to refresh weapon results: {}", e);
-            HttpResponse::InternalServerError().json(json!({"status": "Failed to refresh weapon results"}))
-        }
-    }
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TierListQuery {
+    #[serde(default)]
+    group_by: TierGrouping,
+    #[serde(default)]
+    objective: Objective,
+    #[serde(default)]
+    enemy: EnemyProfile,
+    /// none, valby, enzo or valby+enzo
+    buffs: Option<String>,
+    #[serde(default = "default_tier_hit_chance")]
+    weak_point_hit_chance: f64,
+    /// z_score, percent_of_top or clusters
+    method: Option<String>,
+    /// Comma separated lower bounds of tiers S through D
+    cutoffs: Option<String>,
+}
+
+fn default_tier_hit_chance() -> f64 {
+    1.0
 }
 
-async fn load_all_weapon_results(db: &mongodb::Database) -> Result<HashMap<String, WeaponResultDocument>, mongodb::error::Error> {
-    let collection = db.collection::<WeaponResultDocument>("weapon_results");
-    let mut cursor = collection.find(None, None).await?;
+/// Weapons ranked and tiered within their weapon or bullet type.
+#[utoipa::path(
+    get,
+    path = "/tier-list",
+    params(TierListQuery),
+    responses(
+        (status = 200, description = "Tier list", body = TierList),
+        (status = 400, description = "Invalid buffs, weak point hit chance or thresholds", body = ErrorBody),
+    ),
+)]
+async fn get_tier_list(
+    data: web::Data<AppState>,
+    query: web::Query<TierListQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let buffs = query.buffs.as_deref().unwrap_or("none").parse::<BuffSet>().map_err(ApiError::InvalidRequest)?;
+    let buffs = validate_buffs(buffs)?;
+    let weak_point_hit_chance = validate_weak_point_chance(query.weak_point_hit_chance)?;
+    let thresholds = TierThresholds::parse(query.method.as_deref().unwrap_or("z_score"), query.cutoffs.as_deref())
+        .map_err(ApiError::InvalidRequest)?;
 
-    let mut results = HashMap::new();
-    while let Some(doc) = cursor.try_next().await? {
-        let key = format!("{}_{}_{}_{}",
-            doc.weapon,
-            doc.weak_point_hit_chance,
-            doc.valby,
-            doc.enzo
-        );
-        results.insert(key, doc);
-    }
+    let weapon_results = data.weapon_results.read().await;
+    let dps: HashMap<Weapon, f64> = Weapon::all()
+        .iter()
+        .filter_map(|&weapon| {
+            let key = ResultKey::new(weapon, weak_point_hit_chance, buffs, query.objective, query.enemy);
+            weapon_results.get(&key).map(|doc| (weapon, doc.result.max_dps))
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(TierList {
+        group_by: query.group_by,
+        objective: query.objective,
+        enemy: query.enemy,
+        buffs: buffs.to_string(),
+        weak_point_hit_chance,
+        groups: tier_list(&dps, query.group_by, &thresholds),
+        thresholds,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportQuery {
+    weapon: String,
+    #[serde(default = "default_tier_hit_chance")]
+    weak_point_hit_chance: f64,
+    /// none, valby or enzo
+    buffs: Option<String>,
+    #[serde(default)]
+    objective: Objective,
+    #[serde(default)]
+    enemy: EnemyProfile,
+    #[serde(default)]
+    #[param(inline)]
+    format: ExportFormat,
+}
+
+/// A stored build rendered as Markdown, plain text or CSV.
+#[utoipa::path(
+    get,
+    path = "/export",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "The rendered build", content(
+            (String = "text/markdown"),
+            (String = "text/plain"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "Invalid format, buffs or weak point hit chance", body = ErrorBody),
+        (status = 404, description = "Unknown weapon or no stored result", body = ErrorBody),
+    ),
+)]
+async fn export_build(
+    data: web::Data<AppState>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let weapon = parse_weapon(&query.weapon)?;
+    let weak_point_hit_chance = validate_weak_point_chance(query.weak_point_hit_chance)?;
+    let buffs = query.buffs.as_deref().unwrap_or("none").parse::<BuffSet>().map_err(ApiError::InvalidRequest)?;
+    let buffs = validate_buffs(buffs)?;
+    let key = ResultKey::new(weapon, weak_point_hit_chance, buffs, query.objective, query.enemy);
+
+    let weapon_results = data.weapon_results.read().await;
+    let doc = weapon_results.get(&key).ok_or_else(|| ApiError::NotFound(format!("No result for {}", key)))?;
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .body(query.format.render(&key, &doc.result)))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ShareCodeRequest {
+    weapon: String,
+    weak_point_hit_chance: f64,
+    #[serde(default)]
+    valby: bool,
+    #[serde(default)]
+    enzo: bool,
+    #[serde(default)]
+    objective: Objective,
+    #[serde(default)]
+    enemy: EnemyProfile,
+    /// Only the rolls and modules are shared
+    build: OptimizationResult,
+}
 
+#[derive(Serialize, ToSchema)]
+struct ShareCodeResponse {
+    code: String,
+}
+
+/// Encodes a build into a URL safe share code.
+#[utoipa::path(
+    post,
+    path = "/share-codes",
+    request_body = ShareCodeRequest,
+    responses(
+        (status = 200, description = "Share code of the build", body = ShareCodeResponse),
+        (status = 400, description = "Invalid configuration, rolls or modules", body = ErrorBody),
+        (status = 404, description = "Unknown weapon", body = ErrorBody),
+    ),
+)]
+async fn create_share_code(request: web::Json<ShareCodeRequest>) -> Result<HttpResponse, ApiError> {
+    let request = request.into_inner();
+    let build = SharedBuild {
+        weapon: parse_weapon(&request.weapon)?,
+        weak_point_hit_chance: validate_weak_point_chance(request.weak_point_hit_chance)?,
+        buffs: validate_buffs(BuffSet { valby: request.valby, enzo: request.enzo })?,
+        objective: request.objective,
+        enemy: request.enemy,
+        rolls: request.build.best_rolls,
+        modules: request.build.best_modules.into_iter().map(|(module, _)| module).collect(),
+    };
+    Ok(HttpResponse::Ok().json(ShareCodeResponse { code: build.encode()? }))
+}
+
+/// Decodes a share code and recomputes the build's DPS.
+#[utoipa::path(
+    get,
+    path = "/share-codes/{code}",
+    params(("code" = String, Path, description = "Code returned by POST /share-codes")),
+    responses(
+        (status = 200, description = "The shared build", body = ExpandedBuild),
+        (status = 400, description = "Malformed or outdated share code", body = ErrorBody),
+    ),
+)]
+async fn expand_share_code(code: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let code = code.into_inner();
+    let build = SharedBuild::decode(&code)?;
+    Ok(HttpResponse::Ok().json(build.expand(code)))
+}
+
+/// Body of admin routes that only report they are done.
+#[derive(Serialize, Deserialize, ToSchema)]
+struct StatusResponse {
+    status: String,
+}
+
+/// Reloads every result from the result store.
+#[utoipa::path(
+    post,
+    path = "/refresh-results",
+    security(("admin_key" = []), ("admin_timestamp" = [], "admin_nonce" = [], "admin_signature" = [])),
+    responses(
+        (status = 200, description = "Results reloaded", body = StatusResponse),
+        (status = 400, description = "Malformed admin timestamp", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin credentials", body = ErrorBody),
+        (status = 500, description = "Results couldn't be read", body = ErrorBody),
+    ),
+)]
+async fn refresh_weapon_results(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let new_results = load_all_weapon_results(data.store.as_ref())
+        .await
+        .map_err(|e| ApiError::internal("Failed to refresh weapon results", e))?;
+    let mut weapon_results = data.weapon_results.write().await;
+    *weapon_results = new_results;
+    Ok(HttpResponse::Ok().json(StatusResponse { status: "Weapon results refreshed successfully".to_string() }))
+}
+
+async fn load_all_weapon_results(store: &dyn ResultStore) -> Result<HashMap<ResultKey, ResultRecord>, StoreError> {
+    let mut results = HashMap::new();
+    fetch_all_results(store, &mut results).await?;
     Ok(results)
 }
 
-async fn create_mongo_client() -> mongodb::error::Result<Client> {
-    dotenv().ok();
-    let mongodb_uri = env::var("MONGODB_URI").expect("MONGODB_URI must be set");
+async fn create_mongo_client(mongodb_uri: &str) -> mongodb::error::Result<Client> {
     println!("Connecting to MongoDB...");
     
-    let mut client_options = ClientOptions::parse(&mongodb_uri).await?;
+    let mut client_options = ClientOptions::parse(mongodb_uri).await?;
     
     // Set the server API version to 1
     let server_api = ServerApi::builder().version(ServerApiVersion::V1).build();
@@ -159,101 +424,404 @@ async fn create_mongo_client() -> mongodb::error::Result<Client> {
     Ok(client)
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct WeakPointSweepQuery {
+    /// Comma separated weapon names, all weapons if absent
+    weapons: Option<String>,
+    #[serde(default)]
+    valby: bool,
+    #[serde(default)]
+    enzo: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct WeakPointSweepResponse {
+    sweeps: Vec<WeaponSweep>,
+    weapon_crossovers: Vec<WeaponCrossover>,
+}
+
+/// Stored weak point sweeps and where weapons' DPS curves cross.
+#[utoipa::path(
+    get,
+    path = "/wp-sweep",
+    params(WeakPointSweepQuery),
+    responses(
+        (status = 200, description = "Sweeps and crossovers", body = WeakPointSweepResponse),
+        (status = 400, description = "Conflicting buffs", body = ErrorBody),
+        (status = 404, description = "Unknown weapon", body = ErrorBody),
+        (status = 500, description = "Sweeps couldn't be read", body = ErrorBody),
+        (status = 503, description = "MongoDB isn't configured", body = ErrorBody),
+    ),
+)]
+async fn get_weak_point_sweep(
+    data: web::Data<AppState>,
+    query: web::Query<WeakPointSweepQuery>,
+) -> Result<HttpResponse, ApiError> {
+    validate_buffs(BuffSet { valby: query.valby, enzo: query.enzo })?;
+    let mut filter = doc! { "valby": query.valby, "enzo": query.enzo, "data_version": game_data_version() };
+    if let Some(weapons) = &query.weapons {
+        let names = weapons
+            .split(',')
+            .map(|name| parse_weapon(name.trim()).map(|weapon| weapon.to_string()))
+            .collect::<Result<Vec<String>, ApiError>>()?;
+        filter.insert("weapon", doc! { "$in": names });
+    }
+    let collection = data.mongo()?.collection::<WeaponSweep>("wp_sweeps");
+
+    let sweeps: Vec<WeaponSweep> = collection
+        .find(filter, None)
+        .await
+        .map_err(|e| ApiError::internal("Failed to query weak point sweeps", e))?
+        .try_collect()
+        .await
+        .map_err(|e| ApiError::internal("Failed to read weak point sweeps", e))?;
+
+    let weapon_crossovers = weapon_crossovers(&sweeps);
+    Ok(HttpResponse::Ok().json(WeakPointSweepResponse { sweeps, weapon_crossovers }))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Weapon Optimizer API"),
+    paths(
+        get_weapons,
+        get_weapon_details,
+        get_modules,
+        optimize_weapon_handler,
+        get_weapon_data,
+        get_tier_list,
+        get_weak_point_sweep,
+        create_share_code,
+        expand_share_code,
+        export_build,
+        enqueue_job,
+        list_jobs,
+        get_job,
+        refresh_weapon_results,
+        clear_cache_and_fetch,
+    ),
+    modifiers(&AdminSecurity),
+)]
+struct ApiDoc;
+
+/// Admin routes take either the API key or all three signature headers.
+struct AdminSecurity;
+
+impl Modify for AdminSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for (name, header) in [
+            ("admin_key", KEY_HEADER),
+            ("admin_timestamp", TIMESTAMP_HEADER),
+            ("admin_nonce", NONCE_HEADER),
+            ("admin_signature", SIGNATURE_HEADER),
+        ] {
+            components.add_security_scheme(name, SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(header))));
+        }
+    }
+}
+
+async fn route_not_found(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    Err(ApiError::NotFound(format!("No route {} {}", req.method(), req.path())))
+}
+
+async fn get_openapi() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[derive(Deserialize, ToSchema)]
+struct EnqueueJobRequest {
+    #[serde(flatten)]
+    kind: JobKind,
+    #[serde(default)]
+    grid: ComputationGrid,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    priority: i32,
+    max_attempts: Option<u32>,
+}
+
+/// Queues a worker job.
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    request_body = EnqueueJobRequest,
+    security(("admin_key" = []), ("admin_timestamp" = [], "admin_nonce" = [], "admin_signature" = [])),
+    responses(
+        (status = 202, description = "The queued job", body = Job),
+        (status = 400, description = "Invalid grid, weak point hit chance or admin timestamp", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin credentials", body = ErrorBody),
+        (status = 404, description = "Unknown weapon", body = ErrorBody),
+        (status = 503, description = "MongoDB isn't configured", body = ErrorBody),
+    ),
+)]
+async fn enqueue_job(
+    data: web::Data<AppState>,
+    request: web::Json<EnqueueJobRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let request = request.into_inner();
+    if let JobKind::UpdateWeapons { names } | JobKind::SweepWeakPoint { names, .. } = &request.kind {
+        for name in names {
+            parse_weapon(name)?;
+        }
+    }
+    request.grid.validate().map_err(ApiError::InvalidRequest)?;
+    request.grid.validate_storable().map_err(ApiError::InvalidRequest)?;
+    for &chance in &request.grid.weak_point_hit_chances {
+        validate_weak_point_chance(chance)?;
+    }
+
+    let mut job = Job::new(request.kind, request.grid, request.priority, request.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS));
+    job.force = request.force;
+    jobs::enqueue(data.mongo()?, &job).await.map_err(|e| ApiError::internal("Failed to enqueue job", e))?;
+    Ok(HttpResponse::Accepted().json(&job))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListJobsQuery {
+    /// Jobs of every status if absent
+    status: Option<JobStatus>,
+    #[serde(default = "default_job_limit")]
+    #[param(default = 50, minimum = 1, maximum = 500)]
+    limit: i64,
+}
+
+fn default_job_limit() -> i64 {
+    50
+}
+
+/// The most recent jobs, newest first.
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    params(ListJobsQuery),
+    responses(
+        (status = 200, description = "Jobs", body = Vec<Job>),
+        (status = 400, description = "Invalid status or limit", body = ErrorBody),
+        (status = 503, description = "MongoDB isn't configured", body = ErrorBody),
+    ),
+)]
+async fn list_jobs(
+    data: web::Data<AppState>,
+    query: web::Query<ListJobsQuery>,
+) -> Result<HttpResponse, ApiError> {
+    if !(1..=MAX_LIST_LIMIT).contains(&query.limit) {
+        return Err(ApiError::InvalidRequest(format!("limit must be between 1 and {}", MAX_LIST_LIMIT)));
+    }
+    let jobs = jobs::list_jobs(data.mongo()?, query.status, query.limit)
+        .await
+        .map_err(|e| ApiError::internal("Failed to list jobs", e))?;
+    Ok(HttpResponse::Ok().json(jobs))
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    params(("id" = String, Path, description = "Id returned by POST /jobs")),
+    responses(
+        (status = 200, description = "The job", body = Job),
+        (status = 404, description = "No such job", body = ErrorBody),
+        (status = 503, description = "MongoDB isn't configured", body = ErrorBody),
+    ),
+)]
+async fn get_job(
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    match jobs::get_job(data.mongo()?, &id).await.map_err(|e| ApiError::internal("Failed to read job", e))? {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Err(ApiError::NotFound(format!("No job {}", id))),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ClearCacheQuery {
+    /// `all`, a weapon, a weapon type or a bullet type
     target: Option<String>,
 }
 
+fn header(req: &ServiceRequest, name: &str) -> Option<String> {
+    req.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+/// Lets a request through to an admin route only if `AdminAuth` accepts it.
+async fn require_admin(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    // The signature covers the body, so read it and hand it back afterwards
+    let body = req.extract::<web::Bytes>().await?;
+    let credentials = AdminCredentials {
+        key: header(&req, KEY_HEADER),
+        timestamp: header(&req, TIMESTAMP_HEADER),
+        nonce: header(&req, NONCE_HEADER),
+        signature: header(&req, SIGNATURE_HEADER),
+    };
+    let path_and_query = req.uri().path_and_query().map_or_else(|| req.path().to_string(), |p| p.as_str().to_string());
+    let verified = match req.app_data::<web::Data<AppState>>() {
+        Some(data) => data.admin_auth.verify(&credentials, req.method().as_str(), &path_and_query, &body, jobs::now_millis() / 1000),
+        None => Err(AuthError::NotConfigured),
+    };
+
+    if let Err(e) = verified {
+        warn!("Rejected admin request {} {}: {}", req.method(), path_and_query, e);
+        let response = ApiError::from(e).error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    req.set_payload(Payload::from(body));
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// Reloads the results of some weapons from the result store.
+#[utoipa::path(
+    post,
+    path = "/clear-cache-and-fetch",
+    params(ClearCacheQuery),
+    security(("admin_key" = []), ("admin_timestamp" = [], "admin_nonce" = [], "admin_signature" = [])),
+    responses(
+        (status = 200, description = "Results reloaded", body = StatusResponse),
+        (status = 400, description = "No target or malformed admin timestamp", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin credentials", body = ErrorBody),
+        (status = 404, description = "Unknown target", body = ErrorBody),
+        (status = 500, description = "Results couldn't be read", body = ErrorBody),
+    ),
+)]
 async fn clear_cache_and_fetch(
     data: web::Data<AppState>,
     query: web::Query<ClearCacheQuery>,
-) -> HttpResponse {
-    let mut weapon_results = data.weapon_results.write().await;
-    let db = &data.db;
-    
+) -> Result<HttpResponse, ApiError> {
+    let store = data.store.as_ref();
+
     match query.target.as_deref() {
         Some("all") => {
+            let mut weapon_results = data.weapon_results.write().await;
             weapon_results.clear();
             // Fetch all results
-            if let Err(e) = fetch_all_results(db, &mut weapon_results).await {
-                return HttpResponse::InternalServerError().json(json!({"status": format!("Failed to fetch new data: {}", e)}));
-            }
-            HttpResponse::Ok().json(json!({"status": "All cache cleared and new data fetched successfully"}))
+            fetch_all_results(store, &mut weapon_results)
+                .await
+                .map_err(|e| ApiError::internal("Failed to fetch new data", e))?;
+            Ok(HttpResponse::Ok().json(StatusResponse { status: "All cache cleared and new data fetched successfully".to_string() }))
         },
-        Some(weapon) => {
-            let keys_to_remove: Vec<String> = weapon_results.keys()
-                .filter(|k| k.starts_with(weapon))
-                .cloned()
-                .collect();
-            
-            for key in &keys_to_remove {
-                weapon_results.remove(key);
-            }
+        Some(target) => {
+            let weapons = cache_target_weapons(target)?;
+            let mut weapon_results = data.weapon_results.write().await;
+            weapon_results.retain(|key, _| !weapons.contains(&key.weapon));
 
-            // Fetch results for the specific weapon
-            if let Err(e) = fetch_weapon_results(db, weapon, &mut weapon_results).await {
-                return HttpResponse::InternalServerError().json(json!({"status": format!("Failed to fetch new data for weapon {}: {}", weapon, e)}));
+            // Fetch results for the target's weapons
+            for &weapon in &weapons {
+                fetch_weapon_results(store, weapon, &mut weapon_results)
+                    .await
+                    .map_err(|e| ApiError::internal(&format!("Failed to fetch new data for weapon {}", weapon), e))?;
             }
-            HttpResponse::Ok().json(json!({"status": format!("Cache cleared and new data fetched for weapon: {}", weapon)}))
+            Ok(HttpResponse::Ok().json(StatusResponse { status: format!("Cache cleared and new data fetched for {}", target) }))
         },
-        None => HttpResponse::BadRequest().json(json!({"status": "No target specified for cache clearing"}))
+        None => Err(ApiError::InvalidRequest("No target specified for cache clearing".to_string())),
     }
 }
 
-async fn fetch_all_results(db: &mongodb::Database, weapon_results: &mut HashMap<String, WeaponResultDocument>) -> Result<(), mongodb::error::Error> {
-    let collection = db.collection::<WeaponResultDocument>("weapon_results");
-    let mut cursor = collection.find(None, None).await?;
+/// The weapons a cache clearing target covers: a weapon type, a bullet type
+/// or a single weapon, the same targets the worker's update commands take.
+fn cache_target_weapons(target: &str) -> Result<Vec<Weapon>, ApiError> {
+    let weapons = Weapon::all().iter().copied();
+    if let Ok(weapon_type) = WeaponType::from_str(target) {
+        return Ok(weapons.filter(|weapon| WeaponBaseStats::get(*weapon).weapon_type == weapon_type).collect());
+    }
+    if let Ok(bullet_type) = BulletType::from_str(target) {
+        return Ok(weapons.filter(|weapon| WeaponBaseStats::get(*weapon).bullet_type == bullet_type).collect());
+    }
+    Ok(vec![parse_weapon(target)?])
+}
 
-    while let Some(doc) = cursor.try_next().await? {
-        let key = format!("{}_{:.2}_{}", doc.weapon, doc.weak_point_hit_chance, doc.valby);
-        weapon_results.insert(key, doc);
+async fn fetch_all_results(store: &dyn ResultStore, weapon_results: &mut HashMap<ResultKey, ResultRecord>) -> Result<(), StoreError> {
+    for doc in store.load_all().await? {
+        insert_result(weapon_results, doc);
     }
 
     Ok(())
 }
 
-async fn fetch_weapon_results(db: &mongodb::Database, weapon: &str, weapon_results: &mut HashMap<String, WeaponResultDocument>) -> Result<(), mongodb::error::Error> {
-    let collection = db.collection::<WeaponResultDocument>("weapon_results");
-    let filter = doc! { "weapon": weapon };
-    let mut cursor = collection.find(filter, None).await?;
-
-    while let Some(doc) = cursor.try_next().await? {
-        let key = format!("{}_{}_{}_{}",
-            doc.weapon,
-            doc.weak_point_hit_chance,
-            doc.valby,
-            doc.enzo
-        );
-        weapon_results.insert(key, doc);
+async fn fetch_weapon_results(store: &dyn ResultStore, weapon: Weapon, weapon_results: &mut HashMap<ResultKey, ResultRecord>) -> Result<(), StoreError> {
+    for doc in store.load_weapon(&weapon.to_string()).await? {
+        insert_result(weapon_results, doc);
     }
 
     Ok(())
 }
 
+/// Every route and how bad input to them is reported.
+fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(|e, _| ApiError::InvalidRequest(e.to_string()).into()))
+        .app_data(web::QueryConfig::default().error_handler(|e, _| ApiError::InvalidRequest(e.to_string()).into()))
+        .app_data(web::PathConfig::default().error_handler(|e, _| ApiError::InvalidRequest(e.to_string()).into()))
+        .route("/", web::get().to(index))
+        .route("/weapons", web::get().to(get_weapons))
+        .route("/weapons/{name}", web::get().to(get_weapon_details))
+        .route("/modules", web::get().to(get_modules))
+        .route("/optimize", web::post().to(optimize_weapon_handler))
+        .route("/weapon-data", web::get().to(get_weapon_data))
+        .route("/wp-sweep", web::get().to(get_weak_point_sweep))
+        .route("/tier-list", web::get().to(get_tier_list))
+        .route("/share-codes", web::post().to(create_share_code))
+        .route("/share-codes/{code}", web::get().to(expand_share_code))
+        .route("/export", web::get().to(export_build))
+        .route("/openapi.json", web::get().to(get_openapi))
+        .route("/jobs", web::post().to(enqueue_job).wrap(from_fn(require_admin)))
+        .route("/jobs", web::get().to(list_jobs))
+        .route("/jobs/{id}", web::get().to(get_job))
+        .route("/refresh-results", web::post().to(refresh_weapon_results).wrap(from_fn(require_admin)))
+        .route("/clear-cache-and-fetch", web::post().to(clear_cache_and_fetch).wrap(from_fn(require_admin)))
+        .default_service(web::to(route_not_found));
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
 
-    let mongo_client = match create_mongo_client().await {
-        Ok(client) => client,
-        Err(e) => {
-            error!("Failed to create MongoDB client: {}", e);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+    dotenv().ok();
+
+    let store_target: StoreTarget = env::var("RESULT_STORE")
+        .unwrap_or_else(|_| "mongo".to_string())
+        .parse()
+        .map_err(std::io::Error::other)?;
+
+    // Only the mongo store needs MongoDB, otherwise it's just for jobs and sweeps
+    let db = match env::var("MONGODB_URI") {
+        Ok(uri) => match create_mongo_client(&uri).await {
+            Ok(client) => Some(client.database("weapon_optimizer")),
+            Err(e) => {
+                error!("Failed to create MongoDB client: {}", e);
+                return Err(std::io::Error::other(e));
+            }
+        },
+        Err(_) if store_target == StoreTarget::Mongo => {
+            return Err(std::io::Error::other("MONGODB_URI must be set for the mongo result store"));
+        }
+        Err(_) => {
+            warn!("MONGODB_URI isn't set, jobs and weak point sweeps are unavailable");
+            None
         }
     };
+    let store = store_target.open(db.as_ref()).map_err(std::io::Error::other)?;
 
-    let db = mongo_client.database("weapon_optimizer");
-    
-    info!("Loading all weapon results from database...");
-    let weapon_results = match load_all_weapon_results(&db).await {
+    info!("Loading all weapon results from {:?} store...", store_target);
+    let weapon_results = match load_all_weapon_results(store.as_ref()).await {
         Ok(results) => Arc::new(RwLock::new(results)),
         Err(e) => {
             error!("Failed to load weapon results: {}", e);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+            return Err(std::io::Error::other(e));
         }
     };
     info!("Weapon results loaded successfully");
 
+    let admin_auth = Arc::new(AdminAuth::from_env());
+    if !admin_auth.is_configured() {
+        warn!("Neither ADMIN_API_KEY nor ADMIN_HMAC_SECRET is set, admin routes will refuse every request");
+    }
+
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let address = format!("0.0.0.0:{}", port);
 
@@ -269,17 +837,330 @@ async fn main() -> std::io::Result<()> {
                         .max_age(3600)
                 )
                 .app_data(web::Data::new(AppState { 
-                    db: db.clone(), 
-                    weapon_results: weapon_results.clone() 
+                    db: db.clone(),
+                    store: store.clone(),
+                    weapon_results: weapon_results.clone(),
+                    admin_auth: admin_auth.clone(),
                 }))
-                .route("/", web::get().to(index))
-                .route("/weapons", web::get().to(get_weapons))
-                .route("/optimize", web::post().to(optimize_weapon_handler))
-                .route("/weapon-data", web::get().to(get_weapon_data))
-                .route("/refresh-results", web::post().to(refresh_weapon_results))
-                .route("/clear-cache-and-fetch", web::post().to(clear_cache_and_fetch))
+                .configure(configure_routes)
     })
     .bind(address)?
     .run()
     .await
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use serde_json::json;
+
+    const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    fn collect_refs(value: &serde_json::Value, refs: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                if let Some(serde_json::Value::String(reference)) = map.get("$ref") {
+                    refs.push(reference.clone());
+                }
+                map.values().for_each(|value| collect_refs(value, refs));
+            },
+            serde_json::Value::Array(values) => values.iter().for_each(|value| collect_refs(value, refs)),
+            _ => {},
+        }
+    }
+
+    /// Fails when a handler's types change without regenerating the
+    /// committed spec, which clients are generated from. Regenerate it with
+    /// `UPDATE_OPENAPI=1 cargo test --bin api`.
+    #[test]
+    fn test_openapi_spec_is_current() {
+        let spec = ApiDoc::openapi().to_pretty_json().unwrap();
+        if env::var("UPDATE_OPENAPI").is_ok() {
+            std::fs::write(SPEC_PATH, &spec).unwrap();
+        }
+        let committed = std::fs::read_to_string(SPEC_PATH).expect("openapi.json should be committed");
+        assert!(committed == spec, "openapi.json is out of date, regenerate it with UPDATE_OPENAPI=1 cargo test --bin api");
+
+        let spec: serde_json::Value = serde_json::from_str(&spec).unwrap();
+        let mut refs = Vec::new();
+        collect_refs(&spec, &mut refs);
+        assert!(!refs.is_empty());
+        for reference in refs {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            assert!(spec["components"]["schemas"].get(name).is_some(), "{} isn't in the spec's schemas", reference);
+        }
+    }
+
+    async fn error_code(response: ServiceResponse) -> (u16, serde_json::Value) {
+        let status = response.status().as_u16();
+        let body: serde_json::Value = read_body_json(response).await;
+        (status, body)
+    }
+
+    /// A server without MongoDB whose only result is Python's at 0.5, in
+    /// both the store and the served results.
+    async fn test_state() -> web::Data<AppState> {
+        let record = ResultRecord {
+            weapon: "Python".to_string(),
+            weak_point_hit_chance: 0.5,
+            valby: false,
+            enzo: false,
+            objective: Objective::default(),
+            enemy: EnemyProfile::default(),
+            data_version: game_data_version().to_string(),
+            input_hash: String::new(),
+            result: OptimizationResult { max_dps: 1000.0, best_rolls: Vec::new(), best_modules: Vec::new(), best_reactor: Vec::new() },
+        };
+        let store = store::MemoryStore::default();
+        store.upsert(&record).await.unwrap();
+        let mut results = HashMap::new();
+        insert_result(&mut results, record);
+        web::Data::new(AppState {
+            db: None,
+            store: Arc::new(store),
+            weapon_results: Arc::new(RwLock::new(results)),
+            admin_auth: Arc::new(AdminAuth::new(Some("key".to_string()), Some("secret".to_string()))),
+        })
+    }
+
+    /// Problems with `value` against `schema`, for the subset of JSON schema
+    /// utoipa generates. Properties a schema doesn't list are reported too,
+    /// except under `allOf` where they belong to another part.
+    fn schema_errors(spec: &serde_json::Value, schema: &serde_json::Value, value: &serde_json::Value, at: &str, open: bool) -> Vec<String> {
+        use serde_json::Value;
+
+        if schema == &Value::Bool(false) {
+            return vec![format!("{}: not allowed", at)];
+        }
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            return schema_errors(spec, &spec["components"]["schemas"][name], value, at, open);
+        }
+
+        let mut errors = Vec::new();
+        if let Some(parts) = schema["allOf"].as_array() {
+            for part in parts {
+                errors.extend(schema_errors(spec, part, value, at, true));
+            }
+        }
+        if let Some(options) = schema["oneOf"].as_array() {
+            if !options.iter().any(|option| schema_errors(spec, option, value, at, open).is_empty()) {
+                errors.push(format!("{}: {} matches none of the options", at, value));
+            }
+        }
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let type_matches = |name: &str| match name {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !types.is_empty() && !types.iter().any(|name| type_matches(name)) {
+            return vec![format!("{}: {} isn't {:?}", at, value, types)];
+        }
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                errors.push(format!("{}: {} isn't one of {:?}", at, value, allowed));
+            }
+        }
+
+        match value {
+            Value::Object(map) => {
+                let properties = schema["properties"].as_object();
+                for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                    if !map.contains_key(required) {
+                        errors.push(format!("{}: missing {}", at, required));
+                    }
+                }
+                for (key, field) in map {
+                    let path = format!("{}.{}", at, key);
+                    if let Some(key_schema) = schema.get("propertyNames") {
+                        errors.extend(schema_errors(spec, key_schema, &Value::String(key.clone()), &path, open));
+                    }
+                    match (properties.and_then(|properties| properties.get(key)), schema.get("additionalProperties")) {
+                        (Some(field_schema), _) | (None, Some(field_schema)) => errors.extend(schema_errors(spec, field_schema, field, &path, false)),
+                        (None, None) if properties.is_some() && !open => errors.push(format!("{}: not in the spec", path)),
+                        (None, None) => {},
+                    }
+                }
+            },
+            Value::Array(items) => {
+                let prefix = schema["prefixItems"].as_array().map_or(&[][..], Vec::as_slice);
+                for (i, item) in items.iter().enumerate() {
+                    let path = format!("{}[{}]", at, i);
+                    if let Some(item_schema) = prefix.get(i).or_else(|| schema.get("items")) {
+                        errors.extend(schema_errors(spec, item_schema, item, &path, false));
+                    }
+                }
+            },
+            _ => {},
+        }
+        errors
+    }
+
+    /// Calls every operation in the spec and checks the status is one it
+    /// documents and the body has the documented shape.
+    #[actix_web::test]
+    async fn test_responses_match_the_spec() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let app = init_service(App::new().app_data(test_state().await).configure(configure_routes)).await;
+        let admin = ("x-admin-key", "key");
+        let share_code_body = json!({
+            "weapon": "Python",
+            "weak_point_hit_chance": 0.5,
+            "build": {"max_dps": 0.0, "best_rolls": [], "best_modules": []},
+        });
+
+        let calls = vec![
+            ("get", "/weapons", TestRequest::get().uri("/weapons")),
+            ("get", "/weapons/{name}", TestRequest::get().uri("/weapons/Thundercage")),
+            ("get", "/modules", TestRequest::get().uri("/modules?weapon_type=HandCannon")),
+            ("post", "/optimize", TestRequest::post().uri("/optimize").set_json(json!({"weapon": "Python", "weak_point_hit_chance": 0.5, "valby": false, "enzo": false}))),
+            ("get", "/weapon-data", TestRequest::get().uri("/weapon-data")),
+            ("get", "/tier-list", TestRequest::get().uri("/tier-list?weak_point_hit_chance=0.5")),
+            ("get", "/wp-sweep", TestRequest::get().uri("/wp-sweep")),
+            ("post", "/share-codes", TestRequest::post().uri("/share-codes").set_json(share_code_body.clone())),
+            ("get", "/share-codes/{code}", TestRequest::get().uri("/share-codes/AQ")),
+            ("get", "/export", TestRequest::get().uri("/export?weapon=Python&weak_point_hit_chance=0.5")),
+            ("post", "/jobs", TestRequest::post().uri("/jobs").insert_header(admin).set_json(json!({"type": "UpdateAll"}))),
+            ("get", "/jobs", TestRequest::get().uri("/jobs?status=Queued")),
+            ("get", "/jobs/{id}", TestRequest::get().uri("/jobs/none")),
+            ("post", "/refresh-results", TestRequest::post().uri("/refresh-results").insert_header(admin)),
+            ("post", "/clear-cache-and-fetch", TestRequest::post().uri("/clear-cache-and-fetch?target=Python").insert_header(admin)),
+        ];
+
+        let mut operations: Vec<(String, String)> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, item)| item.as_object().unwrap().keys().map(move |method| (method.clone(), path.clone())))
+            .collect();
+        let mut called: Vec<(String, String)> = calls.iter().map(|(method, path, _)| (method.to_string(), path.to_string())).collect();
+        operations.sort();
+        called.sort();
+        assert_eq!(called, operations, "every operation in the spec should be called once");
+
+        // A real code, so the happy path of /share-codes/{code} is covered too
+        let response = call_service(&app, TestRequest::post().uri("/share-codes").set_json(share_code_body).to_request()).await;
+        let created: serde_json::Value = read_body_json(response).await;
+        let real_code = ("get", "/share-codes/{code}", TestRequest::get().uri(&format!("/share-codes/{}", created["code"].as_str().unwrap())));
+
+        for (method, path, request) in calls.into_iter().chain([real_code]) {
+            let response = call_service(&app, request.to_request()).await;
+            let status = response.status().as_u16().to_string();
+            let content_type = response.headers().get("content-type").unwrap().to_str().unwrap().to_string();
+            let documented = &spec["paths"][path][method]["responses"][&status];
+            assert!(documented.is_object(), "{} {} answered {}, which the spec doesn't document", method, path, status);
+
+            let media_type = content_type.split(';').next().unwrap().trim();
+            let content = &documented["content"][media_type];
+            assert!(content.is_object(), "{} {} answered {} as {}, which the spec doesn't document", method, path, status, content_type);
+            if media_type == "application/json" {
+                let body: serde_json::Value = read_body_json(response).await;
+                let errors = schema_errors(&spec, &content["schema"], &body, "body", false);
+                assert!(errors.is_empty(), "{} {} {}:\n{}", method, path, status, errors.join("\n"));
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_errors_on_every_route() {
+        let app = init_service(App::new().app_data(test_state().await).configure(configure_routes)).await;
+
+        let optimize = |body: serde_json::Value| TestRequest::post().uri("/optimize").set_json(body).to_request();
+        let response = call_service(&app, optimize(json!({"weapon": "Python", "weak_point_hit_chance": 0.5, "valby": false, "enzo": false}))).await;
+        assert_eq!(response.status(), 200);
+        let response = call_service(&app, TestRequest::get().uri("/export?weapon=Python&weak_point_hit_chance=0.5&format=csv").to_request()).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers().get("content-type").unwrap(), "text/csv; charset=utf-8");
+        let clear = |target: &str| TestRequest::post().uri(&format!("/clear-cache-and-fetch?target={}", target)).insert_header(("x-admin-key", "key")).to_request();
+        for target in ["SubmachineGun", "GeneralRounds", "Python", "all"] {
+            assert_eq!(call_service(&app, clear(target)).await.status(), 200, "{}", target);
+        }
+
+        let cases = [
+            (optimize(json!({"weapon": "Pyton", "weak_point_hit_chance": 0.5, "valby": false, "enzo": false})), 404, "unknown_weapon"),
+            (optimize(json!({"weapon": "Python", "weak_point_hit_chance": 1.5, "valby": false, "enzo": false})), 400, "invalid_weak_point_chance"),
+            (optimize(json!({"weapon": "Python", "weak_point_hit_chance": 0.5, "valby": true, "enzo": true})), 400, "conflicting_buffs"),
+            (optimize(json!({"weapon": "Python", "weak_point_hit_chance": 1.0, "valby": false, "enzo": false})), 404, "not_found"),
+            (optimize(json!({"weapon": "Python"})), 400, "invalid_request"),
+            (TestRequest::get().uri("/weapons/Thundercag").to_request(), 404, "unknown_weapon"),
+            (TestRequest::get().uri("/modules?module_type=Nope").to_request(), 400, "invalid_request"),
+            (TestRequest::get().uri("/tier-list?weak_point_hit_chance=-1").to_request(), 400, "invalid_weak_point_chance"),
+            (TestRequest::get().uri("/tier-list?buffs=valby%2Benzo").to_request(), 400, "conflicting_buffs"),
+            (TestRequest::get().uri("/tier-list?method=kmeans").to_request(), 400, "invalid_request"),
+            (TestRequest::get().uri("/wp-sweep?weapons=Python,Pyton").to_request(), 404, "unknown_weapon"),
+            (TestRequest::get().uri("/wp-sweep?weapons=Python").to_request(), 503, "unavailable"),
+            (TestRequest::get().uri("/jobs").to_request(), 503, "unavailable"),
+            (TestRequest::get().uri("/jobs?limit=0").to_request(), 400, "invalid_request"),
+            (TestRequest::get().uri("/jobs?limit=501").to_request(), 400, "invalid_request"),
+            (TestRequest::post().uri("/jobs").insert_header(("x-admin-key", "key")).set_json(json!({"type": "UpdateAll", "grid": {"module_slots": 6}})).to_request(), 400, "invalid_request"),
+            (TestRequest::post().uri("/refresh-results").to_request(), 401, "unauthorized"),
+            (TestRequest::post().uri("/clear-cache-and-fetch?target=Pyton").insert_header(("x-admin-key", "key")).to_request(), 404, "unknown_weapon"),
+            (
+                TestRequest::post()
+                    .uri("/refresh-results")
+                    .insert_header((TIMESTAMP_HEADER, "yesterday"))
+                    .insert_header((NONCE_HEADER, "nonce"))
+                    .insert_header((SIGNATURE_HEADER, "00"))
+                    .to_request(),
+                400,
+                "invalid_request",
+            ),
+            (TestRequest::get().uri("/share-codes/AQ").to_request(), 400, "invalid_share_code"),
+            (TestRequest::post().uri("/share-codes").set_json(json!({"weapon": "Pyton", "weak_point_hit_chance": 0.5, "build": {"max_dps": 0.0, "best_rolls": [], "best_modules": []}})).to_request(), 404, "unknown_weapon"),
+            (TestRequest::get().uri("/export?weapon=Python&weak_point_hit_chance=0.5&format=pdf").to_request(), 400, "invalid_request"),
+            (TestRequest::get().uri("/export?weapon=Python&weak_point_hit_chance=0.25").to_request(), 404, "not_found"),
+            (TestRequest::get().uri("/nowhere").to_request(), 404, "not_found"),
+        ];
+        for (request, status, code) in cases {
+            let uri = request.uri().to_string();
+            let (actual_status, body) = error_code(call_service(&app, request).await).await;
+            assert_eq!((actual_status, body["code"].as_str()), (status, Some(code)), "{}: {}", uri, body);
+            assert!(body["message"].is_string());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_share_code_round_trip() {
+        let app = init_service(App::new().configure(configure_routes)).await;
+        let details = WeaponDetails::get(Weapon::Thundercage);
+        let module = details.modules[0].clone();
+        let request = TestRequest::post()
+            .uri("/share-codes")
+            .set_json(json!({
+                "weapon": "Thundercage",
+                "weak_point_hit_chance": 0.5,
+                "valby": true,
+                "build": OptimizationResult {
+                    max_dps: 0.0,
+                    best_rolls: vec![details.rolls[0]],
+                    best_modules: vec![(module.clone(), 1.0)],
+                    best_reactor: Vec::new(),
+                },
+            }))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), 200);
+        let created: serde_json::Value = read_body_json(response).await;
+        let code = created["code"].as_str().unwrap();
+
+        let response = call_service(&app, TestRequest::get().uri(&format!("/share-codes/{}", code)).to_request()).await;
+        assert_eq!(response.status(), 200);
+        let expanded: ExpandedBuild = read_body_json(response).await;
+        assert_eq!(expanded.weapon, Weapon::Thundercage);
+        assert_eq!(expanded.buffs, "valby");
+        assert_eq!(expanded.modules.iter().map(|module| &module.name).collect::<Vec<_>>(), vec![&module.name]);
+        assert!(expanded.dps > 0.0);
+    }
+}
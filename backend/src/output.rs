@@ -0,0 +1,364 @@
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::{ReplaceOptions, UpdateOptions};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::calculate::EnemyProfile;
+use crate::checkpoint::{RunCheckpoint, RUNS_COLLECTION};
+use crate::game_data::game_data_version;
+use crate::grid::{BuffSet, Objective};
+use crate::result_key::ResultKey;
+use crate::store::{MongoStore, ResultRecord, ResultStore, SqliteStore, RESULTS_COLLECTION};
+use crate::sweep::WeaponSweep;
+use crate::weapons::Weapon;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepRecord {
+    pub data_version: String,
+    #[serde(flatten)]
+    pub sweep: WeaponSweep,
+}
+
+/// One line of a JSON-lines output file, tagged with the collection it is
+/// imported into.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "collection", rename_all = "snake_case")]
+pub enum OutputRecord {
+    WeaponResults(ResultRecord),
+    WpSweeps(SweepRecord),
+}
+
+/// Input hash of a stored result, see `input_hash`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredHash {
+    pub key: ResultKey,
+    pub input_hash: String,
+}
+
+/// Where the worker writes results, parsed from `mongo`, `jsonl:<path>` or
+/// `sqlite:<path>`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputTarget {
+    Mongo,
+    JsonLines(PathBuf),
+    Sqlite(PathBuf),
+}
+
+impl FromStr for OutputTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "mongo" => Ok(OutputTarget::Mongo),
+            Some(("jsonl", path)) if !path.is_empty() => Ok(OutputTarget::JsonLines(PathBuf::from(path))),
+            Some(("sqlite", path)) if !path.is_empty() => Ok(OutputTarget::Sqlite(PathBuf::from(path))),
+            _ => Err(format!("Invalid output {}, expected mongo, jsonl:<path> or sqlite:<path>", s)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Output {
+    Mongo(mongodb::Database),
+    JsonLines(PathBuf),
+    Sqlite(SqliteStore),
+}
+
+/// Results are kept by `SqliteStore`, these are the worker's own tables.
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS wp_sweeps (
+        weapon TEXT NOT NULL,
+        valby INTEGER NOT NULL,
+        enzo INTEGER NOT NULL,
+        data_version TEXT NOT NULL,
+        sweep TEXT NOT NULL,
+        PRIMARY KEY (weapon, valby, enzo, data_version)
+    );
+    CREATE TABLE IF NOT EXISTS worker_runs (
+        id TEXT PRIMARY KEY,
+        checkpoint TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS worker_units (
+        id TEXT PRIMARY KEY,
+        run_id TEXT NOT NULL,
+        unit TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL,
+        lease_owner TEXT,
+        lease_expires_at INTEGER,
+        error TEXT
+    );
+";
+
+impl Output {
+    pub fn sqlite(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let store = SqliteStore::open(path)?;
+        store.connection().execute_batch(SQLITE_SCHEMA)?;
+        Ok(Output::Sqlite(store))
+    }
+
+    fn append_line(path: &Path, record: &OutputRecord) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    fn checkpoint_path(path: &Path, id: &str) -> PathBuf {
+        let file_name = path.file_name().map_or_else(|| "output".into(), |name| name.to_string_lossy());
+        path.with_file_name(format!("{}.{}.checkpoint.json", file_name, id))
+    }
+
+    pub async fn store_result(&self, record: &ResultRecord) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Output::Mongo(db) => MongoStore::new(db.clone()).upsert(record).await?,
+            Output::JsonLines(path) => Self::append_line(path, &OutputRecord::WeaponResults(record.clone()))?,
+            Output::Sqlite(store) => store.upsert(record).await?,
+        }
+        Ok(())
+    }
+
+    pub async fn store_sweep(&self, record: &SweepRecord) -> Result<(), Box<dyn std::error::Error>> {
+        let sweep = &record.sweep;
+        match self {
+            Output::Mongo(db) => {
+                db.collection::<WeaponSweep>("wp_sweeps").update_one(
+                    doc! { "weapon": &sweep.weapon, "valby": sweep.valby, "enzo": sweep.enzo, "data_version": &record.data_version },
+                    doc! { "$set": mongodb::bson::to_bson(sweep)? },
+                    UpdateOptions::builder().upsert(true).build(),
+                ).await?;
+            },
+            Output::JsonLines(path) => Self::append_line(path, &OutputRecord::WpSweeps(record.clone()))?,
+            Output::Sqlite(store) => {
+                let (sweep_json, record) = (serde_json::to_string(sweep)?, record.clone());
+                store.blocking(move |connection| {
+                    let sweep = &record.sweep;
+                    connection.execute(
+                        "INSERT OR REPLACE INTO wp_sweeps VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![sweep.weapon, sweep.valby, sweep.enzo, record.data_version, sweep_json],
+                    )?;
+                    Ok(())
+                }).await?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Input hashes of the current-version results, optionally of one weapon
+    /// only. Results of weapons that no longer exist are left out.
+    pub async fn stored_input_hashes(&self, weapon: Option<Weapon>) -> Result<Vec<StoredHash>, Box<dyn std::error::Error>> {
+        let hashes = match self {
+            Output::Mongo(db) => {
+                let mut filter = doc! { "data_version": game_data_version(), "input_hash": { "$exists": true } };
+                if let Some(weapon) = weapon {
+                    filter.insert("weapon", weapon.to_string());
+                }
+                let options = mongodb::options::FindOptions::builder()
+                    .projection(doc! { "weapon": 1, "weak_point_hit_chance": 1, "valby": 1, "enzo": 1, "objective": 1, "enemy": 1, "input_hash": 1 })
+                    .build();
+                let mut hashes = Vec::new();
+                let mut cursor = db.collection::<mongodb::bson::Document>(RESULTS_COLLECTION).find(filter, options).await?;
+                while let Some(document) = cursor.try_next().await? {
+                    let Ok(stored_weapon) = document.get_str("weapon")?.parse() else {
+                        continue;
+                    };
+                    let buffs = BuffSet { valby: document.get_bool("valby")?, enzo: document.get_bool("enzo")? };
+                    hashes.push(StoredHash {
+                        key: ResultKey::new(
+                            stored_weapon,
+                            document.get_f64("weak_point_hit_chance")?,
+                            buffs,
+                            document.get_str("objective").map_or(Ok(Objective::default()), str::parse)?,
+                            document.get_str("enemy").map_or(Ok(EnemyProfile::default()), str::parse)?,
+                        ),
+                        input_hash: document.get_str("input_hash")?.to_string(),
+                    });
+                }
+                hashes
+            },
+            Output::JsonLines(_) | Output::Sqlite(_) => self
+                .read_records()
+                .await?
+                .into_iter()
+                .filter_map(|record| match record {
+                    OutputRecord::WeaponResults(record) if record.data_version == game_data_version() => Some(StoredHash {
+                        key: record.key()?,
+                        input_hash: record.input_hash,
+                    }),
+                    _ => None,
+                })
+                .collect(),
+        };
+        Ok(hashes.into_iter().filter(|hash| weapon.is_none_or(|weapon| hash.key.weapon == weapon)).collect())
+    }
+
+    /// Every stored weapon result. For JSON-lines outputs later lines win.
+    pub async fn load_results(&self) -> Result<Vec<ResultRecord>, Box<dyn std::error::Error>> {
+        match self {
+            Output::Mongo(db) => Ok(MongoStore::new(db.clone()).load_all().await?),
+            Output::Sqlite(store) => Ok(store.load_all().await?),
+            Output::JsonLines(_) => {
+                let mut results: Vec<ResultRecord> = Vec::new();
+                for record in self.read_records().await? {
+                    if let OutputRecord::WeaponResults(record) = record {
+                        results.retain(|stored| !stored.same_key(&record));
+                        results.push(record);
+                    }
+                }
+                Ok(results)
+            },
+        }
+    }
+
+    /// Everything written to a file output, in write order. Later lines of a
+    /// JSON-lines file supersede earlier ones with the same key.
+    pub async fn read_records(&self) -> Result<Vec<OutputRecord>, Box<dyn std::error::Error>> {
+        match self {
+            Output::Mongo(_) => Err("Reading records back is only supported for file outputs".into()),
+            Output::JsonLines(path) => {
+                if !path.exists() {
+                    return Ok(Vec::new());
+                }
+                let mut records = Vec::new();
+                for line in BufReader::new(File::open(path)?).lines() {
+                    let line = line?;
+                    if !line.trim().is_empty() {
+                        records.push(serde_json::from_str(&line)?);
+                    }
+                }
+                Ok(records)
+            },
+            Output::Sqlite(store) => {
+                let mut records: Vec<OutputRecord> = store.load_all().await?.into_iter().map(OutputRecord::WeaponResults).collect();
+
+                let sweeps = store.blocking(|connection| {
+                    let mut statement = connection.prepare("SELECT data_version, sweep FROM wp_sweeps")?;
+                    let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+                    let mut sweeps = Vec::new();
+                    for row in rows {
+                        let (data_version, sweep) = row?;
+                        sweeps.push(OutputRecord::WpSweeps(SweepRecord { data_version, sweep: serde_json::from_str(&sweep)? }));
+                    }
+                    Ok(sweeps)
+                }).await?;
+                records.extend(sweeps);
+
+                Ok(records)
+            },
+        }
+    }
+
+    pub async fn load_checkpoint(&self, id: &str) -> Result<Option<RunCheckpoint>, Box<dyn std::error::Error>> {
+        match self {
+            Output::Mongo(db) => Ok(db.collection::<RunCheckpoint>(RUNS_COLLECTION).find_one(doc! { "_id": id }, None).await?),
+            Output::JsonLines(path) => {
+                let path = Self::checkpoint_path(path, id);
+                if !path.exists() {
+                    return Ok(None);
+                }
+                Ok(Some(serde_json::from_reader(File::open(path)?)?))
+            },
+            Output::Sqlite(store) => {
+                let id = id.to_string();
+                let checkpoint: Option<String> = store.blocking(move |connection| {
+                    Ok(connection
+                        .query_row("SELECT checkpoint FROM worker_runs WHERE id = ?1", params![id], |row| row.get(0))
+                        .optional()?)
+                }).await?;
+                Ok(checkpoint.map(|checkpoint| serde_json::from_str(&checkpoint)).transpose()?)
+            },
+        }
+    }
+
+    pub async fn save_checkpoint(&self, run: &mut RunCheckpoint) -> Result<(), Box<dyn std::error::Error>> {
+        run.touch();
+        match self {
+            Output::Mongo(db) => {
+                db.collection::<RunCheckpoint>(RUNS_COLLECTION)
+                    .replace_one(doc! { "_id": &run.id }, &*run, ReplaceOptions::builder().upsert(true).build())
+                    .await?;
+            },
+            Output::JsonLines(path) => {
+                // Write then rename so a crash never leaves a truncated checkpoint
+                let path = Self::checkpoint_path(path, &run.id);
+                let partial = path.with_extension("partial");
+                serde_json::to_writer(File::create(&partial)?, &*run)?;
+                std::fs::rename(partial, path)?;
+            },
+            Output::Sqlite(store) => {
+                let (id, checkpoint) = (run.id.clone(), serde_json::to_string(&*run)?);
+                store.blocking(move |connection| {
+                    connection.execute("INSERT OR REPLACE INTO worker_runs VALUES (?1, ?2)", params![id, checkpoint])?;
+                    Ok(())
+                }).await?;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::OptimizationResult;
+    use crate::checkpoint::{UnitOutcome, WorkUnit};
+
+    fn record(weapon: &str, input_hash: &str) -> ResultRecord {
+        ResultRecord {
+            weapon: weapon.to_string(),
+            weak_point_hit_chance: 0.5,
+            valby: false,
+            enzo: true,
+            objective: Objective::Sustained,
+            enemy: EnemyProfile::Colossus,
+            data_version: game_data_version().to_string(),
+            input_hash: input_hash.to_string(),
+            result: OptimizationResult { max_dps: 1.0, best_rolls: Vec::new(), best_modules: Vec::new(), best_reactor: Vec::new() },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_outputs() {
+        let dir = std::env::temp_dir().join(format!("output_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for output in [Output::JsonLines(dir.join("results.jsonl")), Output::sqlite(&dir.join("results.db")).unwrap()] {
+            output.store_result(&record("Thundercage", "old")).await.unwrap();
+            output.store_result(&record("Thundercage", "new")).await.unwrap();
+            output.store_result(&record("Enduring Legacy", "other")).await.unwrap();
+
+            let hashes = output.stored_input_hashes(Some(Weapon::Thundercage)).await.unwrap();
+            assert_eq!(hashes.last().unwrap().input_hash, "new");
+            assert!(hashes.iter().all(|hash| hash.key.weapon == Weapon::Thundercage));
+
+            let mut run = RunCheckpoint::new(Some("run".to_string()), "UpdateAll".to_string());
+            let unit = WorkUnit {
+                weapon: Weapon::Thundercage,
+                valby: false,
+                enzo: true,
+                objective: Objective::Sustained,
+                enemy: EnemyProfile::Colossus,
+                weak_point_hit_chance: 0.5,
+            };
+            run.record(&unit, UnitOutcome::Finished);
+            output.save_checkpoint(&mut run).await.unwrap();
+            assert!(output.load_checkpoint("run").await.unwrap().unwrap().is_finished(&unit));
+            assert!(output.load_checkpoint("missing").await.unwrap().is_none());
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_target() {
+        assert_eq!("mongo".parse::<OutputTarget>(), Ok(OutputTarget::Mongo));
+        assert_eq!("jsonl:out/results.jsonl".parse::<OutputTarget>(), Ok(OutputTarget::JsonLines(PathBuf::from("out/results.jsonl"))));
+        assert_eq!("sqlite:results.db".parse::<OutputTarget>(), Ok(OutputTarget::Sqlite(PathBuf::from("results.db"))));
+        assert!("sqlite:".parse::<OutputTarget>().is_err());
+        assert!("postgres:results".parse::<OutputTarget>().is_err());
+    }
+}
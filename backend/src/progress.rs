@@ -0,0 +1,291 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::debug;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::calculate::OptimizationProgress;
+use crate::checkpoint::{RunCheckpoint, UnitOutcome, WorkUnit};
+
+/// Something that happened during a worker run, logged as a JSON line at
+/// debug level.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    UnitsQueued { weapon: String, units: u64 },
+    UnitStarted { unit: String },
+    UnitFinished {
+        unit: String,
+        outcome: String,
+        seconds: f64,
+        combinations: u64,
+        max_dps: Option<f64>,
+    },
+    WeaponFinished { weapon: String, seconds: f64, best_dps: Option<f64> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WeaponSummary {
+    pub weapon: String,
+    pub units: u64,
+    pub finished: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub seconds: f64,
+    pub combinations: u64,
+    pub best_dps: Option<f64>,
+}
+
+impl WeaponSummary {
+    fn done(&self) -> u64 {
+        self.finished + self.skipped + self.failed
+    }
+}
+
+/// Machine readable report of a run, printed when it ends.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub run_id: String,
+    pub command: String,
+    pub completed: bool,
+    pub seconds: f64,
+    pub units: u64,
+    pub finished: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub combinations: u64,
+    pub combinations_per_second: f64,
+    pub weapons: Vec<WeaponSummary>,
+}
+
+struct CurrentUnit {
+    started: Instant,
+    combinations_before: u64,
+}
+
+/// Live progress of a worker run: one bar for the units of the whole run
+/// with its ETA, one for the combinations of the unit being optimized.
+pub struct RunProgress {
+    run_bar: ProgressBar,
+    unit_bar: ProgressBar,
+    started: Instant,
+    combinations: AtomicU64,
+    weapons: Mutex<Vec<WeaponSummary>>,
+    current: Mutex<Option<CurrentUnit>>,
+}
+
+impl RunProgress {
+    /// Draws to stderr when it's a terminal.
+    pub fn new() -> Self {
+        Self::with_draw_target(ProgressDrawTarget::stderr())
+    }
+
+    /// Tracks progress without drawing it, for the daemon and tests.
+    pub fn hidden() -> Self {
+        Self::with_draw_target(ProgressDrawTarget::hidden())
+    }
+
+    fn with_draw_target(target: ProgressDrawTarget) -> Self {
+        let bars = MultiProgress::with_draw_target(target);
+        let run_bar = bars.add(ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("{elapsed_precise} [{bar:30}] {pos}/{len} units, ETA {eta} {msg}")
+                .expect("valid template")
+                .progress_chars("=> "),
+        ));
+        let unit_bar = bars.add(ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("{msg} [{bar:30}] {human_pos}/{human_len} combinations, {per_sec}")
+                .expect("valid template")
+                .progress_chars("=> "),
+        ));
+        RunProgress {
+            run_bar,
+            unit_bar,
+            started: Instant::now(),
+            combinations: AtomicU64::new(0),
+            weapons: Mutex::new(Vec::new()),
+            current: Mutex::new(None),
+        }
+    }
+
+    fn emit(&self, event: ProgressEvent) {
+        if let Ok(json) = serde_json::to_string(&event) {
+            debug!("{}", json);
+        }
+    }
+
+    fn weapons(&self) -> std::sync::MutexGuard<'_, Vec<WeaponSummary>> {
+        self.weapons.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Adds `units` of `weapon` to the run's total.
+    pub fn queue(&self, weapon: &str, units: u64) {
+        {
+            let mut weapons = self.weapons();
+            match weapons.iter_mut().find(|summary| summary.weapon == weapon) {
+                Some(summary) => summary.units += units,
+                None => weapons.push(WeaponSummary {
+                    weapon: weapon.to_string(),
+                    units,
+                    finished: 0,
+                    skipped: 0,
+                    failed: 0,
+                    seconds: 0.0,
+                    combinations: 0,
+                    best_dps: None,
+                }),
+            }
+        }
+        self.run_bar.inc_length(units);
+        self.emit(ProgressEvent::UnitsQueued { weapon: weapon.to_string(), units });
+    }
+
+    pub fn start_unit(&self, unit: &WorkUnit) {
+        *self.current.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(CurrentUnit {
+            started: Instant::now(),
+            combinations_before: self.combinations.load(Ordering::Relaxed),
+        });
+        self.unit_bar.reset();
+        self.unit_bar.set_length(0);
+        self.unit_bar.set_message(unit.id());
+        self.emit(ProgressEvent::UnitStarted { unit: unit.id() });
+    }
+
+    /// Records the outcome of a unit. Skipped units don't need `start_unit`.
+    pub fn finish_unit(&self, unit: &WorkUnit, outcome: &UnitOutcome, max_dps: Option<f64>) {
+        let current = self.current.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+        let (seconds, combinations) = match (outcome, current) {
+            (UnitOutcome::Skipped, _) | (_, None) => (0.0, 0),
+            (_, Some(current)) => (
+                current.started.elapsed().as_secs_f64(),
+                self.combinations.load(Ordering::Relaxed) - current.combinations_before,
+            ),
+        };
+
+        let finished_weapon = {
+            let mut weapons = self.weapons();
+            weapons.iter_mut().find(|summary| summary.weapon == unit.weapon.to_string()).and_then(|summary| {
+                match outcome {
+                    UnitOutcome::Finished => summary.finished += 1,
+                    UnitOutcome::Skipped => summary.skipped += 1,
+                    UnitOutcome::Failed(_) => summary.failed += 1,
+                }
+                summary.seconds += seconds;
+                summary.combinations += combinations;
+                if let Some(dps) = max_dps {
+                    summary.best_dps = Some(summary.best_dps.map_or(dps, |best| best.max(dps)));
+                }
+                (summary.done() == summary.units).then(|| summary.clone())
+            })
+        };
+
+        self.run_bar.inc(1);
+        self.emit(ProgressEvent::UnitFinished {
+            unit: unit.id(),
+            outcome: match outcome {
+                UnitOutcome::Finished => "finished".to_string(),
+                UnitOutcome::Skipped => "skipped".to_string(),
+                UnitOutcome::Failed(e) => format!("failed: {}", e),
+            },
+            seconds,
+            combinations,
+            max_dps,
+        });
+        if let Some(summary) = finished_weapon {
+            self.run_bar.set_message(format!("{} done", summary.weapon));
+            self.emit(ProgressEvent::WeaponFinished {
+                weapon: summary.weapon,
+                seconds: summary.seconds,
+                best_dps: summary.best_dps,
+            });
+        }
+    }
+
+    /// Clears the bars from the display.
+    pub fn finish(&self) {
+        self.unit_bar.finish_and_clear();
+        self.run_bar.finish_and_clear();
+    }
+
+    /// Clears the display and reports the run.
+    pub fn summary(&self, run: &RunCheckpoint) -> RunSummary {
+        self.finish();
+
+        let seconds = self.started.elapsed().as_secs_f64();
+        let weapons = self.weapons().clone();
+        let combinations = self.combinations.load(Ordering::Relaxed);
+        RunSummary {
+            run_id: run.id.clone(),
+            command: run.command.clone(),
+            completed: run.completed,
+            seconds,
+            units: weapons.iter().map(|summary| summary.units).sum(),
+            finished: weapons.iter().map(|summary| summary.finished).sum(),
+            skipped: weapons.iter().map(|summary| summary.skipped).sum(),
+            failed: weapons.iter().map(|summary| summary.failed).sum(),
+            combinations,
+            combinations_per_second: if seconds > 0.0 { combinations as f64 / seconds } else { 0.0 },
+            weapons,
+        }
+    }
+}
+
+impl Default for RunProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OptimizationProgress for RunProgress {
+    fn started(&self, total_combinations: u64) {
+        self.unit_bar.set_length(total_combinations);
+    }
+
+    fn evaluated(&self, combinations: u64) {
+        self.combinations.fetch_add(combinations, Ordering::Relaxed);
+        self.unit_bar.inc(combinations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::EnemyProfile;
+    use crate::grid::Objective;
+    use crate::weapons::Weapon;
+
+    #[test]
+    fn test_run_summary() {
+        let progress = RunProgress::hidden();
+        let unit = |weapon: Weapon, weak_point_hit_chance| WorkUnit {
+            weapon,
+            valby: false,
+            enzo: false,
+            objective: Objective::Sustained,
+            enemy: EnemyProfile::Colossus,
+            weak_point_hit_chance,
+        };
+        progress.queue("Thundercage", 2);
+        progress.queue("EnduringLegacy", 1);
+
+        progress.start_unit(&unit(Weapon::Thundercage, 0.5));
+        progress.started(100);
+        progress.evaluated(60);
+        progress.evaluated(40);
+        progress.finish_unit(&unit(Weapon::Thundercage, 0.5), &UnitOutcome::Finished, Some(1200.0));
+        progress.finish_unit(&unit(Weapon::Thundercage, 1.0), &UnitOutcome::Skipped, None);
+        progress.start_unit(&unit(Weapon::EnduringLegacy, 0.5));
+        progress.finish_unit(&unit(Weapon::EnduringLegacy, 0.5), &UnitOutcome::Failed("timeout".to_string()), None);
+
+        let run = RunCheckpoint::new(Some("run".to_string()), "UpdateAll".to_string());
+        let summary = progress.summary(&run);
+
+        assert_eq!((summary.units, summary.finished, summary.skipped, summary.failed), (3, 1, 1, 1));
+        assert_eq!(summary.combinations, 100);
+        assert_eq!(summary.weapons[0].weapon, "Thundercage");
+        assert_eq!(summary.weapons[0].combinations, 100);
+        assert_eq!(summary.weapons[0].best_dps, Some(1200.0));
+        assert_eq!(summary.weapons[1].failed, 1);
+        assert!(serde_json::to_value(&summary).unwrap()["weapons"].is_array());
+    }
+}
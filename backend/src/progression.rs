@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+use crate::weapons::{SlotCounts, Weapon, WeaponBaseStats};
+
+pub const MAX_WEAPON_LEVEL: u32 = 100;
+
+// ATK and module capacity by level aren't in the game data we have, so the
+// player supplies what the game shows for their weapon instead.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressError {
+    LevelOutOfRange(u32),
+    InvalidAtk { atk: f64, max_level_atk: f64 },
+    TooManyModuleSlots { slots: usize, max: usize },
+    TooManyCoreSlots { slots: usize, max: usize },
+}
+
+impl std::fmt::Display for ProgressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProgressError::LevelOutOfRange(level) => write!(f, "Level {} is outside 1 to {}", level, MAX_WEAPON_LEVEL),
+            ProgressError::InvalidAtk { atk, max_level_atk } => {
+                write!(f, "ATK {} must be positive and at most the max-level ATK {}", atk, max_level_atk)
+            },
+            ProgressError::TooManyModuleSlots { slots, max } => write!(f, "{} module slots, the weapon has {}", slots, max),
+            ProgressError::TooManyCoreSlots { slots, max } => write!(f, "{} core slots, the weapon has {}", slots, max),
+        }
+    }
+}
+
+impl std::error::Error for ProgressError {}
+
+/// How far a player has progressed a weapon, as shown in game.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WeaponProgress {
+    /// 1 to 100
+    pub level: u32,
+    /// Weapon ATK at this level
+    pub atk: f64,
+    /// Modules that fit in the weapon's module capacity
+    pub module_slots: usize,
+    /// Unlocked core slots, each allows one substat roll
+    pub core_slots: usize,
+}
+
+impl WeaponProgress {
+    /// Max level and fully unlocked.
+    pub fn max_level(weapon: Weapon) -> Self {
+        let slots = weapon.slot_counts();
+        WeaponProgress {
+            level: MAX_WEAPON_LEVEL,
+            atk: WeaponBaseStats::get(weapon).base_atk,
+            module_slots: slots.modules,
+            core_slots: slots.rolls,
+        }
+    }
+
+    pub fn validate(&self, weapon: Weapon) -> Result<(), ProgressError> {
+        if !(1..=MAX_WEAPON_LEVEL).contains(&self.level) {
+            return Err(ProgressError::LevelOutOfRange(self.level));
+        }
+        let max_level_atk = WeaponBaseStats::get(weapon).base_atk;
+        if !(self.atk > 0.0 && self.atk <= max_level_atk) {
+            return Err(ProgressError::InvalidAtk { atk: self.atk, max_level_atk });
+        }
+        let slots = weapon.slot_counts();
+        if self.module_slots > slots.modules {
+            return Err(ProgressError::TooManyModuleSlots { slots: self.module_slots, max: slots.modules });
+        }
+        if self.core_slots > slots.rolls {
+            return Err(ProgressError::TooManyCoreSlots { slots: self.core_slots, max: slots.rolls });
+        }
+        Ok(())
+    }
+
+    /// Max-level base stats with this level's ATK.
+    pub fn base_stats(self, weapon: Weapon) -> WeaponBaseStats {
+        let mut stats = WeaponBaseStats::get(weapon);
+        stats.base_atk = self.atk;
+        stats
+    }
+
+    pub fn slot_counts(self, weapon: Weapon) -> SlotCounts {
+        self.unlocked_slots(weapon.slot_counts())
+    }
+
+    /// The unlocked part of `slots`, the layout of the fully built weapon.
+    pub fn unlocked_slots(self, slots: SlotCounts) -> SlotCounts {
+        SlotCounts {
+            modules: self.module_slots.min(slots.modules),
+            rolls: self.core_slots.min(slots.rolls),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let weapon = Weapon::Thundercage;
+        let max_atk = WeaponBaseStats::get(weapon).base_atk;
+        let max_level = WeaponProgress::max_level(weapon);
+        let halfway = WeaponProgress { level: 50, atk: max_atk / 2.0, module_slots: 8, core_slots: 2 };
+
+        assert_eq!(max_level.base_stats(weapon).base_atk, max_atk);
+        assert_eq!(max_level.slot_counts(weapon), weapon.slot_counts());
+        assert!(max_level.validate(weapon).is_ok());
+        assert!(halfway.validate(weapon).is_ok());
+        assert_eq!(halfway.base_stats(weapon).base_atk, max_atk / 2.0);
+        assert_eq!(halfway.slot_counts(weapon), SlotCounts { modules: 8, rolls: 2 });
+
+        assert_eq!(WeaponProgress { level: 0, ..halfway }.validate(weapon), Err(ProgressError::LevelOutOfRange(0)));
+        assert_eq!(WeaponProgress { level: 101, ..halfway }.validate(weapon), Err(ProgressError::LevelOutOfRange(101)));
+        assert!(WeaponProgress { atk: max_atk * 2.0, ..halfway }.validate(weapon).is_err());
+        assert!(WeaponProgress { module_slots: 11, ..halfway }.validate(weapon).is_err());
+        assert!(WeaponProgress { core_slots: 5, ..halfway }.validate(weapon).is_err());
+    }
+
+    #[test]
+    fn test_progress_narrows_grid_slots() {
+        let weapon = Weapon::Thundercage;
+        let halfway = WeaponProgress { level: 50, atk: WeaponBaseStats::get(weapon).base_atk / 2.0, module_slots: 8, core_slots: 2 };
+        let grid = crate::grid::ComputationGrid { module_slots: Some(6), roll_slots: Some(3), ..Default::default() };
+        let config = crate::calculate::OptimizationConfig { progress: Some(halfway), ..grid.optimization_config(&grid.points()[0]) };
+
+        assert_eq!(config.slot_counts(weapon), SlotCounts { modules: 6, rolls: 2 });
+        let max_level = crate::calculate::OptimizationConfig { progress: Some(WeaponProgress::max_level(weapon)), ..config };
+        assert_eq!(max_level.slot_counts(weapon), SlotCounts { modules: 6, rolls: 3 });
+    }
+}
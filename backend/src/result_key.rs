@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::calculate::EnemyProfile;
+use crate::grid::{BuffSet, Objective};
+use crate::weapons::Weapon;
+
+/// Weak point hit chances are keyed in basis points, finer than any sweep step.
+const BASIS_POINTS: f64 = 10_000.0;
+
+/// Identifies one stored optimization result. Hit chances are quantized so
+/// keys built from `0.1 + 0.2` and `0.3` are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ResultKey {
+    pub weapon: Weapon,
+    pub wp_basis_points: u16,
+    pub buffs: BuffSet,
+    pub objective: Objective,
+    pub enemy: EnemyProfile,
+}
+
+impl ResultKey {
+    pub fn new(weapon: Weapon, weak_point_hit_chance: f64, buffs: BuffSet, objective: Objective, enemy: EnemyProfile) -> Self {
+        ResultKey {
+            weapon,
+            wp_basis_points: (weak_point_hit_chance.clamp(0.0, 1.0) * BASIS_POINTS).round() as u16,
+            buffs,
+            objective,
+            enemy,
+        }
+    }
+
+    /// Key of a result served by the API, which only has the default objective and enemy.
+    pub fn served(weapon: Weapon, weak_point_hit_chance: f64, buffs: BuffSet) -> Self {
+        ResultKey::new(weapon, weak_point_hit_chance, buffs, Objective::default(), EnemyProfile::default())
+    }
+
+    pub fn weak_point_hit_chance(&self) -> f64 {
+        self.wp_basis_points as f64 / BASIS_POINTS
+    }
+}
+
+/// `{weapon}_{chance:.4}_{buffs}_{objective}_{enemy}`, e.g.
+/// `Thundercage_0.5000_valby_Sustained_Colossus`.
+impl fmt::Display for ResultKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}_{:.4}_{}_{}_{}",
+            self.weapon,
+            self.weak_point_hit_chance(),
+            self.buffs,
+            self.objective,
+            self.enemy,
+        )
+    }
+}
+
+impl FromStr for ResultKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('_').collect();
+        let [weapon, chance, buffs, objective, enemy] = parts[..] else {
+            return Err(format!("Invalid result key {}, expected weapon_chance_buffs_objective_enemy", s));
+        };
+        let chance: f64 = chance.parse().map_err(|_| format!("Invalid weak point hit chance {}", chance))?;
+        if !(0.0..=1.0).contains(&chance) {
+            return Err(format!("Weak point hit chance {} is outside 0..=1", chance));
+        }
+        Ok(ResultKey::new(
+            weapon.parse().map_err(|_| format!("Unknown weapon {}", weapon))?,
+            chance,
+            buffs.parse()?,
+            objective.parse().map_err(|_| format!("Unknown objective {}", objective))?,
+            enemy.parse().map_err(|_| format!("Unknown enemy {}", enemy))?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sweep::sweep_grid;
+
+    #[test]
+    fn test_result_key_round_trip() {
+        let buff_sets = ["none", "valby", "enzo", "valby+enzo"].map(|buffs| buffs.parse::<BuffSet>().unwrap());
+        for &weapon in Weapon::all() {
+            for weak_point_hit_chance in sweep_grid(0.0025) {
+                for buffs in buff_sets {
+                    for (objective, enemy) in [(Objective::Sustained, EnemyProfile::Colossus), (Objective::InfiniteAmmo, EnemyProfile::Standard)] {
+                        let key = ResultKey::new(weapon, weak_point_hit_chance, buffs, objective, enemy);
+                        assert_eq!(key.weak_point_hit_chance(), weak_point_hit_chance);
+                        assert_eq!(key.to_string().parse::<ResultKey>(), Ok(key));
+                    }
+                }
+            }
+        }
+
+        let summed = ResultKey::served(Weapon::Python, 0.1 + 0.2, BuffSet { valby: true, enzo: false });
+        assert_eq!(summed, ResultKey::served(Weapon::Python, 0.3, BuffSet { valby: true, enzo: false }));
+        assert_eq!(summed.to_string(), "Python_0.3000_valby_Sustained_Colossus");
+        assert!("Python_0.5_none_Sustained".parse::<ResultKey>().is_err());
+        assert!("Pythonic_0.5_none_Sustained_Colossus".parse::<ResultKey>().is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+
+use crate::calculate::{OptimizationConfig, OptimizationResult, evaluate_build, optimize_weapon, optimize_weapon_sweep};
+use crate::weapons::{Module, Roll, SlotCounts, WeaponBaseStats};
+
+/// Number of grid points a continuous distribution is discretized into.
+const DISTRIBUTION_RESOLUTION: usize = 101;
+
+/// How often a player hits weak points.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WeakPointDistribution {
+    Beta { alpha: f64, beta: f64 },
+    /// (weak point hit chance, weight) pairs, e.g. from a player's own logs.
+    /// Weights don't have to be normalized.
+    Histogram { bins: Vec<(f64, f64)> },
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum RobustObjective {
+    Expected,
+    /// Maximize the given percentile (0 to 1) of the DPS distribution.
+    Percentile(f64),
+}
+
+impl WeakPointDistribution {
+    /// Normalized (chance, probability) pairs sorted by chance.
+    pub fn support(&self) -> Vec<(f64, f64)> {
+        let mut points: Vec<(f64, f64)> = match self {
+            WeakPointDistribution::Beta { alpha, beta } => (0..DISTRIBUTION_RESOLUTION)
+                .map(|i| {
+                    // Bin midpoints keep the density finite for alpha or beta below 1
+                    let chance = (i as f64 + 0.5) / DISTRIBUTION_RESOLUTION as f64;
+                    let density = chance.powf(alpha - 1.0) * (1.0 - chance).powf(beta - 1.0);
+                    (chance, density)
+                })
+                .collect(),
+            WeakPointDistribution::Histogram { bins } => bins
+                .iter()
+                .map(|&(chance, weight)| (chance.clamp(0.0, 1.0), weight.max(0.0)))
+                .collect(),
+        };
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let total: f64 = points.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return Vec::new();
+        }
+        points.into_iter().map(|(chance, weight)| (chance, weight / total)).collect()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.support().iter().map(|(chance, p)| chance * p).sum()
+    }
+
+    pub fn quantile(&self, q: f64) -> f64 {
+        let support = self.support();
+        let mut cumulative = 0.0;
+        for &(chance, p) in &support {
+            cumulative += p;
+            if cumulative >= q {
+                return chance;
+            }
+        }
+        support.last().map(|(chance, _)| *chance).unwrap_or(0.0)
+    }
+}
+
+impl RobustObjective {
+    /// The single weak point hit chance whose optimum also optimizes the
+    /// objective. A build's DPS is linear and non-decreasing in the chance,
+    /// so its mean is the DPS at the mean chance and its percentiles are the
+    /// DPS at the matching percentile of the chance.
+    pub fn equivalent_chance(&self, distribution: &WeakPointDistribution) -> f64 {
+        match self {
+            RobustObjective::Expected => distribution.mean(),
+            RobustObjective::Percentile(q) => distribution.quantile(q.clamp(0.0, 1.0)),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RegretPoint {
+    pub weak_point_hit_chance: f64,
+    pub probability: f64,
+    pub optimal_dps: f64,
+    pub build_dps: f64,
+    pub regret: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RobustResult {
+    pub objective: RobustObjective,
+    pub equivalent_chance: f64,
+    pub objective_dps: f64,
+    #[serde(flatten)]
+    pub result: OptimizationResult,
+    pub regret: Vec<RegretPoint>,
+    pub expected_regret: f64,
+    pub max_regret: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn optimize_weapon_robust(
+    base_stats: WeaponBaseStats,
+    slots: SlotCounts,
+    available_rolls: Vec<Roll>,
+    available_modules: Vec<Module>,
+    module_combinations: Vec<Vec<usize>>,
+    distribution: &WeakPointDistribution,
+    objective: RobustObjective,
+    config: OptimizationConfig,
+) -> RobustResult {
+    let equivalent_chance = objective.equivalent_chance(distribution);
+    let result = optimize_weapon(
+        base_stats,
+        slots,
+        available_rolls.clone(),
+        available_modules.clone(),
+        module_combinations.clone(),
+        equivalent_chance,
+        config.clone(),
+    ).await;
+
+    let support = distribution.support();
+    let chances: Vec<f64> = support.iter().map(|(chance, _)| *chance).collect();
+    let optima = optimize_weapon_sweep(
+        base_stats,
+        slots,
+        available_rolls,
+        available_modules,
+        module_combinations,
+        &chances,
+        config.clone(),
+    );
+
+    let modules: Vec<Module> = result.best_modules.iter().map(|(m, _)| m.clone()).collect();
+    let regret: Vec<RegretPoint> = support
+        .iter()
+        .zip(optima)
+        .map(|(&(chance, probability), optimum)| {
+            let build_dps = evaluate_build(&base_stats, &result.best_rolls, &modules, &result.best_reactor, chance, &config);
+            RegretPoint {
+                weak_point_hit_chance: chance,
+                probability,
+                optimal_dps: optimum.max_dps,
+                build_dps,
+                regret: (optimum.max_dps - build_dps).max(0.0),
+            }
+        })
+        .collect();
+
+    let objective_dps = evaluate_build(&base_stats, &result.best_rolls, &modules, &result.best_reactor, equivalent_chance, &config);
+    let expected_regret = regret.iter().map(|r| r.regret * r.probability).sum();
+    let max_regret = regret.iter().map(|r| r.regret).fold(0.0, f64::max);
+
+    RobustResult {
+        objective,
+        equivalent_chance,
+        objective_dps,
+        result,
+        regret,
+        expected_regret,
+        max_regret,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_mean_and_quantile() {
+        let distribution = WeakPointDistribution::Histogram { bins: vec![(0.2, 1.0), (0.6, 2.0), (1.0, 1.0)] };
+
+        assert!((distribution.mean() - 0.6).abs() < 1e-9);
+        assert_eq!(distribution.quantile(0.1), 0.2);
+        assert_eq!(distribution.quantile(0.5), 0.6);
+        assert_eq!(distribution.quantile(0.9), 1.0);
+    }
+
+    #[test]
+    fn test_beta_mean() {
+        let distribution = WeakPointDistribution::Beta { alpha: 2.0, beta: 6.0 };
+
+        assert!((distribution.mean() - 0.25).abs() < 1e-3);
+    }
+}
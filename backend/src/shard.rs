@@ -0,0 +1,465 @@
+use log::warn;
+use mongodb::bson::{self, doc};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument, UpdateOptions};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+use strum_macros::{Display, EnumString};
+
+use crate::checkpoint::WorkUnit;
+use crate::jobs::now_millis;
+use crate::output::Output;
+
+pub const UNITS_COLLECTION: &str = "worker_units";
+
+pub const DEFAULT_UNIT_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumString, Display)]
+pub enum UnitStatus {
+    Queued,
+    Leased,
+    Done,
+    Failed,
+}
+
+/// A work unit of a sharded run. Agents lease units one at a time; a unit
+/// whose lease expired goes to the next agent that asks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardUnit {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub run_id: String,
+    pub unit: WorkUnit,
+    pub status: UnitStatus,
+    pub attempts: u32,
+    pub lease_owner: Option<String>,
+    pub lease_expires_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl ShardUnit {
+    pub fn new(run_id: &str, unit: WorkUnit) -> Self {
+        ShardUnit {
+            id: format!("{}/{}", run_id, unit.id()),
+            run_id: run_id.to_string(),
+            unit,
+            status: UnitStatus::Queued,
+            attempts: 0,
+            lease_owner: None,
+            lease_expires_at: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ShardCounts {
+    pub queued: u64,
+    pub leased: u64,
+    pub done: u64,
+    pub failed: u64,
+}
+
+impl ShardCounts {
+    pub fn remaining(&self) -> u64 {
+        self.queued + self.leased
+    }
+}
+
+fn units(db: &mongodb::Database) -> mongodb::Collection<ShardUnit> {
+    db.collection::<ShardUnit>(UNITS_COLLECTION)
+}
+
+fn unsupported() -> Box<dyn std::error::Error> {
+    "Sharded runs need a mongo or sqlite: output shared by all agents".into()
+}
+
+/// Adds the units of `run_id` that aren't queued yet, so coordinating the
+/// same run twice doesn't redo anything. Returns how many were added.
+pub async fn enqueue(output: &Output, run_id: &str, work: &[WorkUnit]) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut added = 0;
+    match output {
+        Output::Mongo(db) => {
+            for unit in work {
+                let shard_unit = ShardUnit::new(run_id, unit.clone());
+                let result = units(db).update_one(
+                    doc! { "_id": &shard_unit.id },
+                    doc! { "$setOnInsert": bson::to_document(&shard_unit)? },
+                    UpdateOptions::builder().upsert(true).build(),
+                ).await?;
+                added += result.upserted_id.is_some() as u64;
+            }
+        },
+        Output::Sqlite(store) => {
+            let (run_id, work) = (run_id.to_string(), work.to_vec());
+            added = store.blocking(move |connection| {
+                let transaction = connection.unchecked_transaction()?;
+                let mut added = 0;
+                for unit in work {
+                    let shard_unit = ShardUnit::new(&run_id, unit.clone());
+                    added += transaction.execute(
+                        "INSERT OR IGNORE INTO worker_units (id, run_id, unit, status, attempts) VALUES (?1, ?2, ?3, ?4, 0)",
+                        params![shard_unit.id, run_id, serde_json::to_string(&unit)?, UnitStatus::Queued.to_string()],
+                    )? as u64;
+                }
+                transaction.commit()?;
+                Ok(added)
+            }).await?;
+        },
+        Output::JsonLines(_) => return Err(unsupported()),
+    }
+    Ok(added)
+}
+
+/// Atomically leases the next claimable unit of `run_id` to `worker_id`:
+/// a queued one, or a leased one whose agent stopped before finishing it.
+pub async fn claim(
+    output: &Output,
+    run_id: &str,
+    worker_id: &str,
+    lease_millis: i64,
+    max_attempts: u32,
+) -> Result<Option<ShardUnit>, Box<dyn std::error::Error>> {
+    let now = now_millis();
+    match output {
+        Output::Mongo(db) => {
+            let options = FindOneAndUpdateOptions::builder()
+                .sort(doc! { "_id": 1 })
+                .return_document(ReturnDocument::After)
+                .build();
+            Ok(units(db).find_one_and_update(
+                doc! {
+                    "run_id": run_id,
+                    "$or": [
+                        { "status": UnitStatus::Queued.to_string() },
+                        { "status": UnitStatus::Leased.to_string(), "lease_expires_at": { "$lt": now } },
+                    ],
+                    "attempts": { "$lt": max_attempts },
+                },
+                doc! {
+                    "$set": {
+                        "status": UnitStatus::Leased.to_string(),
+                        "lease_owner": worker_id,
+                        "lease_expires_at": now + lease_millis,
+                    },
+                    "$inc": { "attempts": 1 },
+                },
+                options,
+            ).await?)
+        },
+        Output::Sqlite(store) => {
+            // A single statement, so two agents can't lease the same unit
+            let (run_id_param, worker_id_param) = (run_id.to_string(), worker_id.to_string());
+            let claimed = store.blocking(move |connection| Ok(connection.query_row(
+                "UPDATE worker_units
+                 SET status = ?1, lease_owner = ?2, lease_expires_at = ?3, attempts = attempts + 1
+                 WHERE id = (
+                     SELECT id FROM worker_units
+                     WHERE run_id = ?4
+                       AND (status = ?5 OR (status = ?1 AND lease_expires_at < ?6))
+                       AND attempts < ?7
+                     ORDER BY id LIMIT 1
+                 )
+                 RETURNING id, unit, attempts, error",
+                params![
+                    UnitStatus::Leased.to_string(),
+                    worker_id_param,
+                    now + lease_millis,
+                    run_id_param,
+                    UnitStatus::Queued.to_string(),
+                    now,
+                    max_attempts,
+                ],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?, row.get::<_, Option<String>>(3)?)),
+            ).optional()?)).await?;
+            let Some((id, unit, attempts, error)) = claimed else {
+                return Ok(None);
+            };
+            Ok(Some(ShardUnit {
+                id,
+                run_id: run_id.to_string(),
+                unit: serde_json::from_str(&unit)?,
+                status: UnitStatus::Leased,
+                attempts,
+                lease_owner: Some(worker_id.to_string()),
+                lease_expires_at: Some(now + lease_millis),
+                error,
+            }))
+        },
+        Output::JsonLines(_) => Err(unsupported()),
+    }
+}
+
+/// Extends the lease `worker_id` holds on a unit. Returns false if the lease
+/// was lost to another agent.
+pub async fn heartbeat(output: &Output, id: &str, worker_id: &str, lease_millis: i64) -> Result<bool, Box<dyn std::error::Error>> {
+    let lease_expires_at = now_millis() + lease_millis;
+    match output {
+        Output::Mongo(db) => {
+            let result = units(db).update_one(
+                doc! { "_id": id, "lease_owner": worker_id, "status": UnitStatus::Leased.to_string() },
+                doc! { "$set": { "lease_expires_at": lease_expires_at } },
+                None,
+            ).await?;
+            Ok(result.matched_count > 0)
+        },
+        Output::Sqlite(store) => {
+            let (id, worker_id) = (id.to_string(), worker_id.to_string());
+            Ok(store.blocking(move |connection| Ok(connection.execute(
+                "UPDATE worker_units SET lease_expires_at = ?1 WHERE id = ?2 AND lease_owner = ?3 AND status = ?4",
+                params![lease_expires_at, id, worker_id, UnitStatus::Leased.to_string()],
+            )?)).await? > 0)
+        },
+        Output::JsonLines(_) => Err(unsupported()),
+    }
+}
+
+/// Runs `work` on a leased unit, renewing the lease every third of
+/// `lease_millis` so a unit that outlasts its lease isn't handed to another
+/// agent. The renewals run on their own task, `work` may block its thread.
+pub async fn with_heartbeat<F: Future>(
+    output: &Output,
+    shard_unit: &ShardUnit,
+    worker_id: &str,
+    lease_millis: i64,
+    work: F,
+) -> F::Output {
+    let heartbeat = {
+        let (output, id, worker_id) = (output.clone(), shard_unit.id.clone(), worker_id.to_string());
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis((lease_millis / 3).max(1) as u64));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match heartbeat(&output, &id, &worker_id, lease_millis).await {
+                    Ok(true) => {},
+                    Ok(false) => {
+                        warn!("Lost the lease on unit {}", id);
+                        break;
+                    },
+                    Err(e) => warn!("Heartbeat for unit {} failed: {}", id, e),
+                }
+            }
+        })
+    };
+    let outcome = work.await;
+    heartbeat.abort();
+    outcome
+}
+
+/// Records the outcome of a leased unit. Failed units go back in the queue
+/// until they are out of attempts. Does nothing if the lease was lost, the
+/// result itself is an idempotent upsert either way.
+pub async fn complete(
+    output: &Output,
+    shard_unit: &ShardUnit,
+    worker_id: &str,
+    outcome: Result<(), String>,
+    max_attempts: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (status, error) = match outcome {
+        Ok(()) => (UnitStatus::Done, None),
+        Err(e) if shard_unit.attempts < max_attempts => (UnitStatus::Queued, Some(e)),
+        Err(e) => (UnitStatus::Failed, Some(e)),
+    };
+    match output {
+        Output::Mongo(db) => {
+            units(db).update_one(
+                doc! { "_id": &shard_unit.id, "lease_owner": worker_id },
+                doc! { "$set": {
+                    "status": status.to_string(),
+                    "error": error,
+                    "lease_owner": bson::Bson::Null,
+                    "lease_expires_at": bson::Bson::Null,
+                } },
+                None,
+            ).await?;
+        },
+        Output::Sqlite(store) => {
+            let (id, worker_id) = (shard_unit.id.clone(), worker_id.to_string());
+            store.blocking(move |connection| {
+                connection.execute(
+                    "UPDATE worker_units SET status = ?1, error = ?2, lease_owner = NULL, lease_expires_at = NULL
+                     WHERE id = ?3 AND lease_owner = ?4",
+                    params![status.to_string(), error, id, worker_id],
+                )?;
+                Ok(())
+            }).await?;
+        },
+        Output::JsonLines(_) => return Err(unsupported()),
+    }
+    Ok(())
+}
+
+/// Marks units whose agent stopped on their last attempt as failed, since
+/// `claim` will never hand them out again.
+pub async fn fail_abandoned(output: &Output, run_id: &str, max_attempts: u32) -> Result<u64, Box<dyn std::error::Error>> {
+    let now = now_millis();
+    let error = "Agent stopped before finishing the unit";
+    match output {
+        Output::Mongo(db) => {
+            let result = units(db).update_many(
+                doc! {
+                    "run_id": run_id,
+                    "status": UnitStatus::Leased.to_string(),
+                    "lease_expires_at": { "$lt": now },
+                    "attempts": { "$gte": max_attempts },
+                },
+                doc! { "$set": {
+                    "status": UnitStatus::Failed.to_string(),
+                    "error": error,
+                    "lease_owner": bson::Bson::Null,
+                    "lease_expires_at": bson::Bson::Null,
+                } },
+                None,
+            ).await?;
+            Ok(result.modified_count)
+        },
+        Output::Sqlite(store) => {
+            let run_id = run_id.to_string();
+            Ok(store.blocking(move |connection| Ok(connection.execute(
+                "UPDATE worker_units SET status = ?1, error = ?2, lease_owner = NULL, lease_expires_at = NULL
+                 WHERE run_id = ?3 AND status = ?4 AND lease_expires_at < ?5 AND attempts >= ?6",
+                params![UnitStatus::Failed.to_string(), error, run_id, UnitStatus::Leased.to_string(), now, max_attempts],
+            )?)).await? as u64)
+        },
+        Output::JsonLines(_) => Err(unsupported()),
+    }
+}
+
+pub async fn counts(output: &Output, run_id: &str) -> Result<ShardCounts, Box<dyn std::error::Error>> {
+    let statuses = [UnitStatus::Queued, UnitStatus::Leased, UnitStatus::Done, UnitStatus::Failed];
+    let mut counts = Vec::new();
+    for status in statuses {
+        let count = match output {
+            Output::Mongo(db) => units(db).count_documents(doc! { "run_id": run_id, "status": status.to_string() }, None).await?,
+            Output::Sqlite(store) => {
+                let run_id = run_id.to_string();
+                store.blocking(move |connection| Ok(connection.query_row(
+                    "SELECT COUNT(*) FROM worker_units WHERE run_id = ?1 AND status = ?2",
+                    params![run_id, status.to_string()],
+                    |row| row.get(0),
+                )?)).await?
+            },
+            Output::JsonLines(_) => return Err(unsupported()),
+        };
+        counts.push(count);
+    }
+    Ok(ShardCounts { queued: counts[0], leased: counts[1], done: counts[2], failed: counts[3] })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::EnemyProfile;
+    use crate::grid::Objective;
+    use crate::weapons::Weapon;
+
+    fn unit(weak_point_hit_chance: f64) -> WorkUnit {
+        WorkUnit {
+            weapon: Weapon::Thundercage,
+            valby: false,
+            enzo: false,
+            objective: Objective::Sustained,
+            enemy: EnemyProfile::Colossus,
+            weak_point_hit_chance,
+        }
+    }
+
+    const AGENT_DB: &str = "SHARD_TEST_AGENT_DB";
+    const AGENT_ID: &str = "SHARD_TEST_AGENT_ID";
+    const AGENT_LEASE_MILLIS: i64 = 150;
+
+    /// One agent of `test_agent_processes_share_a_run`, in a child process.
+    /// Units take several leases to compute, blocking the thread the way an
+    /// optimization does, so only the heartbeat keeps them leased.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "run in child processes by test_agent_processes_share_a_run"]
+    async fn agent_process() {
+        let output = Output::sqlite(std::path::Path::new(&std::env::var(AGENT_DB).unwrap())).unwrap();
+        let worker_id = std::env::var(AGENT_ID).unwrap();
+        while let Some(shard_unit) = claim(&output, "run", &worker_id, AGENT_LEASE_MILLIS, DEFAULT_UNIT_ATTEMPTS).await.unwrap() {
+            with_heartbeat(&output, &shard_unit, &worker_id, AGENT_LEASE_MILLIS, async {
+                std::thread::sleep(Duration::from_millis(AGENT_LEASE_MILLIS as u64 * 3));
+            }).await;
+            complete(&output, &shard_unit, &worker_id, Ok(()), DEFAULT_UNIT_ATTEMPTS).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_processes_share_a_run() {
+        let dir = std::env::temp_dir().join(format!("shard_processes_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shared.db");
+        let output = Output::sqlite(&path).unwrap();
+        let work: Vec<WorkUnit> = (1..=6).map(|i| unit(i as f64 / 6.0)).collect();
+        assert_eq!(enqueue(&output, "run", &work).await.unwrap(), 6);
+
+        // Test binaries re-run themselves, cargo only builds executables to
+        // spawn for integration tests
+        let agents: Vec<_> = (0..3)
+            .map(|i| {
+                std::process::Command::new(std::env::current_exe().unwrap())
+                    .args(["shard::tests::agent_process", "--exact", "--ignored", "--quiet"])
+                    .env(AGENT_DB, &path)
+                    .env(AGENT_ID, format!("agent-{}", i))
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut agent in agents {
+            assert!(agent.wait().unwrap().success());
+        }
+
+        // One attempt each: no lease expired while its unit was computed
+        assert_eq!(counts(&output, "run").await.unwrap(), ShardCounts { queued: 0, leased: 0, done: 6, failed: 0 });
+        let attempts: Vec<u32> = {
+            let connection = match &output {
+                Output::Sqlite(store) => store.connection(),
+                _ => unreachable!(),
+            };
+            let mut statement = connection.prepare("SELECT attempts FROM worker_units WHERE run_id = 'run'").unwrap();
+            let attempts = statement.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap();
+            attempts
+        };
+        assert_eq!(attempts, vec![1; 6]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_agents_lease_distinct_units() {
+        let dir = std::env::temp_dir().join(format!("shard_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shared.db");
+        // Two connections to one file, as two agent processes would have
+        let first = Output::sqlite(&path).unwrap();
+        let second = Output::sqlite(&path).unwrap();
+
+        let work = [unit(0.25), unit(0.5), unit(1.0)];
+        assert_eq!(enqueue(&first, "run", &work).await.unwrap(), 3);
+        assert_eq!(enqueue(&second, "run", &work).await.unwrap(), 0);
+
+        let a = claim(&first, "run", "a", 60_000, 2).await.unwrap().unwrap();
+        let b = claim(&second, "run", "b", 60_000, 2).await.unwrap().unwrap();
+        assert_ne!(a.id, b.id);
+
+        complete(&first, &a, "a", Ok(()), 2).await.unwrap();
+        complete(&second, &b, "a", Ok(()), 2).await.unwrap();
+        assert_eq!(counts(&first, "run").await.unwrap(), ShardCounts { queued: 1, leased: 1, done: 1, failed: 0 });
+
+        // An expired lease is handed to the next agent
+        let c = claim(&first, "run", "c", -1, 2).await.unwrap().unwrap();
+        let d = claim(&second, "run", "d", 60_000, 2).await.unwrap().unwrap();
+        assert_eq!(c.id, d.id);
+        assert_eq!(d.attempts, 2);
+
+        complete(&second, &d, "d", Err("timeout".to_string()), 2).await.unwrap();
+        complete(&second, &b, "b", Ok(()), 2).await.unwrap();
+        assert_eq!(counts(&second, "run").await.unwrap(), ShardCounts { queued: 0, leased: 0, done: 2, failed: 1 });
+        assert!(claim(&first, "run", "a", 60_000, 2).await.unwrap().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
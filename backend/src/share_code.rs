@@ -0,0 +1,457 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use utoipa::ToSchema;
+
+use crate::calculate::{evaluate_build, is_valid_combination, BuildConstraints, EnemyProfile};
+use crate::grid::{BuffSet, GridConfig, Objective};
+use crate::result_key::ResultKey;
+use crate::weapons::{get_available_modules, get_available_rolls, Module, Roll, RollType, Weapon, WeaponBaseStats};
+
+/// Bumped whenever the byte layout changes. Older versions keep decoding.
+pub const SHARE_CODE_VERSION: u8 = 2;
+
+const VALBY: u8 = 1;
+const ENZO: u8 = 1 << 1;
+const INFINITE_AMMO: u8 = 1 << 2;
+const STANDARD_ENEMY: u8 = 1 << 3;
+
+/// Roll types by their position in a code, independent of `RollType`'s order.
+const ROLL_TYPES: [RollType; 7] = [
+    RollType::Atk,
+    RollType::ElementAtk,
+    RollType::WeakPointDamage,
+    RollType::Crit,
+    RollType::CritDamage,
+    RollType::RoundsPerMagazine,
+    RollType::ColossusDamage,
+];
+
+/// Roll values are stored as `u16`s in units of `1 / roll_scale`: hundredths
+/// of a percent for percentage rolls, whole points for flat ones.
+fn roll_scale(roll_type: RollType) -> f64 {
+    match roll_type {
+        RollType::ElementAtk | RollType::ColossusDamage => 1.0,
+        _ => 10_000.0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShareCodeError {
+    Encoding(String),
+    UnsupportedVersion(u8),
+    Truncated,
+    UnknownWeapon(u8),
+    /// The weapon's module catalog changed since the code was made
+    CatalogChanged(Weapon),
+    UnknownModule(String),
+    InvalidModules(String),
+    /// More rolls or modules than a count byte holds
+    TooMany(usize),
+    InvalidRoll(String),
+    InvalidWeakPointChance(f64),
+}
+
+impl fmt::Display for ShareCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShareCodeError::Encoding(e) => write!(f, "Share code isn't valid base64: {}", e),
+            ShareCodeError::UnsupportedVersion(version) => write!(f, "Unsupported share code version {}", version),
+            ShareCodeError::Truncated => write!(f, "Share code is truncated"),
+            ShareCodeError::UnknownWeapon(index) => write!(f, "Share code has unknown weapon {}", index),
+            ShareCodeError::CatalogChanged(weapon) => write!(f, "The modules of {} changed since this code was made", weapon),
+            ShareCodeError::UnknownModule(module) => write!(f, "Unknown module {}", module),
+            ShareCodeError::InvalidModules(reason) => write!(f, "Invalid modules: {}", reason),
+            ShareCodeError::TooMany(count) => write!(f, "{} rolls or modules don't fit in a share code", count),
+            ShareCodeError::InvalidRoll(reason) => write!(f, "Invalid roll: {}", reason),
+            ShareCodeError::InvalidWeakPointChance(chance) => write!(f, "Weak point hit chance {} must be between 0 and 1", chance),
+        }
+    }
+}
+
+impl std::error::Error for ShareCodeError {}
+
+/// A full build as carried by a share code: the weapon, its rolls and
+/// modules, and the configuration it was made for. The reactor isn't shared.
+#[derive(Debug, Clone)]
+pub struct SharedBuild {
+    pub weapon: Weapon,
+    pub weak_point_hit_chance: f64,
+    pub buffs: BuffSet,
+    pub objective: Objective,
+    pub enemy: EnemyProfile,
+    pub rolls: Vec<Roll>,
+    pub modules: Vec<Module>,
+}
+
+/// A decoded build with its DPS recomputed against the current game data.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExpandedBuild {
+    pub code: String,
+    pub weapon: Weapon,
+    pub weak_point_hit_chance: f64,
+    /// none, valby or enzo
+    pub buffs: String,
+    pub objective: Objective,
+    pub enemy: EnemyProfile,
+    pub rolls: Vec<Roll>,
+    pub modules: Vec<Module>,
+    pub dps: f64,
+}
+
+impl SharedBuild {
+    pub fn expand(self, code: String) -> ExpandedBuild {
+        let dps = evaluate_build(
+            &WeaponBaseStats::get(self.weapon),
+            &self.rolls,
+            &self.modules,
+            &[],
+            self.weak_point_hit_chance,
+            &self.config().optimization_config(),
+        );
+        ExpandedBuild {
+            code,
+            weapon: self.weapon,
+            weak_point_hit_chance: self.weak_point_hit_chance,
+            buffs: self.buffs.to_string(),
+            objective: self.objective,
+            enemy: self.enemy,
+            rolls: self.rolls,
+            modules: self.modules,
+            dps,
+        }
+    }
+
+    pub fn config(&self) -> GridConfig {
+        GridConfig { buffs: self.buffs, objective: self.objective, enemy: self.enemy }
+    }
+
+    /// `<version byte><weapon><flags><hit chance in basis points, 2 bytes>
+    /// <roll count>(<roll type><value, 2 bytes>)*<module count>(<module id,
+    /// 2 bytes>)*`, base64url without padding. Roll values are rounded to
+    /// `roll_scale`.
+    pub fn encode(&self) -> Result<String, ShareCodeError> {
+        validate_weak_point_chance(self.weak_point_hit_chance)?;
+        let weapon_index = Weapon::all().iter().position(|weapon| *weapon == self.weapon).expect("every weapon is in Weapon::all");
+        let catalog = catalog(self.weapon);
+
+        let indices = self
+            .modules
+            .iter()
+            .map(|module| catalog.iter().position(|candidate| candidate.name == module.name).ok_or_else(|| ShareCodeError::UnknownModule(module.name.clone())))
+            .collect::<Result<Vec<usize>, ShareCodeError>>()?;
+        let quantized = self.rolls.iter().map(|roll| (roll.roll_type, (roll.value * roll_scale(roll.roll_type)).round())).collect::<Vec<_>>();
+        let rolls: Vec<Roll> = quantized.iter().map(|&(roll_type, units)| Roll { roll_type, value: units / roll_scale(roll_type) }).collect();
+        validate(self.weapon, &catalog, &indices, &rolls)?;
+
+        let mut bytes = vec![SHARE_CODE_VERSION, weapon_index as u8];
+        let mut flags = 0;
+        if self.buffs.valby {
+            flags |= VALBY;
+        }
+        if self.buffs.enzo {
+            flags |= ENZO;
+        }
+        if self.objective == Objective::InfiniteAmmo {
+            flags |= INFINITE_AMMO;
+        }
+        if self.enemy == EnemyProfile::Standard {
+            flags |= STANDARD_ENEMY;
+        }
+        bytes.push(flags);
+        let key = ResultKey::new(self.weapon, self.weak_point_hit_chance, self.buffs, self.objective, self.enemy);
+        bytes.extend_from_slice(&key.wp_basis_points.to_be_bytes());
+
+        bytes.push(count(quantized.len())?);
+        for (roll_type, units) in quantized {
+            let roll_type_index = ROLL_TYPES.iter().position(|candidate| *candidate == roll_type).expect("every roll type is in ROLL_TYPES");
+            bytes.push(roll_type_index as u8);
+            if units > u16::MAX as f64 {
+                return Err(ShareCodeError::InvalidRoll(format!("{:?} of {} doesn't fit in a share code", roll_type, units / roll_scale(roll_type))));
+            }
+            bytes.extend_from_slice(&(units as u16).to_be_bytes());
+        }
+
+        bytes.push(count(indices.len())?);
+        for index in indices {
+            bytes.extend_from_slice(&module_id(&catalog[index]).to_be_bytes());
+        }
+
+        Ok(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    /// Decodes and validates a code of any version against the current
+    /// module catalog.
+    pub fn decode(code: &str) -> Result<Self, ShareCodeError> {
+        let bytes = URL_SAFE_NO_PAD.decode(code.trim()).map_err(|e| ShareCodeError::Encoding(e.to_string()))?;
+        let mut reader = Reader { bytes: &bytes, position: 0 };
+
+        let version = reader.u8()?;
+        if !(1..=SHARE_CODE_VERSION).contains(&version) {
+            return Err(ShareCodeError::UnsupportedVersion(version));
+        }
+        let weapon_index = reader.u8()?;
+        let weapon = *Weapon::all().get(weapon_index as usize).ok_or(ShareCodeError::UnknownWeapon(weapon_index))?;
+        let catalog = catalog(weapon);
+        // Version 1 referenced modules by catalog position, so it only holds
+        // while the catalog is unchanged
+        if version == 1 && reader.take(2)? != fingerprint(weapon, &catalog) {
+            return Err(ShareCodeError::CatalogChanged(weapon));
+        }
+        let flags = reader.u8()?;
+        let basis_points = u16::from_be_bytes(reader.take(2)?.try_into().expect("took 2 bytes"));
+        let weak_point_hit_chance = validate_weak_point_chance(basis_points as f64 / 10_000.0)?;
+
+        let mut rolls = Vec::new();
+        for _ in 0..reader.u8()? {
+            let roll_type = reader.u8()?;
+            let roll_type = *ROLL_TYPES.get(roll_type as usize).ok_or_else(|| ShareCodeError::InvalidRoll(format!("unknown roll type {}", roll_type)))?;
+            let value = match version {
+                1 => f64::from_be_bytes(reader.take(8)?.try_into().expect("took 8 bytes")),
+                _ => reader.u16()? as f64 / roll_scale(roll_type),
+            };
+            rolls.push(Roll { roll_type, value });
+        }
+        let mut indices = Vec::new();
+        for _ in 0..reader.u8()? {
+            let index = match version {
+                1 => reader.u8()? as usize,
+                _ => {
+                    let id = reader.u16()?;
+                    catalog
+                        .iter()
+                        .position(|module| module_id(module) == id)
+                        .ok_or_else(|| ShareCodeError::UnknownModule(format!("with id {:04x}", id)))?
+                },
+            };
+            indices.push(index);
+        }
+        if reader.position != bytes.len() {
+            return Err(ShareCodeError::Encoding("trailing bytes".to_string()));
+        }
+        validate(weapon, &catalog, &indices, &rolls)?;
+
+        Ok(SharedBuild {
+            weapon,
+            weak_point_hit_chance,
+            buffs: BuffSet { valby: flags & VALBY != 0, enzo: flags & ENZO != 0 },
+            objective: if flags & INFINITE_AMMO != 0 { Objective::InfiniteAmmo } else { Objective::Sustained },
+            enemy: if flags & STANDARD_ENEMY != 0 { EnemyProfile::Standard } else { EnemyProfile::Colossus },
+            rolls,
+            modules: indices.into_iter().map(|index| catalog[index].clone()).collect(),
+        })
+    }
+}
+
+fn count(len: usize) -> Result<u8, ShareCodeError> {
+    u8::try_from(len).map_err(|_| ShareCodeError::TooMany(len))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ShareCodeError> {
+        let taken = self.bytes.get(self.position..self.position + n).ok_or(ShareCodeError::Truncated)?;
+        self.position += n;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ShareCodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ShareCodeError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().expect("took 2 bytes")))
+    }
+}
+
+fn catalog(weapon: Weapon) -> Vec<Module> {
+    let stats = WeaponBaseStats::get(weapon);
+    get_available_modules(stats.bullet_type, stats.weapon_type)
+}
+
+/// Identifies a module by its name, so codes survive modules being added to
+/// or reordered in the catalog. Ids are unique within every catalog.
+fn module_id(module: &Module) -> u16 {
+    let digest = Sha256::digest(module.name.as_bytes());
+    u16::from_be_bytes([digest[0], digest[1]])
+}
+
+/// Changes when the weapon or its catalog does, so module indices of
+/// version 1 codes can't silently point at other modules.
+fn fingerprint(weapon: Weapon, catalog: &[Module]) -> [u8; 2] {
+    let mut hasher = Sha256::new();
+    hasher.update(weapon.to_string());
+    for module in catalog {
+        hasher.update([0]);
+        hasher.update(&module.name);
+    }
+    let digest = hasher.finalize();
+    [digest[0], digest[1]]
+}
+
+fn validate_weak_point_chance(chance: f64) -> Result<f64, ShareCodeError> {
+    if chance.is_finite() && (0.0..=1.0).contains(&chance) {
+        Ok(chance)
+    } else {
+        Err(ShareCodeError::InvalidWeakPointChance(chance))
+    }
+}
+
+/// Checks the build against the weapon's slots, rolls and slotting rules.
+fn validate(weapon: Weapon, catalog: &[Module], indices: &[usize], rolls: &[Roll]) -> Result<(), ShareCodeError> {
+    let slots = weapon.slot_counts();
+    if indices.len() > slots.modules {
+        return Err(ShareCodeError::InvalidModules(format!("{} modules for {} slots", indices.len(), slots.modules)));
+    }
+    if let Some(index) = indices.iter().find(|&&index| index >= catalog.len()) {
+        return Err(ShareCodeError::InvalidModules(format!("no module {} in the catalog", index)));
+    }
+    let mut unique = indices.to_vec();
+    unique.sort();
+    unique.dedup();
+    if unique.len() != indices.len() {
+        return Err(ShareCodeError::InvalidModules("a module is equipped twice".to_string()));
+    }
+    let module_types: Vec<_> = catalog.iter().map(|module| module.module_type).collect();
+    if !is_valid_combination(indices, &module_types, &BuildConstraints::default()) {
+        return Err(ShareCodeError::InvalidModules("two modules share a type".to_string()));
+    }
+
+    if rolls.len() > slots.rolls {
+        return Err(ShareCodeError::InvalidRoll(format!("{} rolls for {} slots", rolls.len(), slots.rolls)));
+    }
+    let available = get_available_rolls(WeaponBaseStats::get(weapon).weapon_type);
+    for roll in rolls {
+        let Some(max) = available.iter().find(|candidate| candidate.roll_type == roll.roll_type) else {
+            return Err(ShareCodeError::InvalidRoll(format!("{:?} can't roll on {}", roll.roll_type, weapon)));
+        };
+        if !(roll.value.is_finite() && roll.value > 0.0 && roll.value <= max.value) {
+            return Err(ShareCodeError::InvalidRoll(format!("{:?} of {} is outside 0..={}", roll.roll_type, roll.value, max.value)));
+        }
+    }
+    if rolls.iter().enumerate().any(|(i, roll)| rolls[..i].iter().any(|other| other.roll_type == roll.roll_type)) {
+        return Err(ShareCodeError::InvalidRoll("a roll type is used twice".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build() -> SharedBuild {
+        let catalog = catalog(Weapon::Thundercage);
+        let mut modules: Vec<Module> = Vec::new();
+        for module in catalog {
+            if modules.len() < 6 && !modules.iter().any(|other| other.module_type == module.module_type) {
+                modules.push(module);
+            }
+        }
+        SharedBuild {
+            weapon: Weapon::Thundercage,
+            weak_point_hit_chance: 0.35,
+            buffs: BuffSet { valby: true, enzo: false },
+            objective: Objective::InfiniteAmmo,
+            enemy: EnemyProfile::Standard,
+            rolls: get_available_rolls(WeaponBaseStats::get(Weapon::Thundercage).weapon_type).into_iter().take(4).collect(),
+            modules,
+        }
+    }
+
+    /// `build` in the version 1 layout: a catalog fingerprint, raw `f64`
+    /// roll values and catalog positions.
+    fn version_1_code(build: &SharedBuild) -> String {
+        let catalog = catalog(build.weapon);
+        let mut bytes = vec![1, Weapon::all().iter().position(|weapon| *weapon == build.weapon).unwrap() as u8];
+        bytes.extend_from_slice(&fingerprint(build.weapon, &catalog));
+        bytes.push(VALBY | INFINITE_AMMO | STANDARD_ENEMY);
+        bytes.extend_from_slice(&3500u16.to_be_bytes());
+        bytes.push(build.rolls.len() as u8);
+        for roll in &build.rolls {
+            bytes.push(ROLL_TYPES.iter().position(|roll_type| *roll_type == roll.roll_type).unwrap() as u8);
+            bytes.extend_from_slice(&roll.value.to_be_bytes());
+        }
+        bytes.push(build.modules.len() as u8);
+        bytes.extend(build.modules.iter().map(|module| catalog.iter().position(|candidate| candidate.name == module.name).unwrap() as u8));
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    fn roll_values(rolls: &[Roll]) -> Vec<(RollType, f64)> {
+        rolls.iter().map(|roll| (roll.roll_type, roll.value)).collect()
+    }
+
+    #[test]
+    fn test_share_code_round_trip() {
+        let build = build();
+        let code = build.encode().unwrap();
+        assert!(code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        // 31 bytes for 4 rolls and 6 modules
+        assert_eq!(code.len(), 42);
+
+        let decoded = SharedBuild::decode(&code).unwrap();
+        assert_eq!(decoded.weapon, build.weapon);
+        assert_eq!(decoded.weak_point_hit_chance, build.weak_point_hit_chance);
+        assert_eq!(decoded.config(), build.config());
+        assert_eq!(roll_values(&decoded.rolls), roll_values(&build.rolls));
+        assert_eq!(decoded.modules.iter().map(|module| &module.name).collect::<Vec<_>>(), build.modules.iter().map(|module| &module.name).collect::<Vec<_>>());
+        assert_eq!(decoded.encode().unwrap(), code);
+
+        // Values are rounded to hundredths of a percent
+        let mut rounded = build.clone();
+        rounded.rolls[0].value = 0.10004;
+        let decoded = SharedBuild::decode(&rounded.encode().unwrap()).unwrap();
+        assert_eq!(decoded.rolls[0].value, 0.1);
+
+        let decoded = SharedBuild::decode(&version_1_code(&build)).unwrap();
+        assert_eq!(decoded.config(), build.config());
+        assert_eq!(roll_values(&decoded.rolls), roll_values(&build.rolls));
+        assert_eq!(decoded.encode().unwrap(), code);
+    }
+
+    #[test]
+    fn test_module_ids_are_unique() {
+        for weapon in Weapon::all() {
+            let mut ids: Vec<u16> = catalog(*weapon).iter().map(module_id).collect();
+            let modules = ids.len();
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), modules, "two modules of {} share an id", weapon);
+        }
+    }
+
+    #[test]
+    fn test_share_code_validation() {
+        let code = build().encode().unwrap();
+        let bytes = URL_SAFE_NO_PAD.decode(&code).unwrap();
+        let tampered = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            change(&mut bytes);
+            SharedBuild::decode(&URL_SAFE_NO_PAD.encode(bytes)).unwrap_err()
+        };
+
+        assert_eq!(tampered(&|bytes| bytes[0] = 9), ShareCodeError::UnsupportedVersion(9));
+        assert_eq!(tampered(&|bytes| bytes[1] = 250), ShareCodeError::UnknownWeapon(250));
+        assert_eq!(tampered(&|bytes| { bytes.pop(); }), ShareCodeError::Truncated);
+        assert!(matches!(tampered(&|bytes| { let len = bytes.len(); bytes[len - 2..].copy_from_slice(&[0xff, 0xff]); }), ShareCodeError::UnknownModule(_)));
+        assert!(matches!(tampered(&|bytes| { let len = bytes.len(); let previous = [bytes[len - 4], bytes[len - 3]]; bytes[len - 2..].copy_from_slice(&previous); }), ShareCodeError::InvalidModules(_)));
+        assert!(matches!(SharedBuild::decode("not a code!"), Err(ShareCodeError::Encoding(_))));
+
+        let mut version_1 = URL_SAFE_NO_PAD.decode(version_1_code(&build())).unwrap();
+        version_1[2] ^= 0xff;
+        assert_eq!(SharedBuild::decode(&URL_SAFE_NO_PAD.encode(version_1)).unwrap_err(), ShareCodeError::CatalogChanged(Weapon::Thundercage));
+        assert_eq!(count(256), Err(ShareCodeError::TooMany(256)));
+
+        let mut oversized = build();
+        oversized.rolls[0].value *= 2.0;
+        assert!(matches!(oversized.encode(), Err(ShareCodeError::InvalidRoll(_))));
+        let mut foreign = build();
+        foreign.modules[0].name = "Not A Module".to_string();
+        assert_eq!(foreign.encode(), Err(ShareCodeError::UnknownModule("Not A Module".to_string())));
+    }
+}
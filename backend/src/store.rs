@@ -0,0 +1,553 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use log::warn;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::UpdateOptions;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::calculate::{EnemyProfile, OptimizationResult};
+use crate::game_data::game_data_version;
+use crate::grid::{BuffSet, Objective};
+use crate::result_key::ResultKey;
+
+pub const RESULTS_COLLECTION: &str = "weapon_results";
+
+/// A result as stored in the `weapon_results` collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultRecord {
+    pub weapon: String,
+    pub weak_point_hit_chance: f64,
+    pub valby: bool,
+    pub enzo: bool,
+    #[serde(default)]
+    pub objective: Objective,
+    #[serde(default)]
+    pub enemy: EnemyProfile,
+    /// Game data version the result was computed with, empty on results
+    /// stored before versioning.
+    #[serde(default)]
+    pub data_version: String,
+    /// Empty on results stored before input hashing.
+    #[serde(default)]
+    pub input_hash: String,
+    #[serde(flatten)]
+    pub result: OptimizationResult,
+}
+
+impl ResultRecord {
+    /// `None` for results of weapons that no longer exist.
+    pub fn key(&self) -> Option<ResultKey> {
+        let weapon = self.weapon.parse().ok()?;
+        let buffs = BuffSet { valby: self.valby, enzo: self.enzo };
+        Some(ResultKey::new(weapon, self.weak_point_hit_chance, buffs, self.objective, self.enemy))
+    }
+
+    /// Whether `other` is the same result, possibly with different values.
+    pub fn same_key(&self, other: &ResultRecord) -> bool {
+        self.weapon == other.weapon
+            && self.weak_point_hit_chance == other.weak_point_hit_chance
+            && self.valby == other.valby
+            && self.enzo == other.enzo
+            && self.objective == other.objective
+            && self.enemy == other.enemy
+            && self.data_version == other.data_version
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Mongo(mongodb::error::Error),
+    Sqlite(rusqlite::Error),
+    Encoding(String),
+    Unavailable(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Mongo(e) => write!(f, "Result store MongoDB error: {}", e),
+            StoreError::Sqlite(e) => write!(f, "Result store SQLite error: {}", e),
+            StoreError::Encoding(e) => write!(f, "Result store encoding error: {}", e),
+            StoreError::Unavailable(e) => write!(f, "Result store unavailable: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<mongodb::error::Error> for StoreError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StoreError::Mongo(e)
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Encoding(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for StoreError {
+    fn from(e: mongodb::bson::ser::Error) -> Self {
+        StoreError::Encoding(e.to_string())
+    }
+}
+
+impl From<strum::ParseError> for StoreError {
+    fn from(e: strum::ParseError) -> Self {
+        StoreError::Encoding(e.to_string())
+    }
+}
+
+/// Where optimization results are kept.
+#[async_trait]
+pub trait ResultStore: Send + Sync {
+    async fn load_all(&self) -> Result<Vec<ResultRecord>, StoreError>;
+
+    async fn load_weapon(&self, weapon: &str) -> Result<Vec<ResultRecord>, StoreError>;
+
+    /// Inserts `record`, replacing the stored result with the same key.
+    async fn upsert(&self, record: &ResultRecord) -> Result<(), StoreError>;
+
+    /// Game data versions that have stored results, oldest first.
+    async fn list_versions(&self) -> Result<Vec<String>, StoreError>;
+
+    /// Deletes the results of `weapon`, only those of `data_version` if
+    /// given. Returns how many were deleted.
+    async fn delete(&self, weapon: &str, data_version: Option<&str>) -> Result<u64, StoreError>;
+}
+
+/// Which result store to use, parsed from `mongo`, `sqlite:<path>` or
+/// `memory`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreTarget {
+    Mongo,
+    Sqlite(PathBuf),
+    Memory,
+}
+
+impl FromStr for StoreTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "mongo" => Ok(StoreTarget::Mongo),
+            None if s == "memory" => Ok(StoreTarget::Memory),
+            Some(("sqlite", path)) if !path.is_empty() => Ok(StoreTarget::Sqlite(PathBuf::from(path))),
+            _ => Err(format!("Invalid result store {}, expected mongo, sqlite:<path> or memory", s)),
+        }
+    }
+}
+
+impl StoreTarget {
+    /// `db` is only needed, and only has to be connected, for `Mongo`.
+    pub fn open(&self, db: Option<&mongodb::Database>) -> Result<Arc<dyn ResultStore>, StoreError> {
+        Ok(match self {
+            StoreTarget::Mongo => {
+                let db = db.ok_or_else(|| StoreError::Unavailable("MongoDB isn't configured".to_string()))?;
+                Arc::new(MongoStore::new(db.clone()))
+            }
+            StoreTarget::Sqlite(path) => Arc::new(SqliteStore::open(path)?),
+            StoreTarget::Memory => {
+                warn!("Using the memory result store, results are lost when the process exits");
+                Arc::new(MemoryStore::default())
+            }
+        })
+    }
+}
+
+/// Compares dotted versions part by part, numerically where both parts are
+/// numbers, so `1.10` sorts after `1.9`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        let ordering = match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn sorted_versions(mut versions: Vec<String>) -> Vec<String> {
+    versions.sort_by(|a, b| compare_versions(a, b).then_with(|| a.cmp(b)));
+    versions.dedup();
+    versions
+}
+
+pub struct MongoStore {
+    db: mongodb::Database,
+}
+
+impl MongoStore {
+    pub fn new(db: mongodb::Database) -> Self {
+        MongoStore { db }
+    }
+
+    fn results(&self) -> mongodb::Collection<ResultRecord> {
+        self.db.collection::<ResultRecord>(RESULTS_COLLECTION)
+    }
+
+    /// Matches `value`, or a missing field when `value` is the default, since
+    /// results stored before the field existed were computed with the default.
+    fn key_filter(value: String, is_default: bool) -> Bson {
+        if is_default {
+            Bson::Document(doc! { "$in": [Bson::Null, value] })
+        } else {
+            Bson::String(value)
+        }
+    }
+
+    /// Matches the stored result with the key of `record`. Results stored
+    /// before versioning have no version, the current version replaces them.
+    fn upsert_filter(record: &ResultRecord) -> Document {
+        doc! {
+            "weapon": &record.weapon,
+            "weak_point_hit_chance": record.weak_point_hit_chance,
+            "valby": record.valby,
+            "enzo": record.enzo,
+            "objective": Self::key_filter(record.objective.to_string(), record.objective.is_default()),
+            "enemy": Self::key_filter(record.enemy.to_string(), record.enemy.is_default()),
+            "data_version": Self::key_filter(record.data_version.clone(), record.data_version == game_data_version()),
+        }
+    }
+
+    async fn find(&self, filter: Option<Document>) -> Result<Vec<ResultRecord>, StoreError> {
+        Ok(self.results().find(filter, None).await?.try_collect().await?)
+    }
+}
+
+#[async_trait]
+impl ResultStore for MongoStore {
+    async fn load_all(&self) -> Result<Vec<ResultRecord>, StoreError> {
+        self.find(None).await
+    }
+
+    async fn load_weapon(&self, weapon: &str) -> Result<Vec<ResultRecord>, StoreError> {
+        self.find(Some(doc! { "weapon": weapon })).await
+    }
+
+    async fn upsert(&self, record: &ResultRecord) -> Result<(), StoreError> {
+        let mut update = mongodb::bson::to_document(&record.result)?;
+        update.insert("input_hash", &record.input_hash);
+        update.insert("objective", record.objective.to_string());
+        update.insert("enemy", record.enemy.to_string());
+        update.insert("data_version", &record.data_version);
+        self.results().update_one(
+            Self::upsert_filter(record),
+            doc! { "$set": update },
+            UpdateOptions::builder().upsert(true).build(),
+        ).await?;
+        Ok(())
+    }
+
+    async fn list_versions(&self) -> Result<Vec<String>, StoreError> {
+        let versions = self.results().distinct("data_version", None, None).await?;
+        Ok(sorted_versions(versions.iter().filter_map(|version| version.as_str().map(str::to_string)).collect()))
+    }
+
+    async fn delete(&self, weapon: &str, data_version: Option<&str>) -> Result<u64, StoreError> {
+        let mut filter = doc! { "weapon": weapon };
+        if let Some(data_version) = data_version {
+            filter.insert("data_version", data_version);
+        }
+        Ok(self.results().delete_many(filter, None).await?.deleted_count)
+    }
+}
+
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS weapon_results (
+        weapon TEXT NOT NULL,
+        weak_point_hit_chance REAL NOT NULL,
+        valby INTEGER NOT NULL,
+        enzo INTEGER NOT NULL,
+        objective TEXT NOT NULL,
+        enemy TEXT NOT NULL,
+        data_version TEXT NOT NULL,
+        input_hash TEXT NOT NULL,
+        result TEXT NOT NULL,
+        PRIMARY KEY (weapon, weak_point_hit_chance, valby, enzo, objective, enemy, data_version)
+    );
+";
+
+/// Results in an embedded SQLite database, for running without MongoDB.
+/// Clones share the connection.
+#[derive(Clone)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Opens a database file that several processes may share.
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(std::time::Duration::from_secs(30))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        Self::with_connection(connection)
+    }
+
+    pub fn with_connection(connection: Connection) -> Result<Self, StoreError> {
+        connection.execute_batch(SQLITE_SCHEMA)?;
+        Ok(SqliteStore { connection: Arc::new(Mutex::new(connection)) })
+    }
+
+    /// The underlying connection, for tables other than the results. Async
+    /// code goes through `blocking` instead.
+    pub fn connection(&self) -> MutexGuard<'_, Connection> {
+        lock(&self.connection)
+    }
+
+    /// Runs `f` on a blocking thread, so queries that wait on the lock or on
+    /// other processes don't stall the async runtime.
+    pub async fn blocking<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, StoreError> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || f(&lock(&connection)))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+}
+
+fn lock(connection: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn select(connection: &Connection, weapon: Option<&str>) -> Result<Vec<ResultRecord>, StoreError> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT weapon, weak_point_hit_chance, valby, enzo, objective, enemy, data_version, input_hash, result FROM weapon_results {}",
+        if weapon.is_some() { "WHERE weapon = ?1" } else { "" },
+    ))?;
+    let values: Vec<&dyn rusqlite::ToSql> = weapon.iter().map(|weapon| weapon as &dyn rusqlite::ToSql).collect();
+    let rows = statement.query_map(values.as_slice(), |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, f64>(1)?,
+            row.get::<_, bool>(2)?,
+            row.get::<_, bool>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, String>(6)?,
+            row.get::<_, String>(7)?,
+            row.get::<_, String>(8)?,
+        ))
+    })?;
+
+    let mut records = Vec::new();
+    for row in rows {
+        let (weapon, weak_point_hit_chance, valby, enzo, objective, enemy, data_version, input_hash, result) = row?;
+        records.push(ResultRecord {
+            weapon,
+            weak_point_hit_chance,
+            valby,
+            enzo,
+            objective: objective.parse()?,
+            enemy: enemy.parse()?,
+            data_version,
+            input_hash,
+            result: serde_json::from_str(&result)?,
+        });
+    }
+    Ok(records)
+}
+
+#[async_trait]
+impl ResultStore for SqliteStore {
+    async fn load_all(&self) -> Result<Vec<ResultRecord>, StoreError> {
+        self.blocking(|connection| select(connection, None)).await
+    }
+
+    async fn load_weapon(&self, weapon: &str) -> Result<Vec<ResultRecord>, StoreError> {
+        let weapon = weapon.to_string();
+        self.blocking(move |connection| select(connection, Some(&weapon))).await
+    }
+
+    async fn upsert(&self, record: &ResultRecord) -> Result<(), StoreError> {
+        let record = record.clone();
+        let result = serde_json::to_string(&record.result)?;
+        self.blocking(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO weapon_results VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    record.weapon,
+                    record.weak_point_hit_chance,
+                    record.valby,
+                    record.enzo,
+                    record.objective.to_string(),
+                    record.enemy.to_string(),
+                    record.data_version,
+                    record.input_hash,
+                    result,
+                ],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn list_versions(&self) -> Result<Vec<String>, StoreError> {
+        self.blocking(|connection| {
+            let mut statement = connection.prepare("SELECT DISTINCT data_version FROM weapon_results")?;
+            let versions = statement.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+            Ok(sorted_versions(versions))
+        }).await
+    }
+
+    async fn delete(&self, weapon: &str, data_version: Option<&str>) -> Result<u64, StoreError> {
+        let weapon = weapon.to_string();
+        let data_version = data_version.map(str::to_string);
+        self.blocking(move |connection| {
+            let deleted = match data_version {
+                Some(data_version) => connection.execute(
+                    "DELETE FROM weapon_results WHERE weapon = ?1 AND data_version = ?2",
+                    params![weapon, data_version],
+                )?,
+                None => connection.execute("DELETE FROM weapon_results WHERE weapon = ?1", params![weapon])?,
+            };
+            Ok(deleted as u64)
+        }).await
+    }
+}
+
+/// Results kept in memory only, for tests and trying out the api without a
+/// database.
+#[derive(Default)]
+pub struct MemoryStore {
+    records: std::sync::RwLock<Vec<ResultRecord>>,
+}
+
+impl MemoryStore {
+    fn records(&self) -> std::sync::RwLockReadGuard<'_, Vec<ResultRecord>> {
+        self.records.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn records_mut(&self) -> std::sync::RwLockWriteGuard<'_, Vec<ResultRecord>> {
+        self.records.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl ResultStore for MemoryStore {
+    async fn load_all(&self) -> Result<Vec<ResultRecord>, StoreError> {
+        Ok(self.records().clone())
+    }
+
+    async fn load_weapon(&self, weapon: &str) -> Result<Vec<ResultRecord>, StoreError> {
+        Ok(self.records().iter().filter(|record| record.weapon == weapon).cloned().collect())
+    }
+
+    async fn upsert(&self, record: &ResultRecord) -> Result<(), StoreError> {
+        let mut records = self.records_mut();
+        match records.iter_mut().find(|stored| stored.same_key(record)) {
+            Some(stored) => *stored = record.clone(),
+            None => records.push(record.clone()),
+        }
+        Ok(())
+    }
+
+    async fn list_versions(&self) -> Result<Vec<String>, StoreError> {
+        Ok(sorted_versions(self.records().iter().map(|record| record.data_version.clone()).collect()))
+    }
+
+    async fn delete(&self, weapon: &str, data_version: Option<&str>) -> Result<u64, StoreError> {
+        let mut records = self.records_mut();
+        let before = records.len();
+        records.retain(|record| record.weapon != weapon || data_version.is_some_and(|version| record.data_version != version));
+        Ok((before - records.len()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(weapon: &str, data_version: &str, max_dps: f64) -> ResultRecord {
+        ResultRecord {
+            weapon: weapon.to_string(),
+            weak_point_hit_chance: 0.5,
+            valby: false,
+            enzo: false,
+            objective: Objective::Sustained,
+            enemy: EnemyProfile::Colossus,
+            data_version: data_version.to_string(),
+            input_hash: String::new(),
+            result: OptimizationResult { max_dps, best_rolls: Vec::new(), best_modules: Vec::new(), best_reactor: Vec::new() },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_result_stores() {
+        let stores: Vec<Box<dyn ResultStore>> = vec![
+            Box::new(MemoryStore::default()),
+            Box::new(SqliteStore::with_connection(Connection::open_in_memory().unwrap()).unwrap()),
+        ];
+
+        for store in stores {
+            store.upsert(&record("Thundercage", "1.1", 100.0)).await.unwrap();
+            store.upsert(&record("Thundercage", "1.1", 200.0)).await.unwrap();
+            store.upsert(&record("Thundercage", "1.0", 50.0)).await.unwrap();
+            store.upsert(&record("Enduring Legacy", "1.1", 300.0)).await.unwrap();
+
+            assert_eq!(store.load_all().await.unwrap().len(), 3);
+            let thundercage = store.load_weapon("Thundercage").await.unwrap();
+            assert_eq!(thundercage.len(), 2);
+            assert!(thundercage.iter().any(|record| record.result.max_dps == 200.0));
+            assert_eq!(store.list_versions().await.unwrap(), vec!["1.0", "1.1"]);
+            store.upsert(&record("Thundercage", "1.10", 100.0)).await.unwrap();
+            store.upsert(&record("Thundercage", "1.9", 100.0)).await.unwrap();
+            assert_eq!(store.list_versions().await.unwrap(), vec!["1.0", "1.1", "1.9", "1.10"]);
+
+            assert_eq!(store.delete("Thundercage", Some("1.0")).await.unwrap(), 1);
+            assert_eq!(store.delete("Thundercage", None).await.unwrap(), 3);
+            assert!(store.load_weapon("Thundercage").await.unwrap().is_empty());
+            assert_eq!(store.load_all().await.unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_mongo_upsert_replaces_unversioned_results() {
+        let current = MongoStore::upsert_filter(&record("Thundercage", game_data_version(), 100.0));
+        assert_eq!(current.get_document("data_version").unwrap(), &doc! { "$in": [Bson::Null, game_data_version()] });
+
+        let old = MongoStore::upsert_filter(&record("Thundercage", "1.0", 100.0));
+        assert_eq!(old.get_str("data_version").unwrap(), "1.0");
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.1"), Ordering::Less);
+        assert_eq!(compare_versions("2.0", "10.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.03", "1.3"), Ordering::Equal);
+        assert_eq!(sorted_versions(vec!["1.3".into(), "1.03".into(), "1.3".into()]), vec!["1.03", "1.3"]);
+    }
+
+    #[test]
+    fn test_store_target() {
+        assert_eq!("mongo".parse::<StoreTarget>(), Ok(StoreTarget::Mongo));
+        assert_eq!("memory".parse::<StoreTarget>(), Ok(StoreTarget::Memory));
+        assert_eq!("sqlite:results.db".parse::<StoreTarget>(), Ok(StoreTarget::Sqlite(PathBuf::from("results.db"))));
+        assert!("jsonl:results.jsonl".parse::<StoreTarget>().is_err());
+    }
+}
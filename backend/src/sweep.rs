@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::calculate::OptimizationResult;
+
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
+pub struct SweepPoint {
+    pub weak_point_hit_chance: f64,
+    #[serde(flatten)]
+    pub result: OptimizationResult,
+}
+
+/// A point where the optimal build of one weapon changes between two
+/// neighbouring grid chances.
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
+pub struct BuildCrossover {
+    pub from_chance: f64,
+    pub to_chance: f64,
+    pub previous_modules: Vec<String>,
+    pub next_modules: Vec<String>,
+    pub previous_rolls: Vec<String>,
+    pub next_rolls: Vec<String>,
+}
+
+/// A point where `leader` overtakes `overtaken`. The chance is interpolated
+/// between the two grid points that bracket the sign change.
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
+pub struct WeaponCrossover {
+    pub weak_point_hit_chance: f64,
+    pub leader: String,
+    pub overtaken: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, ToSchema)]
+pub struct WeaponSweep {
+    pub weapon: String,
+    pub valby: bool,
+    pub enzo: bool,
+    pub points: Vec<SweepPoint>,
+    pub build_crossovers: Vec<BuildCrossover>,
+}
+
+impl WeaponSweep {
+    pub fn new(weapon: String, valby: bool, enzo: bool, points: Vec<SweepPoint>) -> Self {
+        let build_crossovers = build_crossovers(&points);
+        WeaponSweep { weapon, valby, enzo, points, build_crossovers }
+    }
+}
+
+/// Evenly spaced chances from 0 to 1 inclusive.
+pub fn sweep_grid(step: f64) -> Vec<f64> {
+    let steps = (1.0 / step).round().max(1.0) as usize;
+    (0..=steps).map(|i| (i as f64 / steps as f64 * 1e4).round() / 1e4).collect()
+}
+
+fn module_names(result: &OptimizationResult) -> Vec<String> {
+    let mut names: Vec<String> = result.best_modules.iter().map(|(m, _)| m.name.clone()).collect();
+    names.sort();
+    names
+}
+
+fn roll_names(result: &OptimizationResult) -> Vec<String> {
+    let mut names: Vec<String> = result.best_rolls.iter().map(|r| format!("{:?}", r.roll_type)).collect();
+    names.sort();
+    names
+}
+
+pub fn build_crossovers(points: &[SweepPoint]) -> Vec<BuildCrossover> {
+    points
+        .windows(2)
+        .filter_map(|pair| {
+            let (previous, next) = (&pair[0], &pair[1]);
+            let previous_modules = module_names(&previous.result);
+            let next_modules = module_names(&next.result);
+            let previous_rolls = roll_names(&previous.result);
+            let next_rolls = roll_names(&next.result);
+            if previous_modules == next_modules && previous_rolls == next_rolls {
+                return None;
+            }
+            Some(BuildCrossover {
+                from_chance: previous.weak_point_hit_chance,
+                to_chance: next.weak_point_hit_chance,
+                previous_modules,
+                next_modules,
+                previous_rolls,
+                next_rolls,
+            })
+        })
+        .collect()
+}
+
+/// Finds every pair of weapons whose DPS curves cross. Sweeps are expected
+/// to share the same grid; pairs with mismatched grids are skipped. Curves
+/// that touch and separate again on the same side don't cross. When they tie
+/// over a stretch before separating, the crossover is where the tie starts.
+pub fn weapon_crossovers(sweeps: &[WeaponSweep]) -> Vec<WeaponCrossover> {
+    let mut crossovers = Vec::new();
+    for (i, a) in sweeps.iter().enumerate() {
+        for b in &sweeps[i + 1..] {
+            let same_grid = a.points.len() == b.points.len()
+                && a.points.iter().zip(&b.points).all(|(p, q)| p.weak_point_hit_chance == q.weak_point_hit_chance);
+            if !same_grid {
+                continue;
+            }
+            // Last point where one weapon was ahead, and where a tie after it began
+            let mut last_lead: Option<(f64, f64)> = None;
+            let mut tie_start: Option<f64> = None;
+            for (p, q) in a.points.iter().zip(&b.points) {
+                let chance = p.weak_point_hit_chance;
+                let diff = p.result.max_dps - q.result.max_dps;
+                if diff == 0.0 {
+                    tie_start.get_or_insert(chance);
+                    continue;
+                }
+                if let Some((lead_chance, lead_diff)) = last_lead {
+                    if (lead_diff > 0.0) != (diff > 0.0) {
+                        let crossed_at = tie_start.unwrap_or_else(|| {
+                            let t = lead_diff / (lead_diff - diff);
+                            lead_chance + t * (chance - lead_chance)
+                        });
+                        let (leader, overtaken) = if diff > 0.0 { (a, b) } else { (b, a) };
+                        crossovers.push(WeaponCrossover {
+                            weak_point_hit_chance: crossed_at,
+                            leader: leader.weapon.clone(),
+                            overtaken: overtaken.weapon.clone(),
+                        });
+                    }
+                }
+                last_lead = Some((chance, diff));
+                tie_start = None;
+            }
+        }
+    }
+    crossovers.sort_by(|x, y| x.weak_point_hit_chance.total_cmp(&y.weak_point_hit_chance));
+    crossovers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapons::{Module, ModuleType, Roll, RollType};
+
+    fn point(chance: f64, max_dps: f64, module: &str) -> SweepPoint {
+        SweepPoint {
+            weak_point_hit_chance: chance,
+            result: OptimizationResult {
+                max_dps,
+                best_rolls: vec![Roll { roll_type: RollType::Atk, value: 0.1 }],
+                best_modules: vec![(Module { name: module.to_string(), module_type: ModuleType::None, effects: Vec::new() }, 1.0)],
+                best_reactor: Vec::new(),
+            },
+        }
+    }
+
+    fn sweep(weapon: &str, dps: &[f64]) -> WeaponSweep {
+        let points = dps.iter().enumerate().map(|(i, &dps)| point(i as f64 * 0.25, dps, "Same")).collect();
+        WeaponSweep::new(weapon.to_string(), false, false, points)
+    }
+
+    #[test]
+    fn test_build_crossovers() {
+        let points = vec![point(0.0, 1.0, "Rifling"), point(0.5, 2.0, "Rifling"), point(1.0, 3.0, "Weak Point Sight")];
+        let crossovers = build_crossovers(&points);
+        assert_eq!(crossovers.len(), 1);
+        assert_eq!((crossovers[0].from_chance, crossovers[0].to_chance), (0.5, 1.0));
+        assert_eq!(crossovers[0].previous_modules, vec!["Rifling"]);
+        assert_eq!(crossovers[0].next_modules, vec!["Weak Point Sight"]);
+        assert!(build_crossovers(&points[..2]).is_empty());
+    }
+
+    #[test]
+    fn test_weapon_crossovers() {
+        // a starts ahead and falls behind between 0.25 and 0.5
+        let crossovers = weapon_crossovers(&[sweep("a", &[10.0, 8.0, 4.0]), sweep("b", &[5.0, 6.0, 8.0])]);
+        assert_eq!(crossovers.len(), 1);
+        assert_eq!((crossovers[0].leader.as_str(), crossovers[0].overtaken.as_str()), ("b", "a"));
+        assert!((crossovers[0].weak_point_hit_chance - (0.25 + 0.25 * 2.0 / 6.0)).abs() < 1e-12);
+
+        // Tied at the last point only, nothing crossed
+        assert!(weapon_crossovers(&[sweep("a", &[4.0, 6.0]), sweep("b", &[5.0, 6.0])]).is_empty());
+        assert!(weapon_crossovers(&[sweep("a", &[6.0, 6.0]), sweep("b", &[5.0, 6.0])]).is_empty());
+        // Touching and separating on the same side isn't a crossover
+        assert!(weapon_crossovers(&[sweep("a", &[4.0, 6.0, 4.0]), sweep("b", &[5.0, 6.0, 5.0])]).is_empty());
+        // Tied over a stretch, then crossed: the crossover is where the tie started
+        let crossovers = weapon_crossovers(&[sweep("a", &[4.0, 6.0, 7.0, 9.0]), sweep("b", &[5.0, 6.0, 7.0, 8.0])]);
+        assert_eq!(crossovers.len(), 1);
+        assert_eq!(crossovers[0].weak_point_hit_chance, 0.25);
+        assert_eq!(crossovers[0].leader, "a");
+
+        // Mismatched grids are skipped
+        let mut shifted = sweep("b", &[5.0, 6.0, 8.0]);
+        shifted.points[1].weak_point_hit_chance = 0.3;
+        assert!(weapon_crossovers(&[sweep("a", &[10.0, 8.0, 4.0]), shifted]).is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum_macros::{Display, EnumString};
+use utoipa::ToSchema;
+
+use crate::calculate::EnemyProfile;
+use crate::grid::Objective;
+use crate::weapons::{Weapon, WeaponBaseStats};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, EnumString, Display, ToSchema)]
+pub enum Tier {
+    S, A, B, C, D, F,
+}
+
+const TIERS: [Tier; 6] = [Tier::S, Tier::A, Tier::B, Tier::C, Tier::D, Tier::F];
+
+/// How a pool of weapons is cut into tiers. The cutoffs are the lower
+/// bounds of S through D, anything below the last one is F.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(tag = "method", content = "cutoffs", rename_all = "snake_case")]
+pub enum TierThresholds {
+    /// Standard deviations away from the pool's mean, same as the frontend tier lists
+    ZScore([f64; 5]),
+    /// Percent of the pool's top DPS
+    PercentOfTop([f64; 5]),
+    /// Splits the pool at its five largest DPS gaps
+    Clusters,
+}
+
+impl Default for TierThresholds {
+    fn default() -> Self {
+        TierThresholds::ZScore([2.0, 1.0, 0.0, -1.0, -2.0])
+    }
+}
+
+impl TierThresholds {
+    /// Parses a method of `z_score`, `percent_of_top` or `clusters` with
+    /// optional comma separated descending cutoffs.
+    pub fn parse(method: &str, cutoffs: Option<&str>) -> Result<Self, String> {
+        let cutoffs = match cutoffs {
+            Some(cutoffs) => {
+                let values = cutoffs
+                    .split(',')
+                    .map(|value| value.trim().parse::<f64>().map_err(|_| format!("Invalid tier cutoff {}", value)))
+                    .collect::<Result<Vec<f64>, String>>()?;
+                let values: [f64; 5] = values
+                    .try_into()
+                    .map_err(|values: Vec<f64>| format!("Expected 5 tier cutoffs, got {}", values.len()))?;
+                if values.windows(2).any(|pair| pair[0] <= pair[1]) {
+                    return Err("Tier cutoffs must be strictly descending".to_string());
+                }
+                Some(values)
+            },
+            None => None,
+        };
+
+        match (method, cutoffs) {
+            ("z_score", cutoffs) => Ok(cutoffs.map_or_else(TierThresholds::default, TierThresholds::ZScore)),
+            ("percent_of_top", cutoffs) => Ok(TierThresholds::PercentOfTop(cutoffs.unwrap_or([95.0, 85.0, 70.0, 55.0, 40.0]))),
+            ("clusters", None) => Ok(TierThresholds::Clusters),
+            ("clusters", Some(_)) => Err("Clustering doesn't take cutoffs".to_string()),
+            (method, _) => Err(format!("Unknown tier method {}, expected z_score, percent_of_top or clusters", method)),
+        }
+    }
+
+    /// Tier of every DPS value of a pool, in the same order.
+    pub fn assign(&self, dps: &[f64]) -> Vec<Tier> {
+        if dps.is_empty() {
+            return Vec::new();
+        }
+        match self {
+            TierThresholds::ZScore(cutoffs) => {
+                let n = dps.len() as f64;
+                let mean = dps.iter().sum::<f64>() / n;
+                let std_dev = (dps.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / n).sqrt();
+                dps.iter()
+                    .map(|d| {
+                        let z_score = if std_dev > 0.0 { (d - mean) / std_dev } else { 0.0 };
+                        tier_above(z_score, cutoffs, false)
+                    })
+                    .collect()
+            },
+            TierThresholds::PercentOfTop(cutoffs) => {
+                let top = dps.iter().copied().fold(f64::MIN, f64::max);
+                dps.iter()
+                    .map(|d| {
+                        let percent = if top > 0.0 { d / top * 100.0 } else { 0.0 };
+                        tier_above(percent, cutoffs, true)
+                    })
+                    .collect()
+            },
+            TierThresholds::Clusters => {
+                let mut sorted = dps.to_vec();
+                sorted.sort_by(|a, b| b.total_cmp(a));
+                let mut gaps: Vec<(usize, f64)> = sorted.windows(2).map(|pair| pair[0] - pair[1]).enumerate().collect();
+                gaps.sort_by(|a, b| b.1.total_cmp(&a.1));
+                // A break after position i starts the next tier at i + 1
+                let mut breaks: Vec<f64> = gaps
+                    .into_iter()
+                    .filter(|(_, gap)| *gap > 0.0)
+                    .take(TIERS.len() - 1)
+                    .map(|(i, _)| sorted[i + 1])
+                    .collect();
+                breaks.sort_by(|a, b| b.total_cmp(a));
+                dps.iter().map(|d| TIERS[breaks.iter().filter(|lower| *d <= **lower).count()]).collect()
+            },
+        }
+    }
+}
+
+fn tier_above(value: f64, cutoffs: &[f64; 5], inclusive: bool) -> Tier {
+    cutoffs
+        .iter()
+        .position(|cutoff| if inclusive { value >= *cutoff } else { value > *cutoff })
+        .map_or(Tier::F, |i| TIERS[i])
+}
+
+/// Assigns tiers by how many standard deviations a weapon's DPS is away
+/// from the mean of its pool, same as the frontend tier lists.
+pub fn assign_tiers(dps: &[f64]) -> Vec<Tier> {
+    TierThresholds::default().assign(dps)
+}
+
+/// What weapons are ranked against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TierGrouping {
+    #[default]
+    WeaponType,
+    BulletType,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TierEntry {
+    pub weapon: Weapon,
+    pub rank: usize,
+    pub tier: Tier,
+    pub dps: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TierGroup {
+    pub group: String,
+    pub weapons: Vec<TierEntry>,
+}
+
+/// A tier list and the settings it was ranked by.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TierList {
+    pub group_by: TierGrouping,
+    pub objective: Objective,
+    pub enemy: EnemyProfile,
+    pub buffs: String,
+    pub weak_point_hit_chance: f64,
+    pub thresholds: TierThresholds,
+    pub groups: Vec<TierGroup>,
+}
+
+/// Ranks every weapon of `dps` within its group, best first. Groups are in
+/// `Weapon::all()` order of their first weapon.
+pub fn tier_list(dps: &HashMap<Weapon, f64>, grouping: TierGrouping, thresholds: &TierThresholds) -> Vec<TierGroup> {
+    let mut groups: Vec<(String, Vec<(Weapon, f64)>)> = Vec::new();
+    for weapon in Weapon::all().iter().filter(|weapon| dps.contains_key(weapon)) {
+        let stats = WeaponBaseStats::get(*weapon);
+        let group = match grouping {
+            TierGrouping::WeaponType => stats.weapon_type.to_string(),
+            TierGrouping::BulletType => stats.bullet_type.to_string(),
+        };
+        let entry = (*weapon, dps[weapon]);
+        match groups.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, pool)) => pool.push(entry),
+            None => groups.push((group, vec![entry])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(group, mut pool)| {
+            pool.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let tiers = thresholds.assign(&pool.iter().map(|(_, dps)| *dps).collect::<Vec<_>>());
+            let weapons = pool
+                .into_iter()
+                .zip(tiers)
+                .enumerate()
+                .map(|(rank, ((weapon, dps), tier))| TierEntry { weapon, rank: rank + 1, tier, dps })
+                .collect();
+            TierGroup { group, weapons }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tier_thresholds() {
+        let dps = [100.0, 96.0, 80.0, 79.0, 50.0, 10.0];
+        assert_eq!(assign_tiers(&[5.0, 5.0]), vec![Tier::C, Tier::C]);
+
+        let percent = TierThresholds::parse("percent_of_top", None).unwrap();
+        assert_eq!(percent.assign(&dps), vec![Tier::S, Tier::S, Tier::B, Tier::B, Tier::D, Tier::F]);
+
+        let clusters = TierThresholds::parse("clusters", None).unwrap();
+        assert_eq!(clusters.assign(&dps), vec![Tier::S, Tier::A, Tier::B, Tier::C, Tier::D, Tier::F]);
+        assert_eq!(clusters.assign(&[100.0, 100.0, 10.0]), vec![Tier::S, Tier::S, Tier::A]);
+
+        let custom = TierThresholds::parse("z_score", Some("1.5, 0.5, 0, -0.5, -1.5")).unwrap();
+        assert_eq!(custom, TierThresholds::ZScore([1.5, 0.5, 0.0, -0.5, -1.5]));
+        assert!(TierThresholds::parse("percent_of_top", Some("90,80,70")).is_err());
+        assert!(TierThresholds::parse("percent_of_top", Some("90,80,80,70,60")).is_err());
+        assert!(TierThresholds::parse("kmeans", None).is_err());
+    }
+
+    #[test]
+    fn test_tier_list_groups() {
+        let dps = HashMap::from([
+            (Weapon::Thundercage, 3000.0),
+            (Weapon::Python, 2000.0),
+            (Weapon::PiercingLight, 90000.0),
+        ]);
+        let by_type = tier_list(&dps, TierGrouping::WeaponType, &TierThresholds::default());
+        assert_eq!(by_type.len(), 2);
+        let smgs = by_type.iter().find(|group| group.group == "SubmachineGun").unwrap();
+        assert_eq!(smgs.weapons.iter().map(|entry| (entry.weapon, entry.rank)).collect::<Vec<_>>(), vec![(Weapon::Thundercage, 1), (Weapon::Python, 2)]);
+        let snipers = by_type.iter().find(|group| group.group == "SniperRifle").unwrap();
+        assert_eq!(snipers.weapons[0].rank, 1);
+    }
+}
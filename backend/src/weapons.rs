@@ -1,14 +1,16 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use crate::modules::*;
 use strum_macros::{EnumString, Display};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString, Display, ToSchema)]
 pub enum WeaponType {
     AssaultRifle, BeamRifle, HandCannon, Handgun, Launcher, MachineGun, ScoutRifle, Shotgun,
     SniperRifle, SubmachineGun, TacticalRifle,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString, Display)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString, Display, ToSchema)]
 pub enum BulletType {
     GeneralRounds,
     SpecialRounds,
@@ -16,37 +18,39 @@ pub enum BulletType {
     HighPowerRounds
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
 pub struct Roll {
     pub roll_type: RollType,
     pub value: f64,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 pub enum RollType {
     Atk, ElementAtk, WeakPointDamage, Crit, CritDamage, RoundsPerMagazine, ColossusDamage,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Module {
     pub name: String,
     pub module_type: ModuleType,
     pub effects: Vec<ModuleEffect>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 pub enum ModuleBonusType {
     Atk, WeakPointDamage, Crit, CritDamage, RoundsPerMagazine, ReloadTime, FireRate,
-    EleEnhancement, EleMult, FiringFiesta, ShellCapacity
+    EleEnhancement, EleMult, FiringFiesta, ShellCapacity,
+    /// Flat damage against colossi, only rolled on components
+    ColossusDamage,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
 pub struct ModuleEffect {
     pub effect_type: ModuleBonusType,
     pub value: f64,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 pub enum ModuleType {
     None,
     Atk,
@@ -89,7 +93,7 @@ impl From<usize> for ModuleType {
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, EnumString, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString, Display, ToSchema)]
 pub enum Weapon {
     // AssaultRifle
     CaligosHorn,
@@ -261,6 +265,45 @@ impl Weapon {
     }
 }
 
+/// Number of module slots and substat rolls a weapon can be built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SlotCounts {
+    pub modules: usize,
+    pub rolls: usize,
+}
+
+impl Default for SlotCounts {
+    fn default() -> Self {
+        SlotCounts { modules: 10, rolls: 4 }
+    }
+}
+
+impl SlotCounts {
+    pub fn is_full(&self) -> bool {
+        *self == SlotCounts::default()
+    }
+}
+
+impl Weapon {
+    /// Slots of the fully built weapon, from its `WeaponBaseStats`. Fewer
+    /// slots are set through `OptimizationConfig::module_slots` and
+    /// `roll_slots`, or the weapon's level.
+    pub fn slot_counts(self) -> SlotCounts {
+        WeaponBaseStats::get(self).slots
+    }
+}
+
+/// Every distinct module catalog, keyed by the name its stored
+/// combinations are saved under.
+pub const MODULE_SETS: [(&str, BulletType, WeaponType); 6] = [
+    ("GeneralRounds", BulletType::GeneralRounds, WeaponType::AssaultRifle),
+    ("SpecialRounds", BulletType::SpecialRounds, WeaponType::AssaultRifle),
+    ("ImpactRounds", BulletType::ImpactRounds, WeaponType::AssaultRifle),
+    ("SniperRifle", BulletType::HighPowerRounds, WeaponType::SniperRifle),
+    ("Shotgun", BulletType::HighPowerRounds, WeaponType::Shotgun),
+    ("Launcher", BulletType::HighPowerRounds, WeaponType::Launcher),
+];
+
 pub fn get_available_modules(bullet_type: BulletType, weapon_type: WeaponType) -> Vec<Module> {
     match bullet_type {
         BulletType::GeneralRounds => general_rounds_modules::get_modules(),
@@ -379,7 +422,7 @@ pub fn get_available_rolls(weapon_type: WeaponType) -> Vec<Roll> {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
 pub struct WeaponBaseStats {
     pub ele_enhancement: f64,
     pub flat_ele_atk: f64,
@@ -396,9 +439,27 @@ pub struct WeaponBaseStats {
     pub crit_damage: f64,
     pub weak_point_damage: f64,
     pub bullets_per_shot: f64,
+    /// Layout of the fully built weapon. Only written out when it differs
+    /// from the full layout, so game data versions of weapons with the
+    /// common layout don't change.
+    #[serde(default, skip_serializing_if = "SlotCounts::is_full")]
+    pub slots: SlotCounts,
 }
 
 impl WeaponBaseStats {
+    /// Which entry of `MODULE_SETS` this weapon takes its modules from.
+    pub fn module_key(&self) -> &'static str {
+        match (self.bullet_type, self.weapon_type) {
+            (_, WeaponType::SniperRifle) => "SniperRifle",
+            (_, WeaponType::Shotgun) => "Shotgun",
+            (_, WeaponType::Launcher) => "Launcher",
+            (BulletType::ImpactRounds, _) => "ImpactRounds",
+            (BulletType::GeneralRounds, _) => "GeneralRounds",
+            (BulletType::SpecialRounds, _) => "SpecialRounds",
+            _ => panic!("Unexpected combination of bullet type and weapon type"),
+        }
+    }
+
     pub fn get(name: Weapon) -> Self {
         match name {
             Weapon::Thundercage => Self {
@@ -417,6 +478,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 1.2,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::FallenHope => Self {
                 ele_enhancement: 0.0,
@@ -434,6 +496,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.7,
                 weak_point_damage: 1.2,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Perferator => Self {
                 ele_enhancement: 0.0,
@@ -451,6 +514,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 2.25,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::AssassinsEdge => Self {
                 ele_enhancement: 0.0,
@@ -468,6 +532,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 2.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::ImmediateExecution => Self {
                 ele_enhancement: 0.0,
@@ -485,6 +550,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 2.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::ExplorersPath => Self {
                 ele_enhancement: 0.0,
@@ -502,6 +568,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.3,
                 weak_point_damage: 2.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::NazeistrasDevotion => Self {
                 ele_enhancement: 0.0,
@@ -519,6 +586,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.25,
                 weak_point_damage: 1.7,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::BurningHound => Self {
                 ele_enhancement: 0.0,
@@ -536,6 +604,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.7,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Tamer => Self {
                 ele_enhancement: 0.0,
@@ -553,6 +622,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.6,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::EternalWillpower => Self {
                 ele_enhancement: 0.0,
@@ -570,6 +640,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::PiercingLight => Self {
                 ele_enhancement: 0.0,
@@ -587,6 +658,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 2.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::AfterglowSword => Self {
                 ele_enhancement: 0.0,
@@ -604,6 +676,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 1.8,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Viper => Self {
                 ele_enhancement: 0.0,
@@ -621,6 +694,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.85,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::YoungNoblesAmbition => Self {
                 ele_enhancement: 0.0,
@@ -638,6 +712,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.3,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::SecretGarden => Self {
                 ele_enhancement: 0.0,
@@ -655,6 +730,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.25,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Detachment => Self {
                 ele_enhancement: 0.0,
@@ -672,6 +748,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::TheUnwelcomed => Self {
                 ele_enhancement: 0.0,
@@ -689,6 +766,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::EnduringLegacy => Self {
                 ele_enhancement: 0.0,
@@ -706,6 +784,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.3,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::GregsReversedFate => Self {
                 ele_enhancement: 0.0,
@@ -723,6 +802,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 2.0,
                 bullets_per_shot: 2.4,
+                slots: SlotCounts::default(),
             },
             Weapon::Python => Self {
                 ele_enhancement: 0.0,
@@ -740,6 +820,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.3,
                 weak_point_damage: 2.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::VestigialOrgan => Self {
                 ele_enhancement: 0.0,
@@ -757,6 +838,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.1,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::LightspeedBarrel => Self {
                 ele_enhancement: 0.0,
@@ -774,6 +856,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::GuardianR4 => Self {
                 ele_enhancement: 0.0,
@@ -791,6 +874,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::DeathRoar => Self {
                 ele_enhancement: 0.0,
@@ -808,6 +892,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::RestoredRelic => Self {
                 ele_enhancement: 0.0,
@@ -825,6 +910,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::SigvoresProof => Self {
                 ele_enhancement: 0.0,
@@ -842,6 +928,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Subconsciousness => Self {
                 ele_enhancement: 0.0,
@@ -859,6 +946,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 8.0,
+                slots: SlotCounts::default(),
             },
             Weapon::InABlink => Self {
                 ele_enhancement: 0.0,
@@ -876,6 +964,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 10.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Gangster => Self {
                 ele_enhancement: 0.0,
@@ -893,6 +982,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.1,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 12.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Executor => Self {
                 ele_enhancement: 0.0,
@@ -910,6 +1000,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.1,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 15.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Smithereens => Self {
                 ele_enhancement: 0.0,
@@ -927,6 +1018,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.1,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 10.0,
+                slots: SlotCounts::default(),
             },
             Weapon::TheLastKnight => Self {
                 ele_enhancement: 0.0,
@@ -944,6 +1036,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 9.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Fireworks => Self {
                 ele_enhancement: 0.0,
@@ -961,6 +1054,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.15,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 6.0,
+                slots: SlotCounts::default(),
             },
             Weapon::RecipientUnknown => Self {
                 ele_enhancement: 0.0,
@@ -978,6 +1072,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Belief => Self {
                 ele_enhancement: 0.0,
@@ -995,6 +1090,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.7,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::DifferentDream => Self {
                 ele_enhancement: 0.0,
@@ -1012,6 +1108,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::SupermoonZ15 => Self {
                 ele_enhancement: 0.0,
@@ -1029,6 +1126,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::ForrestGaze => Self {
                 ele_enhancement: 0.0,
@@ -1046,6 +1144,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::WaveOfLight => Self {
                 ele_enhancement: 0.0,
@@ -1063,6 +1162,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.8,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::TheAgeOfInnovation => Self {
                 ele_enhancement: 0.0,
@@ -1080,6 +1180,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::SillyWeeper => Self {
                 ele_enhancement: 0.0,
@@ -1097,6 +1198,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::NightmareHound => Self {
                 ele_enhancement: 0.0,
@@ -1114,6 +1216,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::HerosScar => Self {
                 ele_enhancement: 0.0,
@@ -1131,6 +1234,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::SonicCoOp => Self {
                 ele_enhancement: 0.0,
@@ -1148,6 +1252,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::BlueBeetle => Self {
                 ele_enhancement: 0.0,
@@ -1165,6 +1270,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::CaligosHorn => Self {
                 ele_enhancement: 0.0,
@@ -1182,6 +1288,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::DivinePunishment => Self {
                 ele_enhancement: 0.0,
@@ -1199,6 +1306,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.7,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::MagnusAA => Self {
                 ele_enhancement: 0.0,
@@ -1216,6 +1324,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::OminousHound => Self {
                 ele_enhancement: 0.0,
@@ -1233,6 +1342,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::PhantomPain => Self {
                 ele_enhancement: 0.0,
@@ -1250,6 +1360,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::MillenniumFrost => Self {
                 ele_enhancement: 0.0,
@@ -1267,6 +1378,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::HungryHound => Self {
                 ele_enhancement: 0.0,
@@ -1284,6 +1396,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.7,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::NewBeginning => Self {
                 ele_enhancement: 0.0,
@@ -1301,6 +1414,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::RedWolf => Self {
                 ele_enhancement: 0.0,
@@ -1318,6 +1432,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::TheLastDagger => Self {
                 ele_enhancement: 0.0,
@@ -1335,6 +1450,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 1.6,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::RoseOfAisha => Self {
                 ele_enhancement: 0.0,
@@ -1352,6 +1468,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.8,
                 weak_point_damage: 1.7,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::TheFinalMaster => Self {
                 ele_enhancement: 0.0,
@@ -1369,6 +1486,7 @@ impl WeaponBaseStats {
                 crit_damage: 2.0,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::TamedBeast => Self {
                 ele_enhancement: 0.0,
@@ -1386,6 +1504,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::AlbionCavalryGun => Self {
                 ele_enhancement: 0.0,
@@ -1403,6 +1522,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.25,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Cygnus => Self {
                 ele_enhancement: 0.0,
@@ -1420,6 +1540,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.1,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::LionOfBlueMane => Self {
                 ele_enhancement: 0.0,
@@ -1437,6 +1558,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::RascalV51 => Self {
                 ele_enhancement: 0.0,
@@ -1454,6 +1576,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::UnfinishedStudy => Self {
                 ele_enhancement: 0.0,
@@ -1471,6 +1594,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Albinism => Self {
                 ele_enhancement: 0.0,
@@ -1488,6 +1612,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.3,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::AlterEgo => Self {
                 ele_enhancement: 0.0,
@@ -1505,6 +1630,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::BlueBloodBloomer => Self {
                 ele_enhancement: 0.0,
@@ -1522,6 +1648,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::DevilsCall => Self {
                 ele_enhancement: 0.0,
@@ -1539,6 +1666,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.5,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::Firefly => Self {
                 ele_enhancement: 0.0,
@@ -1556,6 +1684,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.2,
                 weak_point_damage: 1.0,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
             Weapon::RedEye => Self {
                 ele_enhancement: 0.0,
@@ -1573,6 +1702,7 @@ impl WeaponBaseStats {
                 crit_damage: 1.3,
                 weak_point_damage: 1.5,
                 bullets_per_shot: 1.0,
+                slots: SlotCounts::default(),
             },
         }
     }
@@ -1,58 +1,111 @@
 use mongodb::{Client, options::ClientOptions, bson::doc};
 use mongodb::{options::ServerApi, options::ServerApiVersion};
 use structopt::StructOpt;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
 use dotenv::dotenv;
-use log::info;
-use std::hash::{Hash, Hasher};
+use log::{info, warn};
 use tokio::time::Duration;
-use std::fs::{File, create_dir_all};
-use std::io::{Write, Read};
+use std::fs::File;
 use std::path::Path;
-use bincode;
-use reqwest;
 
 mod weapons;
 mod calculate;
 mod modules;
+pub mod components;
+pub mod progression;
+pub mod tiers;
+pub mod diff;
+pub mod game_data;
+pub mod grid;
+pub mod checkpoint;
+pub mod output;
+pub mod store;
+pub mod combination_cache;
+pub mod input_hash;
+pub mod jobs;
+pub mod sweep;
+pub mod robust;
+pub mod progress;
+pub mod shard;
+pub mod admin_auth;
+pub mod result_key;
+pub mod export;
 
-use crate::weapons::{Weapon, WeaponType, BulletType, WeaponBaseStats, get_available_modules, get_available_rolls};
-use crate::calculate::{OptimizationConfig, OptimizationResult, ModuleCombinations, generate_module_combinations, optimize_weapon};
-
-#[derive(Debug, Clone, PartialEq)]
-struct OptimizationKey {
-    weapon: String,
-    weak_point_hit_chance: f64,
-    valby: bool,
-    enzo: bool
-}
-
-impl Eq for OptimizationKey {}
-
-impl Hash for OptimizationKey {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.weapon.hash(state);
-        let bits: u64 = self.weak_point_hit_chance.to_bits();
-        bits.hash(state);
-        self.valby.hash(state);
-        self.enzo.hash(state);
-    }
-}
-
-#[derive(Debug)]
-struct WorkerResults {
-    module_combinations: HashMap<String, ModuleCombinations>,
-    weapon_results: HashMap<OptimizationKey, OptimizationResult>,
-}
+use crate::admin_auth::AdminClient;
+use crate::weapons::{Weapon, WeaponType, BulletType, WeaponBaseStats, MODULE_SETS, get_available_modules, get_available_rolls};
+use crate::calculate::{EnemyProfile, OptimizationConfig, OptimizationResult, ModuleCombinations, combinations_for, generate_module_combinations, optimize_weapon, optimize_weapon_sweep, optimize_weapon_with_progress};
+use crate::sweep::{SweepPoint, WeaponSweep, sweep_grid};
+use crate::progression::WeaponProgress;
+use crate::game_data::{GameData, GAME_DATA_DIR, data_changes, game_data_version};
+use crate::diff::{DiffThresholds, WeaponDelta, compare_results, compare_to_stored};
+use crate::grid::{BuffSet, ComponentLoadout, ComputationGrid, GridConfig, Objective, load_file, parse_list};
+use crate::jobs::{Job, JobKind};
+use crate::input_hash::input_hash;
+use crate::combination_cache::CACHE_DIR;
+use crate::checkpoint::{RunCheckpoint, UnitOutcome, WorkUnit};
+use crate::output::{Output, OutputRecord, OutputTarget, SweepRecord};
+use crate::store::ResultRecord;
+use crate::progress::RunProgress;
+use crate::shard::DEFAULT_UNIT_ATTEMPTS;
+use crate::robust::{RobustObjective, WeakPointDistribution, optimize_weapon_robust};
+use crate::result_key::ResultKey;
+use crate::export::ExportFormat;
 
 #[derive(StructOpt)]
 struct Cli {
+    /// Recompute results even if their inputs haven't changed
+    #[structopt(long, global = true)]
+    force: bool,
+    /// Continue the run with this id, skipping the units it already finished
+    #[structopt(long, global = true)]
+    resume: Option<String>,
+    /// TOML or JSON file with the weak point hit chances, buffs, objectives and enemies to compute
+    #[structopt(long, global = true, parse(from_os_str))]
+    grid: Option<std::path::PathBuf>,
+    /// Comma separated weak point hit chances, overrides the grid file
+    #[structopt(long, global = true)]
+    wp_chances: Option<String>,
+    /// Comma separated buff sets out of none, valby, enzo and valby+enzo
+    #[structopt(long, global = true)]
+    buffs: Option<String>,
+    /// Comma separated objectives out of Sustained and InfiniteAmmo
+    #[structopt(long, global = true)]
+    objectives: Option<String>,
+    /// Comma separated enemy profiles out of Colossus and Standard
+    #[structopt(long, global = true)]
+    enemies: Option<String>,
+    /// TOML or JSON file with build constraints, overrides the grid file's
+    #[structopt(long, global = true, parse(from_os_str))]
+    constraints: Option<std::path::PathBuf>,
+    /// Module slots of every weapon, for partially socketed builds
+    #[structopt(long, global = true)]
+    module_slots: Option<usize>,
+    /// Roll slots of every weapon
+    #[structopt(long, global = true)]
+    roll_slots: Option<usize>,
+    /// TOML or JSON file with the equipped reactor and external components
+    #[structopt(long, global = true, parse(from_os_str))]
+    components: Option<std::path::PathBuf>,
+    /// Pick the best substats out of the reactor's instead of equipping all
+    #[structopt(long, global = true)]
+    optimize_reactor: bool,
+    /// Also write the JSON run summary to this file
+    #[structopt(long, global = true, parse(from_os_str))]
+    summary: Option<std::path::PathBuf>,
+    /// Where results go: mongo, jsonl:<path> or sqlite:<path>
+    #[structopt(long, global = true, default_value = "mongo")]
+    output: OutputTarget,
+    /// Fail instead of regenerating module combination files that don't match the module catalog
+    #[structopt(long, global = true)]
+    no_regenerate: bool,
     #[structopt(subcommand)]
     cmd: Command,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(StructOpt, Debug)]
 enum Command {
     UpdateWeapons { names: Vec<String> },
@@ -60,11 +113,138 @@ enum Command {
     UpdateBulletType { bullet_type: String },
     UpdateModules { module_type: String },
     UpdateAll,
+    /// Sweeps the weak point hit chance from 0 to 1 for the given weapons (all if none given)
+    SweepWeakPoint {
+        names: Vec<String>,
+        #[structopt(long, default_value = "0.05")]
+        step: f64,
+    },
+    /// Finds one build for a distribution of weak point hit chances and prints it as JSON
+    OptimizeRobust {
+        name: String,
+        #[structopt(long)]
+        valby: bool,
+        #[structopt(long)]
+        enzo: bool,
+        /// Beta distribution parameters as "alpha,beta"
+        #[structopt(long, required_unless = "histogram")]
+        beta: Option<String>,
+        /// JSON file with [chance, weight] pairs
+        #[structopt(long, parse(from_os_str))]
+        histogram: Option<std::path::PathBuf>,
+        /// Optimize this percentile (0 to 1) instead of the expected DPS
+        #[structopt(long)]
+        percentile: Option<f64>,
+    },
+    /// Finds the best build at the given weapon level and what leveling to 100 would add
+    OptimizeAtLevel {
+        name: String,
+        level: u32,
+        /// Weapon ATK the game shows at this level
+        #[structopt(long)]
+        atk: f64,
+        /// Modules that fit in the weapon's module capacity
+        #[structopt(long, default_value = "10")]
+        modules: usize,
+        #[structopt(long, default_value = "4")]
+        core_slots: usize,
+        #[structopt(long, default_value = "0.5")]
+        weak_point_hit_chance: f64,
+        #[structopt(long)]
+        valby: bool,
+        #[structopt(long)]
+        enzo: bool,
+    },
+    /// Writes the compiled-in game data to game_data/<version>.json
+    ExportGameData,
+    /// Prints the stored builds of the given weapons (all if none given) at every grid point
+    ExportBuilds {
+        names: Vec<String>,
+        /// markdown, text or csv
+        #[structopt(long, default_value = "markdown")]
+        format: ExportFormat,
+    },
+    /// Recomputes results under two game data versions and reports what changed
+    DiffVersions {
+        from: String,
+        to: String,
+        /// Weapons to compare, all if none given
+        names: Vec<String>,
+        /// Patch name of the changelog entry, `to` if not given
+        #[structopt(long)]
+        label: Option<String>,
+        /// Print a changelog entry instead of the full diff
+        #[structopt(long)]
+        changelog: bool,
+    },
+    /// Recomputes results like the update commands and compares them to the stored ones without writing
+    Diff {
+        /// Weapons to recompute
+        names: Vec<String>,
+        /// Recompute every weapon of this type instead
+        #[structopt(long, conflicts_with = "bullet-type")]
+        weapon_type: Option<String>,
+        /// Recompute every weapon using this bullet type instead
+        #[structopt(long)]
+        bullet_type: Option<String>,
+        /// Fail if a max DPS changes by more than this many percent
+        #[structopt(long, default_value = "1.0")]
+        threshold: f64,
+        /// Fail if a tier changes
+        #[structopt(long)]
+        fail_on_tier_change: bool,
+        /// Fail if an optimal build changes
+        #[structopt(long)]
+        fail_on_build_change: bool,
+    },
+    /// Loads results written with a jsonl: or sqlite: output into MongoDB
+    Import {
+        source: OutputTarget,
+    },
+    /// Queues the work units of a sharded run for agents to lease
+    Coordinate {
+        /// Weapons to compute, all if none given
+        names: Vec<String>,
+        /// Run to add the units to, a new one if absent
+        #[structopt(long)]
+        run_id: Option<String>,
+        /// Wait for the agents to finish the run and print its unit counts
+        #[structopt(long)]
+        wait: bool,
+        /// Seconds between checks while waiting
+        #[structopt(long, default_value = "10")]
+        poll_interval: u64,
+    },
+    /// Leases and computes units of a sharded run until none are left
+    Agent {
+        run_id: String,
+        /// Identifies this agent in unit leases, defaults to the host name and pid
+        #[structopt(long)]
+        worker_id: Option<String>,
+        /// Seconds an agent has to finish a unit before another may take it
+        #[structopt(long, default_value = "900")]
+        lease_secs: u64,
+        /// Seconds to wait for units leased by other agents to finish or expire
+        #[structopt(long, default_value = "10")]
+        poll_interval: u64,
+    },
+    /// Runs jobs from the worker_jobs queue until stopped
+    Daemon {
+        /// Identifies this worker in job leases, defaults to the host name and pid
+        #[structopt(long)]
+        worker_id: Option<String>,
+        /// Seconds to wait before polling an empty queue again
+        #[structopt(long, default_value = "10")]
+        poll_interval: u64,
+        /// Seconds a job stays leased without a heartbeat before another worker may retry it
+        #[structopt(long, default_value = "120")]
+        lease_secs: u64,
+    },
 }
 
 async fn create_mongo_client() -> Result<Client, Box<dyn std::error::Error>> {
     dotenv().ok();
-    let mongodb_uri = env::var("MONGODB_URI").expect("MONGODB_URI must be set");
+    let mongodb_uri = env::var("MONGODB_URI").map_err(|_| "MONGODB_URI must be set, or pick a file --output")?;
     info!("Connecting to MongoDB...");
     
     let mut client_options = ClientOptions::parse(&mongodb_uri).await?;
@@ -88,91 +268,174 @@ async fn create_mongo_client() -> Result<Client, Box<dyn std::error::Error>> {
     Ok(client)
 }
 
+/// The grid file if given, with any list flags replacing its fields.
+fn grid_from_cli(opts: &Cli) -> Result<ComputationGrid, Box<dyn std::error::Error>> {
+    let mut grid = match &opts.grid {
+        Some(path) => ComputationGrid::load(path)?,
+        None => ComputationGrid::default(),
+    };
+    if let Some(chances) = &opts.wp_chances {
+        grid.weak_point_hit_chances = parse_list(chances)?;
+    }
+    if let Some(buffs) = &opts.buffs {
+        grid.buffs = parse_list(buffs)?;
+    }
+    if let Some(objectives) = &opts.objectives {
+        grid.objectives = parse_list(objectives)?;
+    }
+    if let Some(enemies) = &opts.enemies {
+        grid.enemies = parse_list(enemies)?;
+    }
+    if let Some(path) = &opts.constraints {
+        grid.constraints = load_file(path)?;
+    }
+    if opts.module_slots.is_some() {
+        grid.module_slots = opts.module_slots;
+    }
+    if opts.roll_slots.is_some() {
+        grid.roll_slots = opts.roll_slots;
+    }
+    if let Some(path) = &opts.components {
+        let loadout: ComponentLoadout = load_file(path)?;
+        grid.reactor = loadout.reactor;
+        grid.external_components = loadout.external_components;
+    }
+    if opts.optimize_reactor {
+        grid.optimize_reactor = true;
+    }
+    grid.validate()?;
+    Ok(grid)
+}
+
+async fn connect_db() -> Result<mongodb::Database, Box<dyn std::error::Error>> {
+    Ok(create_mongo_client().await?.database("weapon_optimizer"))
+}
+
+async fn open_output(target: &OutputTarget) -> Result<Output, Box<dyn std::error::Error>> {
+    match target {
+        OutputTarget::Mongo => Ok(Output::Mongo(connect_db().await?)),
+        OutputTarget::JsonLines(path) => Ok(Output::JsonLines(path.clone())),
+        OutputTarget::Sqlite(path) => Output::sqlite(path),
+    }
+}
+
+/// Copies everything from a file output into MongoDB.
+async fn import_results(source: &OutputTarget) -> Result<(), Box<dyn std::error::Error>> {
+    if *source == OutputTarget::Mongo {
+        return Err("Import reads a jsonl:<path> or sqlite:<path> output".into());
+    }
+    let records = open_output(source).await?.read_records().await?;
+    let target = Output::Mongo(connect_db().await?);
+
+    let (mut results, mut sweeps) = (0, 0);
+    for record in records {
+        match record {
+            OutputRecord::WeaponResults(record) => {
+                target.store_result(&record).await?;
+                results += 1;
+            },
+            OutputRecord::WpSweeps(record) => {
+                target.store_sweep(&record).await?;
+                sweeps += 1;
+            },
+        }
+    }
+    info!("Imported {} results and {} weak point sweeps", results, sweeps);
+    Ok(())
+}
+
+/// Prints the current game data's results of `names` at the grid's points,
+/// in `Weapon::all()` and grid order.
+async fn export_builds(output: &Output, names: Vec<String>, grid: &ComputationGrid, format: ExportFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let weapons: Vec<Weapon> = if names.is_empty() {
+        Weapon::all().to_vec()
+    } else {
+        select_weapons(names, None, None)?
+    };
+    let results: HashMap<ResultKey, OptimizationResult> = output
+        .load_results()
+        .await?
+        .into_iter()
+        .filter(|record| record.data_version == game_data_version())
+        .filter_map(|record| Some((record.key()?, record.result)))
+        .collect();
+
+    let mut keys = Vec::new();
+    for weapon in Weapon::all().iter().copied().filter(|weapon| weapons.contains(weapon)) {
+        for point in grid.points() {
+            for &chance in &grid.weak_point_hit_chances {
+                let key = ResultKey::new(weapon, chance, point.buffs, point.objective, point.enemy);
+                if results.contains_key(&key) {
+                    keys.push(key);
+                } else {
+                    warn!("No stored result for {}", key);
+                }
+            }
+        }
+    }
+    if keys.is_empty() {
+        return Err("No stored results to export".into());
+    }
+    print!("{}", format.render_all(keys.iter().map(|key| (key, &results[key]))));
+    Ok(())
+}
+
 fn generate_all_module_combinations(module_type: Option<&str>) -> HashMap<String, ModuleCombinations> {
     info!("Generating module combinations...");
     let mut combinations = HashMap::new();
-    let module_types = [
-        ("GeneralRounds", BulletType::GeneralRounds, None),
-        ("SpecialRounds", BulletType::SpecialRounds, None),
-        ("ImpactRounds", BulletType::ImpactRounds, None),
-        ("SniperRifle", BulletType::HighPowerRounds, Some(WeaponType::SniperRifle)),
-        ("Shotgun", BulletType::HighPowerRounds, Some(WeaponType::Shotgun)),
-        ("Launcher", BulletType::HighPowerRounds, Some(WeaponType::Launcher)),
-    ];
-
-    for (key, bullet_type, weapon_type) in module_types.iter() {
+    for (key, bullet_type, weapon_type) in MODULE_SETS.iter() {
         if module_type.is_none() || module_type == Some(key) {
-            let modules = get_available_modules(*bullet_type, weapon_type.unwrap_or(WeaponType::AssaultRifle));
-            let module_combinations = generate_module_combinations(&modules);
-            combinations.insert(key.to_string(), ModuleCombinations { combinations: module_combinations });
+            let modules = get_available_modules(*bullet_type, *weapon_type);
+            let module_combinations = combination_cache::generate(&modules);
+            if let Err(e) = combination_cache::save(CACHE_DIR, key, &modules, &module_combinations) {
+                eprintln!("Failed to save module combinations: {}", e);
+            }
+            combinations.insert(key.to_string(), module_combinations);
         }
     }
 
-    if let Err(e) = save_module_combinations(&combinations, "module_combinations") {
-        eprintln!("Failed to save module combinations: {}", e);
-    }
-
     combinations
 }
 
-async fn store_results_in_db(db: &mongodb::Database, results: WorkerResults) -> Result<(), Box<dyn std::error::Error>> {
-    // Store module combinations
+async fn store_module_combinations_in_db(db: &mongodb::Database, module_combinations: &HashMap<String, ModuleCombinations>) -> Result<(), Box<dyn std::error::Error>> {
     let module_collection = db.collection::<ModuleCombinations>("module_combinations");
-    for (key, combinations) in results.module_combinations {
+    for (key, combinations) in module_combinations {
         module_collection.update_one(
             doc! { "key": key },
-            doc! { "$set": mongodb::bson::to_bson(&combinations).unwrap() },
-            mongodb::options::UpdateOptions::builder().upsert(true).build(),
-        ).await?;
-    }
-
-    // Store weapon results
-    let weapon_collection = db.collection::<OptimizationResult>("weapon_results");
-    for (key, result) in results.weapon_results {
-        weapon_collection.update_one(
-            doc! {
-                "weapon": key.weapon,
-                "weak_point_hit_chance": key.weak_point_hit_chance,
-                "valby": key.valby,
-                "enzo": key.enzo,
-            },
-            doc! { "$set": mongodb::bson::to_bson(&result).unwrap() },
+            doc! { "$set": mongodb::bson::to_bson(combinations)? },
             mongodb::options::UpdateOptions::builder().upsert(true).build(),
         ).await?;
     }
-
     Ok(())
 }
 
-async fn update_weapons(db: &mongodb::Database, module_combinations: &HashMap<String, ModuleCombinations>, names: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
-    for name in names {
-        if let Ok(weapon) = Weapon::from_str(&name) {
-            update_weapon(db, module_combinations, weapon, &OptimizationConfig::default()).await?;
-            update_weapon(db, module_combinations, weapon, &OptimizationConfig { valby: true, ..Default::default() }).await?;
-            update_weapon(db, module_combinations, weapon, &OptimizationConfig { enzo: true, ..Default::default() }).await?;
-        }
-    }
-    Ok(())
+/// Input hashes of the stored current-version results, optionally of one weapon only.
+async fn stored_input_hashes(output: &Output, weapon: Option<Weapon>) -> Result<HashMap<ResultKey, String>, Box<dyn std::error::Error>> {
+    Ok(output
+        .stored_input_hashes(weapon)
+        .await?
+        .into_iter()
+        .map(|stored| (stored.key, stored.input_hash))
+        .collect())
 }
 
-async fn update_bullet_type(db: &mongodb::Database, module_combinations: &HashMap<String, ModuleCombinations>, bullet_type: String) -> Result<(), Box<dyn std::error::Error>> {
+async fn update_weapons(output: &Output, module_combinations: &HashMap<String, ModuleCombinations>, names: Vec<String>, grid: &ComputationGrid, force: bool, run: &mut RunCheckpoint, progress: &RunProgress) -> Result<(), Box<dyn std::error::Error>> {
+    let weapons: Vec<Weapon> = names.iter().filter_map(|name| Weapon::from_str(name).ok()).collect();
+    update_weapon_list(output, module_combinations, &weapons, grid, force, run, progress).await
+}
+
+async fn update_bullet_type(output: &Output, module_combinations: &HashMap<String, ModuleCombinations>, bullet_type: String, grid: &ComputationGrid, force: bool, run: &mut RunCheckpoint, progress: &RunProgress) -> Result<(), Box<dyn std::error::Error>> {
     if let Ok(bt) = BulletType::from_str(&bullet_type) {
-        for weapon in Weapon::all().iter().filter(|w| WeaponBaseStats::get(**w).bullet_type == bt) {
-            update_weapon(db, module_combinations, *weapon, &OptimizationConfig::default()).await?;
-            update_weapon(db, module_combinations, *weapon, &OptimizationConfig { valby: true, ..Default::default() }).await?;
-            update_weapon(db, module_combinations, *weapon, &OptimizationConfig { enzo: true, ..Default::default() }).await?;
-        }
+        let weapons: Vec<Weapon> = Weapon::all().iter().copied().filter(|w| WeaponBaseStats::get(*w).bullet_type == bt).collect();
+        update_weapon_list(output, module_combinations, &weapons, grid, force, run, progress).await?;
     }
     Ok(())
 }
 
-async fn update_weapon_type(db: &mongodb::Database, module_combinations: &HashMap<String, ModuleCombinations>, weapon_type: String) -> Result<(), Box<dyn std::error::Error>> {
+async fn update_weapon_type(output: &Output, module_combinations: &HashMap<String, ModuleCombinations>, weapon_type: String, grid: &ComputationGrid, force: bool, run: &mut RunCheckpoint, progress: &RunProgress) -> Result<(), Box<dyn std::error::Error>> {
     if let Ok(wt) = WeaponType::from_str(&weapon_type) {
-        for weapon in Weapon::all().iter().filter(|w| WeaponBaseStats::get(**w).weapon_type == wt) {
-            update_weapon(db, module_combinations, *weapon, &OptimizationConfig::default()).await?;
-            update_weapon(db, module_combinations, *weapon, &OptimizationConfig { valby: true, ..Default::default() }).await?;
-            update_weapon(db, module_combinations, *weapon, &OptimizationConfig { enzo: true, ..Default::default() }).await?;
-        }
+        let weapons: Vec<Weapon> = Weapon::all().iter().copied().filter(|w| WeaponBaseStats::get(*w).weapon_type == wt).collect();
+        update_weapon_list(output, module_combinations, &weapons, grid, force, run, progress).await?;
     }
     Ok(())
 }
@@ -182,9 +445,10 @@ async fn update_modules(module_combinations: &HashMap<String, ModuleCombinations
         .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("Module combinations not found for {}", module_type)))?;
 
     // Save the updated combinations
-    let mut updated_combinations = HashMap::new();
-    updated_combinations.insert(module_type.clone(), combinations.clone());
-    save_module_combinations(&updated_combinations, "module_combinations")?;
+    let (_, bullet_type, weapon_type) = MODULE_SETS.iter().find(|(key, _, _)| *key == module_type)
+        .ok_or_else(|| format!("Unknown module type {}", module_type))?;
+    let modules = get_available_modules(*bullet_type, *weapon_type);
+    combination_cache::save(CACHE_DIR, &module_type, &modules, combinations)?;
 
     info!("Updated module combinations for {}", module_type);
     info!("Number of combinations: {}", combinations.combinations.len());
@@ -192,150 +456,849 @@ async fn update_modules(module_combinations: &HashMap<String, ModuleCombinations
     Ok(())
 }
 
-async fn update_all(db: &mongodb::Database, module_combinations: &HashMap<String, ModuleCombinations>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut results = WorkerResults {
-        module_combinations: module_combinations.clone(),
-        weapon_results: HashMap::new(),
+async fn update_all(output: &Output, module_combinations: &HashMap<String, ModuleCombinations>, grid: &ComputationGrid, force: bool, run: &mut RunCheckpoint, progress: &RunProgress) -> Result<(), Box<dyn std::error::Error>> {
+    if let Output::Mongo(db) = output {
+        store_module_combinations_in_db(db, module_combinations).await?;
+    }
+    update_weapon_list(output, module_combinations, Weapon::all(), grid, force, run, progress).await
+}
+
+async fn update_weapon_list(output: &Output, module_combinations: &HashMap<String, ModuleCombinations>, weapons: &[Weapon], grid: &ComputationGrid, force: bool, run: &mut RunCheckpoint, progress: &RunProgress) -> Result<(), Box<dyn std::error::Error>> {
+    let stored_hashes = match (force, weapons) {
+        (true, _) => HashMap::new(),
+        (false, [weapon]) => stored_input_hashes(output, Some(*weapon)).await?,
+        (false, _) => stored_input_hashes(output, None).await?,
     };
+    let points = grid.points();
+    for weapon in weapons {
+        progress.queue(&weapon.to_string(), (points.len() * grid.weak_point_hit_chances.len()) as u64);
+    }
+    for weapon in weapons {
+        for point in &points {
+            update_weapon(output, module_combinations, *weapon, point, grid, &stored_hashes, run, progress).await?;
+        }
+    }
+    Ok(())
+}
 
-    // Weapon DPS simulations
-    for weapon in Weapon::all() {
-        let base_stats = WeaponBaseStats::get(*weapon);
-        let available_rolls = get_available_rolls(base_stats.weapon_type);
-        
-        // Determine which module combinations to use
-        let module_key = match (base_stats.bullet_type, base_stats.weapon_type) {
-            (_, WeaponType::SniperRifle) => "SniperRifle",
-            (_, WeaponType::Shotgun) => "Shotgun",
-            (_, WeaponType::Launcher) => "Launcher",
-            (BulletType::ImpactRounds, _) => "ImpactRounds",
-            (BulletType::GeneralRounds, _) => "GeneralRounds",
-            (BulletType::SpecialRounds, _) => "SpecialRounds",
-            _ => panic!("Unexpected combination of bullet type and weapon type"),
+/// Computes and stores every weak point hit chance of the grid for one
+/// weapon and grid point, checkpointing `run` after each result.
+#[allow(clippy::too_many_arguments)]
+async fn update_weapon(
+    output: &Output,
+    module_combinations: &HashMap<String, ModuleCombinations>,
+    weapon: Weapon,
+    point: &GridConfig,
+    grid: &ComputationGrid,
+    stored_hashes: &HashMap<ResultKey, String>,
+    run: &mut RunCheckpoint,
+    progress: &RunProgress,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base_stats = WeaponBaseStats::get(weapon);
+    let available_rolls = get_available_rolls(base_stats.weapon_type);
+    
+    let module_key = base_stats.module_key();
+
+    let available_modules = get_available_modules(base_stats.bullet_type, base_stats.weapon_type);
+    let config = &grid.optimization_config(point);
+
+    let mut pending = Vec::new();
+    for &weak_point_hit_chance in &grid.weak_point_hit_chances {
+        let unit = WorkUnit {
+            weapon,
+            valby: config.valby,
+            enzo: config.enzo,
+            objective: point.objective,
+            enemy: point.enemy,
+            weak_point_hit_chance,
         };
-        
-        let module_combinations = results.module_combinations.get(module_key)
-            .expect("Module combinations not found")
-            .combinations.clone();
+        if run.is_finished(&unit) {
+            progress.finish_unit(&unit, &UnitOutcome::Finished, None);
+            continue;
+        }
+        let key = unit.key();
+        let hash = input_hash(&base_stats, config.slot_counts(weapon), &available_rolls, &available_modules, config, weak_point_hit_chance);
+        if stored_hashes.get(&key) == Some(&hash) {
+            progress.finish_unit(&unit, &UnitOutcome::Skipped, None);
+            run.record(&unit, UnitOutcome::Skipped);
+        } else {
+            pending.push((unit, hash));
+        }
+    }
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let slots = config.slot_counts(weapon);
+    let weapon_module_combinations = combinations_for(
+        module_combinations.get(module_key).expect("Module combinations not found"),
+        &available_modules,
+        &config.constraints,
+        slots.modules,
+    );
+
+    for (unit, hash) in pending {
+        progress.start_unit(&unit);
+        let result = optimize_weapon_with_progress(
+            base_stats,
+            slots,
+            available_rolls.clone(),
+            available_modules.clone(),
+            weapon_module_combinations.clone(),
+            unit.weak_point_hit_chance,
+            config.clone(),
+            progress,
+        ).await;
+        let max_dps = result.max_dps;
 
+        let record = result_record(&unit, hash, result);
+        let outcome = match output.store_result(&record).await {
+            Ok(()) => UnitOutcome::Finished,
+            Err(e) => UnitOutcome::Failed(e.to_string()),
+        };
+        progress.finish_unit(&unit, &outcome, Some(max_dps));
+        run.record(&unit, outcome);
+        output.save_checkpoint(run).await?;
+    }
+
+    Ok(())
+}
+
+async fn start_run(output: &Output, resume: Option<String>, command: String) -> Result<RunCheckpoint, Box<dyn std::error::Error>> {
+    Ok(match resume {
+        Some(id) => RunCheckpoint::resume(output.load_checkpoint(&id).await?, id, command),
+        None => RunCheckpoint::new(None, command),
+    })
+}
+
+/// Marks `run` completed and prints its JSON summary, also writing it to
+/// `summary_path` if given. Failed units make the run an error so it can be
+/// resumed.
+async fn finish_run(output: &Output, run: &mut RunCheckpoint, progress: &RunProgress, summary_path: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    run.completed = run.failed.is_empty();
+    output.save_checkpoint(run).await?;
+    info!("{}", run.summary());
+    let summary = serde_json::to_string_pretty(&progress.summary(run))?;
+    println!("{}", summary);
+    if let Some(path) = summary_path {
+        std::fs::write(path, &summary)?;
+    }
+    if !run.failed.is_empty() {
+        return Err(format!("{} units failed, resume with --resume {}", run.failed.len(), run.id).into());
+    }
+    Ok(())
+}
+
+async fn sweep_weak_point(output: &Output, module_combinations: &HashMap<String, ModuleCombinations>, names: Vec<String>, step: f64, buffs: &ComputationGrid) -> Result<(), Box<dyn std::error::Error>> {
+    let weapons: Vec<Weapon> = if names.is_empty() {
+        Weapon::all().to_vec()
+    } else {
+        names.iter().filter_map(|name| Weapon::from_str(name).ok()).collect()
+    };
+    let grid = sweep_grid(step);
+
+    for weapon in weapons {
+        let base_stats = WeaponBaseStats::get(weapon);
+        let available_rolls = get_available_rolls(base_stats.weapon_type);
         let available_modules = get_available_modules(base_stats.bullet_type, base_stats.weapon_type);
-        
-        for config_type in [(true, false), (false, true), (false, false)] {
-            let (valby, enzo) = config_type;
-            let config = OptimizationConfig { valby, enzo, ..Default::default() };
-            
-            for &weak_point_hit_chance in &[0.25, 0.5, 1.0] {
-                let result = optimize_weapon(
-                    base_stats,
-                    available_rolls.clone(),
-                    available_modules.clone(),
-                    module_combinations.clone(),
-                    weak_point_hit_chance,
-                    config.clone(),
-                ).await;
-        
-                let key = OptimizationKey {
-                    weapon: weapon.to_string(),
-                    weak_point_hit_chance,
-                    valby,
-                    enzo,
-                };
-                results.weapon_results.insert(key, result);
-            }
+
+        for config in buffs.configs() {
+            let (valby, enzo) = (config.valby, config.enzo);
+            let slots = config.slot_counts(weapon);
+            let weapon_module_combinations = combinations_for(
+                module_combinations.get(base_stats.module_key()).expect("Module combinations not found"),
+                &available_modules,
+                &config.constraints,
+                slots.modules,
+            );
+
+            let results = optimize_weapon_sweep(
+                base_stats,
+                slots,
+                available_rolls.clone(),
+                available_modules.clone(),
+                weapon_module_combinations,
+                &grid,
+                config,
+            );
+            let points = grid.iter()
+                .zip(results)
+                .map(|(&weak_point_hit_chance, result)| SweepPoint { weak_point_hit_chance, result })
+                .collect();
+            let sweep = WeaponSweep::new(weapon.to_string(), valby, enzo, points);
+            info!("{} (valby: {}, enzo: {}): {} build crossovers", weapon, valby, enzo, sweep.build_crossovers.len());
+
+            output.store_sweep(&SweepRecord { data_version: game_data_version().to_string(), sweep }).await?;
         }
     }
 
-    store_results_in_db(db, results).await?;
-
     Ok(())
 }
 
-async fn update_weapon(db: &mongodb::Database, module_combinations: &HashMap<String, ModuleCombinations>, weapon: Weapon, config: &OptimizationConfig) -> Result<(), Box<dyn std::error::Error>> {
+#[allow(clippy::too_many_arguments)]
+async fn optimize_robust(
+    module_combinations: &HashMap<String, ModuleCombinations>,
+    grid: &ComputationGrid,
+    name: String,
+    valby: bool,
+    enzo: bool,
+    beta: Option<String>,
+    histogram: Option<std::path::PathBuf>,
+    percentile: Option<f64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let weapon = Weapon::from_str(&name).map_err(|_| format!("Unknown weapon {}", name))?;
+    let distribution = match (beta, histogram) {
+        (_, Some(path)) => {
+            let bins: Vec<(f64, f64)> = serde_json::from_reader(File::open(path)?)?;
+            WeakPointDistribution::Histogram { bins }
+        },
+        (Some(beta), None) => {
+            let params: Vec<f64> = beta.split(',').map(|p| p.trim().parse()).collect::<Result<_, _>>()?;
+            match params[..] {
+                [alpha, beta] if alpha > 0.0 && beta > 0.0 => WeakPointDistribution::Beta { alpha, beta },
+                _ => return Err("--beta expects two positive numbers, e.g. 2,5".into()),
+            }
+        },
+        (None, None) => return Err("Either --beta or --histogram is required".into()),
+    };
+    let objective = percentile.map_or(RobustObjective::Expected, RobustObjective::Percentile);
+
     let base_stats = WeaponBaseStats::get(weapon);
-    let available_rolls = get_available_rolls(base_stats.weapon_type);
-    
-    let module_key = match (base_stats.bullet_type, base_stats.weapon_type) {
-        (_, WeaponType::SniperRifle) => "SniperRifle",
-        (_, WeaponType::Shotgun) => "Shotgun",
-        (_, WeaponType::Launcher) => "Launcher",
-        (BulletType::ImpactRounds, _) => "ImpactRounds",
-        (BulletType::GeneralRounds, _) => "GeneralRounds",
-        (BulletType::SpecialRounds, _) => "SpecialRounds",
-        _ => panic!("Unexpected combination of bullet type and weapon type"),
+    let available_modules = get_available_modules(base_stats.bullet_type, base_stats.weapon_type);
+    let config = grid.optimization_config(&grid.single_point(BuffSet { valby, enzo })?);
+    let slots = config.slot_counts(weapon);
+    let weapon_module_combinations = combinations_for(
+        module_combinations.get(base_stats.module_key()).expect("Module combinations not found"),
+        &available_modules,
+        &config.constraints,
+        slots.modules,
+    );
+
+    let result = optimize_weapon_robust(
+        base_stats,
+        slots,
+        get_available_rolls(base_stats.weapon_type),
+        available_modules,
+        weapon_module_combinations,
+        &distribution,
+        objective,
+        config,
+    ).await;
+    println!("{}", serde_json::to_string_pretty(&result)?);
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct LevelComparison {
+    progress: WeaponProgress,
+    current: OptimizationResult,
+    max_level: OptimizationResult,
+    dps_gain: f64,
+    dps_gain_percent: f64,
+}
+
+async fn optimize_at_level(
+    module_combinations: &HashMap<String, ModuleCombinations>,
+    weapon: Weapon,
+    progress: WeaponProgress,
+    weak_point_hit_chance: f64,
+    config: OptimizationConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut results = Vec::new();
+    for progress in [progress, WeaponProgress::max_level(weapon)] {
+        // The level narrows the slots further than the grid's slot overrides
+        let config = OptimizationConfig { progress: Some(progress), ..config.clone() };
+        let base_stats = config.base_stats(weapon);
+        let available_modules = get_available_modules(base_stats.bullet_type, base_stats.weapon_type);
+        let slots = config.slot_counts(weapon);
+        let weapon_module_combinations = combinations_for(
+            module_combinations.get(base_stats.module_key()).expect("Module combinations not found"),
+            &available_modules,
+            &config.constraints,
+            slots.modules,
+        );
+        results.push(optimize_weapon(
+            base_stats,
+            slots,
+            get_available_rolls(base_stats.weapon_type),
+            available_modules,
+            weapon_module_combinations,
+            weak_point_hit_chance,
+            config,
+        ).await);
+    }
+
+    let max_level = results.pop().unwrap();
+    let current = results.pop().unwrap();
+    let dps_gain = max_level.max_dps - current.max_dps;
+    let comparison = LevelComparison {
+        progress,
+        dps_gain,
+        dps_gain_percent: if current.max_dps > 0.0 { dps_gain / current.max_dps * 100.0 } else { 0.0 },
+        current,
+        max_level,
     };
+    println!("{}", serde_json::to_string_pretty(&comparison)?);
 
-    let weapon_module_combinations = module_combinations.get(module_key)
-        .expect("Module combinations not found")
-        .combinations.clone();
+    Ok(())
+}
 
-    let available_modules = get_available_modules(base_stats.bullet_type, base_stats.weapon_type);
+async fn results_for_version(
+    data: &GameData,
+    weapons: &[Weapon],
+    config: &OptimizationConfig,
+    weak_point_hit_chance: f64,
+    combinations_by_key: &mut HashMap<(&'static str, usize), Vec<Vec<usize>>>,
+) -> HashMap<Weapon, OptimizationResult> {
+    let mut results = HashMap::new();
 
-    let collection = db.collection::<OptimizationResult>("weapon_results");
+    for &weapon in weapons {
+        let Some(base_stats) = data.base_stats(weapon) else {
+            continue;
+        };
+        let slots = config.slots_within(base_stats.slots);
+        let available_modules = data.modules(base_stats.module_key());
+        let module_combinations = combinations_by_key
+            .entry((base_stats.module_key(), slots.modules))
+            .or_insert_with(|| generate_module_combinations(&available_modules, &config.constraints, slots.modules))
+            .clone();
 
-    for weak_point_hit_chance in [0.25, 0.5, 1.0].iter() {
         let result = optimize_weapon(
             base_stats,
-            available_rolls.clone(),
-            available_modules.clone(),
-            weapon_module_combinations.clone(),
-            *weak_point_hit_chance,
+            slots,
+            data.rolls(base_stats.weapon_type),
+            available_modules,
+            module_combinations,
+            weak_point_hit_chance,
             config.clone(),
         ).await;
+        results.insert(weapon, result);
+    }
 
-        collection.update_one(
-            doc! {
-                "weapon": weapon.to_string(),
-                "weak_point_hit_chance": weak_point_hit_chance,
-                "valby": config.valby,
-                "enzo": config.enzo,
-            },
-            doc! { "$set": mongodb::bson::to_bson(&result).unwrap() },
-            mongodb::options::UpdateOptions::builder().upsert(true).build(),
-        ).await?;
+    results
+}
+
+#[derive(Serialize)]
+struct VersionDiff {
+    from: String,
+    to: String,
+    data_changes: Vec<String>,
+    points: Vec<PointDiff>,
+}
+
+/// Same shape as the entries of the frontend changelog.
+#[derive(Serialize)]
+struct ChangelogEntry {
+    version: String,
+    date: String,
+    changes: Vec<String>,
+}
+
+fn point_label(point: &PointDiff) -> String {
+    let buffs = BuffSet { valby: point.valby, enzo: point.enzo };
+    format!("{}, {}, {}, wp {:.2}", buffs, point.objective, point.enemy, point.weak_point_hit_chance)
+}
+
+/// Reruns `names` (all weapons if empty) with the game data of both
+/// versions at every grid point.
+async fn diff_versions(from: String, to: String, names: Vec<String>, grid: &ComputationGrid, label: Option<String>, changelog: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let from_data = GameData::load(GAME_DATA_DIR, &from)?;
+    let to_data = GameData::load(GAME_DATA_DIR, &to)?;
+    let weapons: Vec<Weapon> = if names.is_empty() {
+        Weapon::all().to_vec()
+    } else {
+        names.iter().filter_map(|name| Weapon::from_str(name).ok()).collect()
+    };
+
+    // Constraints are the same at every point, so combinations only depend on the module set and slots
+    let (mut before_combinations, mut after_combinations) = (HashMap::new(), HashMap::new());
+    let mut points = Vec::new();
+    for point in grid.points() {
+        let config = grid.optimization_config(&point);
+        for &weak_point_hit_chance in &grid.weak_point_hit_chances {
+            let before = results_for_version(&from_data, &weapons, &config, weak_point_hit_chance, &mut before_combinations).await;
+            let after = results_for_version(&to_data, &weapons, &config, weak_point_hit_chance, &mut after_combinations).await;
+            points.push(PointDiff {
+                valby: point.buffs.valby,
+                enzo: point.buffs.enzo,
+                objective: point.objective,
+                enemy: point.enemy,
+                weak_point_hit_chance,
+                weapons: compare_results(&before, &after),
+            });
+        }
     }
+    let diff = VersionDiff { data_changes: data_changes(&from_data, &to_data), points, from, to };
+
+    if !changelog {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
+    let mut changes = diff.data_changes;
+    for point in &diff.points {
+        let label = point_label(point);
+        for delta in &point.weapons {
+            if delta.dps_delta.abs() >= 1.0 {
+                changes.push(format!(
+                    "{} max DPS {:.0} -> {:.0} ({:+.1}%) at {}",
+                    delta.weapon,
+                    delta.before_dps.unwrap_or(0.0),
+                    delta.after_dps.unwrap_or(0.0),
+                    delta.dps_delta_percent,
+                    label,
+                ));
+            }
+            if delta.tier_changed() {
+                if let (Some(before), Some(after)) = (delta.before_tier, delta.after_tier) {
+                    changes.push(format!("{} tier {} -> {} at {}", delta.weapon, before, after, label));
+                }
+            }
+            if delta.build_changed() {
+                changes.push(format!(
+                    "{} new optimal build at {}: +[{}] -[{}]",
+                    delta.weapon, label, delta.modules_added.join(", "), delta.modules_removed.join(", "),
+                ));
+            }
+        }
+    }
+    let date = mongodb::bson::DateTime::now().try_to_rfc3339_string()?;
+    let entry = ChangelogEntry { version: label.unwrap_or(diff.to), date: date[..10].to_string(), changes };
+    println!("{}", serde_json::to_string_pretty(&entry)?);
 
     Ok(())
 }
 
-fn save_module_combinations(combinations: &HashMap<String, ModuleCombinations>, dir: &str) -> Result<(), Box<dyn std::error::Error>> {
-    create_dir_all(dir)?;
-    for (key, combination) in combinations {
-        let path = Path::new(dir).join(format!("{}.bin", key));
-        let encoded: Vec<u8> = bincode::serialize(&combination)?;
-        let mut file = File::create(path)?;
-        file.write_all(&encoded)?;
+/// Runs are checkpointed under the job id, so a retried job picks up where
+/// the previous attempt stopped.
+async fn run_job(output: &Output, module_combinations: &HashMap<String, ModuleCombinations>, job: &Job) -> Result<(), Box<dyn std::error::Error>> {
+    job.grid.validate_storable()?;
+    let mut run = start_run(output, Some(job.id.clone()), format!("{:?}", job.kind)).await?;
+    let progress = &RunProgress::hidden();
+    match &job.kind {
+        JobKind::UpdateWeapons { names } => update_weapons(output, module_combinations, names.clone(), &job.grid, job.force, &mut run, progress).await?,
+        JobKind::UpdateWeaponType { weapon_type } => update_weapon_type(output, module_combinations, weapon_type.clone(), &job.grid, job.force, &mut run, progress).await?,
+        JobKind::UpdateBulletType { bullet_type } => update_bullet_type(output, module_combinations, bullet_type.clone(), &job.grid, job.force, &mut run, progress).await?,
+        JobKind::UpdateAll => update_all(output, module_combinations, &job.grid, job.force, &mut run, progress).await?,
+        JobKind::SweepWeakPoint { names, step } => return sweep_weak_point(output, module_combinations, names.clone(), *step, &job.grid).await,
+    }
+    finish_run(output, &mut run, progress, None).await?;
+
+    // The results are stored at this point, a stale api cache shouldn't
+    // fail the job and have it recomputed
+    let targets = match &job.kind {
+        JobKind::UpdateWeapons { names } => names.clone(),
+        JobKind::UpdateWeaponType { weapon_type } => vec![weapon_type.clone()],
+        JobKind::UpdateBulletType { bullet_type } => vec![bullet_type.clone()],
+        JobKind::UpdateAll => vec!["all".to_string()],
+        JobKind::SweepWeakPoint { .. } => Vec::new(),
+    };
+    for target in targets {
+        if let Err(e) = clear_api_cache(output, &target).await {
+            warn!("Failed to clear the api cache for {}: {}", target, e);
+        }
     }
     Ok(())
 }
 
-fn load_module_combinations(dir: &str) -> Result<HashMap<String, ModuleCombinations>, Box<dyn std::error::Error>> {
-    let mut combinations = HashMap::new();
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("bin") {
-            let mut file = File::open(&path)?;
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer)?;
-            let combination: ModuleCombinations = bincode::deserialize(&buffer)?;
-            let key = path.file_stem().and_then(|s| s.to_str()).unwrap().to_string();
-            combinations.insert(key, combination);
+/// Takes jobs from the queue in `db` and writes their results to `output`.
+async fn run_daemon(
+    db: &mongodb::Database,
+    output: &Output,
+    module_combinations: &HashMap<String, ModuleCombinations>,
+    worker_id: String,
+    poll_interval: u64,
+    lease_secs: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let lease_millis = (lease_secs * 1000) as i64;
+    info!("Worker {} polling {}", worker_id, jobs::JOBS_COLLECTION);
+
+    loop {
+        let abandoned = jobs::fail_abandoned(db).await?;
+        if abandoned > 0 {
+            warn!("Marked {} abandoned jobs as failed", abandoned);
+        }
+
+        let Some(job) = jobs::claim_next(db, &worker_id, lease_millis).await? else {
+            tokio::time::sleep(Duration::from_secs(poll_interval)).await;
+            continue;
+        };
+        info!("Running job {} ({:?}), attempt {}/{}", job.id, job.kind, job.attempts, job.max_attempts);
+
+        let heartbeat = {
+            let (db, id, worker_id) = (db.clone(), job.id.clone(), worker_id.clone());
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_millis((lease_millis / 3).max(1) as u64));
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    match jobs::heartbeat(&db, &id, &worker_id, lease_millis).await {
+                        Ok(true) => {},
+                        Ok(false) => {
+                            warn!("Lost the lease on job {}", id);
+                            break;
+                        },
+                        Err(e) => warn!("Heartbeat for job {} failed: {}", id, e),
+                    }
+                }
+            })
+        };
+
+        let outcome = run_job(output, module_combinations, &job).await.map_err(|e| e.to_string());
+        heartbeat.abort();
+
+        match &outcome {
+            Ok(()) => info!("Job {} succeeded", job.id),
+            Err(e) => warn!("Job {} failed: {}", job.id, e),
         }
+        jobs::finish(db, &job, &worker_id, outcome).await?;
+    }
+}
+
+/// The named weapons, or every weapon of a weapon or bullet type.
+fn select_weapons(names: Vec<String>, weapon_type: Option<String>, bullet_type: Option<String>) -> Result<Vec<Weapon>, Box<dyn std::error::Error>> {
+    let weapons: Vec<Weapon> = match (weapon_type, bullet_type) {
+        (Some(weapon_type), _) => {
+            let wt = WeaponType::from_str(&weapon_type).map_err(|_| format!("Unknown weapon type {}", weapon_type))?;
+            Weapon::all().iter().copied().filter(|w| WeaponBaseStats::get(*w).weapon_type == wt).collect()
+        },
+        (None, Some(bullet_type)) => {
+            let bt = BulletType::from_str(&bullet_type).map_err(|_| format!("Unknown bullet type {}", bullet_type))?;
+            Weapon::all().iter().copied().filter(|w| WeaponBaseStats::get(*w).bullet_type == bt).collect()
+        },
+        (None, None) => names.iter().map(|name| Weapon::from_str(name).map_err(|_| format!("Unknown weapon {}", name))).collect::<Result<_, _>>()?,
+    };
+    if weapons.is_empty() {
+        return Err("No weapons selected".into());
     }
-    Ok(combinations)
+    Ok(weapons)
 }
 
-async fn clear_api_cache(target: &str) -> Result<(), Box<dyn std::error::Error>> {
+#[derive(Serialize)]
+struct PointDiff {
+    valby: bool,
+    enzo: bool,
+    objective: Objective,
+    enemy: EnemyProfile,
+    weak_point_hit_chance: f64,
+    weapons: Vec<WeaponDelta>,
+}
+
+#[derive(Serialize)]
+struct StoredDiff {
+    data_version: String,
+    thresholds: DiffThresholds,
+    points: Vec<PointDiff>,
+    over_threshold: Vec<String>,
+}
+
+/// Recomputes `weapons` over the grid and prints how the results differ
+/// from the stored ones of the current data version. Nothing is written.
+/// Errors if a change goes over `thresholds`, to gate formula changes.
+async fn diff_stored(
+    output: &Output,
+    module_combinations: &HashMap<String, ModuleCombinations>,
+    weapons: &[Weapon],
+    grid: &ComputationGrid,
+    thresholds: &DiffThresholds,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stored: HashMap<ResultKey, OptimizationResult> = output
+        .load_results()
+        .await?
+        .into_iter()
+        .filter(|record| record.data_version == game_data_version())
+        .filter_map(|record| Some((record.key()?, record.result)))
+        .collect();
+    let progress = &RunProgress::new();
+    for weapon in weapons {
+        progress.queue(&weapon.to_string(), (grid.points().len() * grid.weak_point_hit_chances.len()) as u64);
+    }
+
+    let mut points = Vec::new();
+    let mut over_threshold = Vec::new();
+    for point in grid.points() {
+        for &weak_point_hit_chance in &grid.weak_point_hit_chances {
+            let mut fresh = HashMap::new();
+            for weapon in weapons {
+                let unit = WorkUnit {
+                    weapon: *weapon,
+                    valby: point.buffs.valby,
+                    enzo: point.buffs.enzo,
+                    objective: point.objective,
+                    enemy: point.enemy,
+                    weak_point_hit_chance,
+                };
+                let result = optimize_unit(module_combinations, &unit, grid, progress).await?;
+                progress.finish_unit(&unit, &UnitOutcome::Finished, Some(result.max_dps));
+                fresh.insert(*weapon, result);
+            }
+
+            let before: HashMap<Weapon, OptimizationResult> = Weapon::all()
+                .iter()
+                .filter_map(|weapon| {
+                    let key = ResultKey::new(*weapon, weak_point_hit_chance, point.buffs, point.objective, point.enemy);
+                    stored.get(&key).map(|result| (*weapon, result.clone()))
+                })
+                .collect();
+            let deltas = compare_to_stored(&before, &fresh);
+            for delta in &deltas {
+                if let Some(reason) = thresholds.exceeded_by(delta) {
+                    over_threshold.push(format!(
+                        "{} ({}, {}, {}, wp {:.2}): {}",
+                        delta.weapon, point.buffs, point.objective, point.enemy, weak_point_hit_chance, reason,
+                    ));
+                }
+            }
+            points.push(PointDiff {
+                valby: point.buffs.valby,
+                enzo: point.buffs.enzo,
+                objective: point.objective,
+                enemy: point.enemy,
+                weak_point_hit_chance,
+                weapons: deltas,
+            });
+        }
+    }
+    progress.finish();
+
+    let count = over_threshold.len();
+    let diff = StoredDiff { data_version: game_data_version().to_string(), thresholds: thresholds.clone(), points, over_threshold };
+    println!("{}", serde_json::to_string_pretty(&diff)?);
+    if count > 0 {
+        return Err(format!("{} changes over the threshold", count).into());
+    }
+    Ok(())
+}
+
+fn default_worker_id() -> String {
+    let host = env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_string());
+    format!("{}-{}", host, std::process::id())
+}
+
+/// Queues every unit of `names` (all weapons if empty) over the grid under
+/// `run_id`. With `wait`, blocks until agents have finished them.
+async fn coordinate(
+    output: &Output,
+    names: Vec<String>,
+    grid: &ComputationGrid,
+    run_id: Option<String>,
+    wait: bool,
+    poll_interval: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let weapons: Vec<Weapon> = if names.is_empty() {
+        Weapon::all().to_vec()
+    } else {
+        names.iter().map(|name| Weapon::from_str(name).map_err(|_| format!("Unknown weapon {}", name))).collect::<Result<_, _>>()?
+    };
+    let mut work = Vec::new();
+    for weapon in &weapons {
+        for point in grid.points() {
+            for &weak_point_hit_chance in &grid.weak_point_hit_chances {
+                work.push(WorkUnit {
+                    weapon: *weapon,
+                    valby: point.buffs.valby,
+                    enzo: point.buffs.enzo,
+                    objective: point.objective,
+                    enemy: point.enemy,
+                    weak_point_hit_chance,
+                });
+            }
+        }
+    }
+
+    let run_id = run_id.unwrap_or_else(|| mongodb::bson::oid::ObjectId::new().to_hex());
+    let added = shard::enqueue(output, &run_id, &work).await?;
+    info!("Queued {} of {} units for run {}, start agents with: worker agent {}", added, work.len(), run_id, run_id);
+    println!("{}", run_id);
+    if !wait {
+        return Ok(());
+    }
+
+    loop {
+        shard::fail_abandoned(output, &run_id, DEFAULT_UNIT_ATTEMPTS).await?;
+        let counts = shard::counts(output, &run_id).await?;
+        info!("Run {}: {} queued, {} leased, {} done, {} failed", run_id, counts.queued, counts.leased, counts.done, counts.failed);
+        if counts.remaining() == 0 {
+            println!("{}", serde_json::to_string_pretty(&counts)?);
+            if counts.failed > 0 {
+                return Err(format!("{} units of run {} failed", counts.failed, run_id).into());
+            }
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(poll_interval)).await;
+    }
+}
+
+/// Leases units of `run_id` one at a time until the run has none left.
+/// Results are upserts by key, so a unit computed twice after a lost lease
+/// just overwrites itself.
+#[allow(clippy::too_many_arguments)]
+async fn run_agent(
+    output: &Output,
+    module_combinations: &HashMap<String, ModuleCombinations>,
+    run_id: &str,
+    worker_id: &str,
+    grid: &ComputationGrid,
+    force: bool,
+    lease_secs: u64,
+    poll_interval: u64,
+    summary_path: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let lease_millis = (lease_secs * 1000) as i64;
+    let mut run = RunCheckpoint::new(Some(format!("{}-{}", run_id, worker_id)), format!("Agent {}", run_id));
+    let progress = &RunProgress::new();
+    info!("Agent {} working on run {}", worker_id, run_id);
+    // Units are leased to one agent at a time, so hashes stored during the run can't affect this agent's units
+    let stored_hashes = if force { HashMap::new() } else { stored_input_hashes(output, None).await? };
+
+    loop {
+        shard::fail_abandoned(output, run_id, DEFAULT_UNIT_ATTEMPTS).await?;
+        let Some(shard_unit) = shard::claim(output, run_id, worker_id, lease_millis, DEFAULT_UNIT_ATTEMPTS).await? else {
+            if shard::counts(output, run_id).await?.leased == 0 {
+                break;
+            }
+            // Other agents hold the rest, wait in case their leases expire
+            tokio::time::sleep(Duration::from_secs(poll_interval)).await;
+            continue;
+        };
+
+        let unit = &shard_unit.unit;
+        progress.queue(&unit.weapon.to_string(), 1);
+        let outcome = shard::with_heartbeat(
+            output,
+            &shard_unit,
+            worker_id,
+            lease_millis,
+            compute_unit(output, module_combinations, unit, grid, &stored_hashes, progress),
+        ).await;
+        let recorded = match &outcome {
+            Ok(outcome) => outcome.clone(),
+            Err(e) => UnitOutcome::Failed(e.to_string()),
+        };
+        progress.finish_unit(unit, &recorded, None);
+        run.record(unit, recorded);
+        shard::complete(output, &shard_unit, worker_id, outcome.map(|_| ()).map_err(|e| e.to_string()), DEFAULT_UNIT_ATTEMPTS).await?;
+    }
+
+    run.completed = true;
+    let summary = serde_json::to_string_pretty(&progress.summary(&run))?;
+    println!("{}", summary);
+    if let Some(path) = summary_path {
+        std::fs::write(path, &summary)?;
+    }
+    Ok(())
+}
+
+/// Computes and stores one unit, unless its hash in `stored_hashes` shows
+/// the stored result is already up to date.
+async fn compute_unit(
+    output: &Output,
+    module_combinations: &HashMap<String, ModuleCombinations>,
+    unit: &WorkUnit,
+    grid: &ComputationGrid,
+    stored_hashes: &HashMap<ResultKey, String>,
+    progress: &RunProgress,
+) -> Result<UnitOutcome, Box<dyn std::error::Error>> {
+    let weapon = unit.weapon;
+    let base_stats = WeaponBaseStats::get(weapon);
+    let config = grid.optimization_config(&unit.grid_config());
+    let hash = input_hash(
+        &base_stats,
+        config.slot_counts(weapon),
+        &get_available_rolls(base_stats.weapon_type),
+        &get_available_modules(base_stats.bullet_type, base_stats.weapon_type),
+        &config,
+        unit.weak_point_hit_chance,
+    );
+
+    if stored_hashes.get(&unit.key()) == Some(&hash) {
+        return Ok(UnitOutcome::Skipped);
+    }
+
+    let result = optimize_unit(module_combinations, unit, grid, progress).await?;
+    output.store_result(&result_record(unit, hash, result)).await?;
+    Ok(UnitOutcome::Finished)
+}
+
+/// Optimizes one unit the same way `update_weapon` does, without storing it.
+async fn optimize_unit(
+    module_combinations: &HashMap<String, ModuleCombinations>,
+    unit: &WorkUnit,
+    grid: &ComputationGrid,
+    progress: &RunProgress,
+) -> Result<OptimizationResult, Box<dyn std::error::Error>> {
+    let weapon = unit.weapon;
+    let base_stats = WeaponBaseStats::get(weapon);
+    let available_rolls = get_available_rolls(base_stats.weapon_type);
+    let available_modules = get_available_modules(base_stats.bullet_type, base_stats.weapon_type);
+    let config = grid.optimization_config(&unit.grid_config());
+    let slots = config.slot_counts(weapon);
+    let combinations = combinations_for(
+        module_combinations.get(base_stats.module_key()).ok_or("Module combinations not found")?,
+        &available_modules,
+        &config.constraints,
+        slots.modules,
+    );
+    progress.start_unit(unit);
+    Ok(optimize_weapon_with_progress(
+        base_stats,
+        slots,
+        available_rolls,
+        available_modules,
+        combinations,
+        unit.weak_point_hit_chance,
+        config,
+        progress,
+    ).await)
+}
+
+fn result_record(unit: &WorkUnit, input_hash: String, result: OptimizationResult) -> ResultRecord {
+    ResultRecord {
+        weapon: unit.weapon.to_string(),
+        weak_point_hit_chance: unit.weak_point_hit_chance,
+        valby: unit.valby,
+        enzo: unit.enzo,
+        objective: unit.objective,
+        enemy: unit.enemy,
+        data_version: game_data_version().to_string(),
+        input_hash,
+        result,
+    }
+}
+
+/// Has the api at `API_URL` reload the results of `target`, a weapon, weapon
+/// type or bullet type, or `all`. The api only reads MongoDB, so other
+/// outputs leave its cache alone.
+async fn clear_api_cache(output: &Output, target: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !matches!(output, Output::Mongo(_)) {
+        return Ok(());
+    }
+    let Ok(api_url) = env::var("API_URL") else {
+        warn!("API_URL is not set, not clearing the api cache for {}", target);
+        return Ok(());
+    };
+    send_clear_cache(&api_url, &AdminClient::from_env(), target).await
+}
+
+async fn send_clear_cache(api_url: &str, admin: &AdminClient, target: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("Clearing api cache...");
+    let mut url = reqwest::Url::parse(api_url)?.join("/clear-cache-and-fetch")?;
+    url.query_pairs_mut().append_pair("target", target);
+    let path_and_query = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
     let client = reqwest::Client::new();
-    let response = client.post("https://weapon-optimizer-api.onrender.com/clear-cache-and-fetch")
-        .query(&[("target", target)])
-        .send()
-        .await?;
+    let mut request = client.post(url);
+    for (name, value) in admin.headers("POST", &path_and_query, b"")? {
+        request = request.header(name, value);
+    }
+    let response = request.send().await?;
 
     if !response.status().is_success() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
+        return Err(Box::new(std::io::Error::other(
             format!("Failed to clear cache: {}", response.status())
         )));
     }
@@ -351,39 +1314,169 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opts = Cli::from_args();
     info!("Received command: {:?}", opts.cmd);
 
-    let client = create_mongo_client().await?;
-    let db = client.database("weapon_optimizer");
-
     let module_combinations = match &opts.cmd {
         Command::UpdateAll => generate_all_module_combinations(None),
         Command::UpdateModules { module_type } => generate_all_module_combinations(Some(module_type)),
-        _ => load_module_combinations("module_combinations")?,
+        Command::ExportGameData | Command::ExportBuilds { .. } | Command::DiffVersions { .. } | Command::Import { .. } | Command::Coordinate { .. } => HashMap::new(),
+        _ => combination_cache::load_all(CACHE_DIR, !opts.no_regenerate)?,
     };
 
+    let grid = grid_from_cli(&opts)?;
+    let command = format!("{:?}", opts.cmd);
+    let writes_results = matches!(
+        opts.cmd,
+        Command::UpdateAll | Command::UpdateWeapons { .. } | Command::UpdateWeaponType { .. } | Command::UpdateBulletType { .. }
+            | Command::SweepWeakPoint { .. } | Command::Coordinate { .. } | Command::Agent { .. }
+    );
+    if writes_results {
+        grid.validate_storable()?;
+    }
+
     match opts.cmd {
         Command::UpdateAll => {
-            update_all(&db, &module_combinations).await?;
-            // clear_api_cache("all").await?;
+            let output = open_output(&opts.output).await?;
+            let mut run = start_run(&output, opts.resume, command).await?;
+            let progress = &RunProgress::new();
+            update_all(&output, &module_combinations, &grid, opts.force, &mut run, progress).await?;
+            finish_run(&output, &mut run, progress, opts.summary.as_deref()).await?;
+            clear_api_cache(&output, "all").await?;
         },
         Command::UpdateWeapons { names } => {
-            update_weapons(&db, &module_combinations, names.clone()).await?;
-            // for name in names {
-            //     clear_api_cache(&name).await?;
-            // }
+            let output = open_output(&opts.output).await?;
+            let mut run = start_run(&output, opts.resume, command).await?;
+            let progress = &RunProgress::new();
+            update_weapons(&output, &module_combinations, names.clone(), &grid, opts.force, &mut run, progress).await?;
+            finish_run(&output, &mut run, progress, opts.summary.as_deref()).await?;
+            for name in names {
+                clear_api_cache(&output, &name).await?;
+            }
         },
         Command::UpdateWeaponType { weapon_type } => {
-            update_weapon_type(&db, &module_combinations, weapon_type.clone()).await?;
-            // clear_api_cache(&weapon_type).await?;
+            let output = open_output(&opts.output).await?;
+            let mut run = start_run(&output, opts.resume, command).await?;
+            let progress = &RunProgress::new();
+            update_weapon_type(&output, &module_combinations, weapon_type.clone(), &grid, opts.force, &mut run, progress).await?;
+            finish_run(&output, &mut run, progress, opts.summary.as_deref()).await?;
+            clear_api_cache(&output, &weapon_type).await?;
         },
         Command::UpdateBulletType { bullet_type } => {
-            update_bullet_type(&db, &module_combinations, bullet_type.clone()).await?;
-            // clear_api_cache(&bullet_type).await?;
+            let output = open_output(&opts.output).await?;
+            let mut run = start_run(&output, opts.resume, command).await?;
+            let progress = &RunProgress::new();
+            update_bullet_type(&output, &module_combinations, bullet_type.clone(), &grid, opts.force, &mut run, progress).await?;
+            finish_run(&output, &mut run, progress, opts.summary.as_deref()).await?;
+            clear_api_cache(&output, &bullet_type).await?;
         },
         Command::UpdateModules { module_type } => {
-            update_modules(&module_combinations, module_type.clone()).await?;
-            // clear_api_cache(&module_type).await?;
+            // Only the combinations change, the api's results stay current
+            update_modules(&module_combinations, module_type).await?;
+        },
+        Command::SweepWeakPoint { names, step } => {
+            let output = open_output(&opts.output).await?;
+            sweep_weak_point(&output, &module_combinations, names, step, &grid).await?;
+        },
+        Command::OptimizeRobust { name, valby, enzo, beta, histogram, percentile } => {
+            optimize_robust(&module_combinations, &grid, name, valby, enzo, beta, histogram, percentile).await?;
+        },
+        Command::ExportBuilds { names, format } => {
+            let output = open_output(&opts.output).await?;
+            export_builds(&output, names, &grid, format).await?;
+        },
+        Command::ExportGameData => {
+            GameData::current().save(GAME_DATA_DIR)?;
+            info!("Exported game data {} to {}", game_data_version(), GAME_DATA_DIR);
+        },
+        Command::DiffVersions { from, to, names, label, changelog } => {
+            diff_versions(from, to, names, &grid, label, changelog).await?;
+        },
+        Command::OptimizeAtLevel { name, level, atk, modules, core_slots, weak_point_hit_chance, valby, enzo } => {
+            let weapon = Weapon::from_str(&name).map_err(|_| format!("Unknown weapon {}", name))?;
+            let progress = WeaponProgress { level, atk, module_slots: modules, core_slots };
+            progress.validate(weapon)?;
+            let config = grid.optimization_config(&grid.single_point(BuffSet { valby, enzo })?);
+            optimize_at_level(&module_combinations, weapon, progress, weak_point_hit_chance, config).await?;
+        },
+        Command::Daemon { worker_id, poll_interval, lease_secs } => {
+            let worker_id = worker_id.unwrap_or_else(default_worker_id);
+            let db = connect_db().await?;
+            let output = match &opts.output {
+                OutputTarget::Mongo => Output::Mongo(db.clone()),
+                target => open_output(target).await?,
+            };
+            run_daemon(&db, &output, &module_combinations, worker_id, poll_interval, lease_secs).await?;
+        },
+        Command::Import { source } => {
+            import_results(&source).await?;
+        },
+        Command::Diff { names, weapon_type, bullet_type, threshold, fail_on_tier_change, fail_on_build_change } => {
+            let output = open_output(&opts.output).await?;
+            let weapons = select_weapons(names, weapon_type, bullet_type)?;
+            let thresholds = DiffThresholds { max_dps_change_percent: threshold, tier: fail_on_tier_change, build: fail_on_build_change };
+            diff_stored(&output, &module_combinations, &weapons, &grid, &thresholds).await?;
+        },
+        Command::Coordinate { names, run_id, wait, poll_interval } => {
+            let output = open_output(&opts.output).await?;
+            coordinate(&output, names, &grid, run_id, wait, poll_interval).await?;
+        },
+        Command::Agent { run_id, worker_id, lease_secs, poll_interval } => {
+            let output = open_output(&opts.output).await?;
+            let worker_id = worker_id.unwrap_or_else(default_worker_id);
+            run_agent(&output, &module_combinations, &run_id, &worker_id, &grid, opts.force, lease_secs, poll_interval, opts.summary.as_deref()).await?;
         },
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin_auth::{AdminAuth, AdminCredentials, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+    use std::io::{BufRead, BufReader, Write};
+
+    /// Accepts one request, answers 200 and returns its request line and
+    /// lowercased headers.
+    fn serve_once(listener: std::net::TcpListener) -> std::thread::JoinHandle<(String, HashMap<String, String>)> {
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(':') else {
+                    break;
+                };
+                headers.insert(name.to_lowercase(), value.trim().to_string());
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").unwrap();
+            (request_line, headers)
+        })
+    }
+
+    #[tokio::test]
+    async fn test_clear_api_cache_is_signed_for_the_api() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = serve_once(listener);
+
+        let admin = AdminClient::new(None, Some("secret".to_string()));
+        send_clear_cache(&api_url, &admin, "Enduring Legacy").await.unwrap();
+        let (request_line, headers) = server.join().unwrap();
+
+        // Verified the way the api's admin middleware does, against what went over the wire
+        let mut parts = request_line.split_whitespace();
+        let (method, path_and_query) = (parts.next().unwrap(), parts.next().unwrap());
+        assert_eq!(path_and_query, "/clear-cache-and-fetch?target=Enduring+Legacy");
+        let credentials = AdminCredentials {
+            key: None,
+            timestamp: headers.get(TIMESTAMP_HEADER).cloned(),
+            nonce: headers.get(NONCE_HEADER).cloned(),
+            signature: headers.get(SIGNATURE_HEADER).cloned(),
+        };
+        let auth = AdminAuth::new(None, Some("secret".to_string()));
+        assert_eq!(auth.verify(&credentials, method, path_and_query, b"", jobs::now_millis() / 1000), Ok(()));
+    }
+}